@@ -27,6 +27,7 @@ options-fullscreen-off = OFF
 options-language-label = Script language
 options-language-rhai = Rhai
 options-language-keystone = Keystone
+options-language-blocks = Blocks
 options-button-back = BACK
 options-locale-label = Language
 options-locale-ja = 日本語
@@ -36,8 +37,47 @@ options-locale-zh = 中文
 stage-ui-back-to-title = Return to Stage Select
 stage-ui-menu-run = Run
 stage-ui-menu-stop = Stop
+stage-ui-menu-debug = Debug
+stage-ui-debug-rhai-only = Debugging needs Rhai for now: Keystone programs do not report which line is running.
+stage-ui-menu-step = Step
+stage-ui-menu-continue = Continue
 stage-ui-menu-font-decrease = -
 stage-ui-menu-font-increase = +
+stage-ui-stone-tab = Stone {$index}
+stage-ui-inspector-title = Inspector
+stage-ui-inspector-sensors = Sensors
+stage-ui-inspector-variables = Variables
+stage-ui-inspector-no-variables = No variables yet.
+stage-ui-inspector-variables-off = Tick "Inspect variables" before running to see them.
+stage-ui-inspect-variables = Inspect variables
+stage-ui-inspect-variables-hint = Show the script's variables in the inspector while it runs. Runs get a little slower.
+stage-ui-console-title = Console
+stage-ui-console-clear = Clear
+stage-ui-map-seed = Seed {$seed}
+stage-ui-map-seed-load = Load
+stage-ui-map-seed-invalid = "{$input}" is not a map seed. Enter a whole number.
+stage-ui-map-error-title = Stage could not be built
+stage-ui-map-error-body = This layout could not be generated. Try another seed or go back to the stage list.
+stage-ui-blocks-palette = Blocks
+stage-ui-blocks-program = Program
+stage-ui-blocks-empty = Drag blocks here to build a program.
+stage-ui-blocks-drop-here = Drop here
+stage-ui-blocks-trash = Drop a block here to remove it
+stage-ui-blocks-preview = Generated code
+stage-ui-blocks-seconds = s
+stage-ui-blocks-move = Move
+stage-ui-blocks-dig = Dig
+stage-ui-blocks-sleep = Wait
+stage-ui-blocks-loop = Repeat forever
+stage-ui-blocks-if-touched = If touched
+stage-ui-blocks-if-empty = If empty
+stage-ui-blocks-direction-up = up
+stage-ui-blocks-direction-down = down
+stage-ui-blocks-direction-left = left
+stage-ui-blocks-direction-right = right
+stage-ui-convert-script = Convert my {$from} solution to {$to}
+stage-ui-convert-script-done = Converted.
+stage-ui-convert-script-failed = Could not convert the {$from} script. Only move, dig, sleep, loop, if and variables can be converted.
 stage-ui-status-command-help-open = Command reference opened.
 stage-ui-status-command-help-close = Command reference hidden.
 stage-ui-feedback-stopped = Execution stopped.
@@ -49,6 +89,9 @@ stage-ui-feedback-goal = Stage clear!
 stage-ui-feedback-advance = Advancing to "{$stage}".
 stage-ui-feedback-start = "{$stage}" has started.
 stage-ui-feedback-complete = All stages cleared!
+stage-ui-seed = Seed
+stage-ui-seed-fixed = Keep
+stage-ui-seed-fixed-hint = Reuse this seed for the next run so random values repeat exactly.
 
 stage-ui-tutorial-controls-hint = F1 runs the script, F2 shrinks the font, F3 enlarges it; arrows move, Space jumps.
 stage-ui-tutorial-ok = Got it!
@@ -58,12 +101,34 @@ stage-ui-clear-window-title = Stage Clear!
 stage-ui-clear-heading = Goal reached.
 stage-ui-clear-body = Catch your breath before the next challenge.
 stage-ui-clear-ok = OK
+stage-ui-clear-metric-lines = Lines
+stage-ui-clear-metric-tokens = Tokens
+stage-ui-clear-metric-commands = Commands
+stage-ui-clear-metric-sleep = Sleep (s)
+stage-ui-clear-metric-digs = Digs
+stage-ui-clear-metric-par = par {$par}
 
 stage-ui-command-help-button = Command reference
 stage-ui-command-help-title = Command reference
 stage-ui-command-help-close = Close
 
+stage-ui-syntax-title = Shortcuts
+stage-ui-syntax-move = Directions can be written without quotes: `{$move-up-bare}`
+stage-ui-syntax-repeat = repeat runs a block a fixed number of times.
+    {$repeat-example}
+stage-ui-syntax-forever = forever repeats a block until the run stops, just like loop.
+    {$forever-example}
+stage-ui-syntax-while-touched = while_touched repeats a block while the player is touching the stone.
+    {$while-touched-example}
+
 stage-ui-error-invalid-move-direction = move() needs left/top/right/down but got "{$direction}".
 stage-ui-error-invalid-sleep-duration = sleep() duration must be zero or greater.
 stage-ui-error-engine = Script runtime error: {$message}
 stage-ui-error-unsupported-language = Script language "{$language}" is not supported.
+stage-ui-error-command-not-allowed = This stone cannot use {$command}.
+stage-ui-error-location-line = (line {$line})
+stage-ui-error-location-line-column = (line {$line}, column {$column})
+stage-ui-warning-busy-loop = Warning: this loop never moves, digs or sleeps, so the stone will get stuck.
+stage-ui-warning-unreachable = Warning: this code never runs because the loop before it never ends.
+stage-ui-warning-unknown-direction = Warning: "{$direction}" is not a direction. Use left, up, right or down.
+stage-ui-warning-missing-command = Warning: this stone cannot use {$command}.
@@ -27,6 +27,7 @@ options-fullscreen-off = OFF
 options-language-label = スクリプト言語
 options-language-rhai = Rhai
 options-language-keystone = Keystone
+options-language-blocks = ブロック
 options-button-back = 戻る
 options-locale-label = 言語設定
 options-locale-ja = 日本語
@@ -36,8 +37,47 @@ options-locale-zh = 中文
 stage-ui-back-to-title = ステージ選択に戻る
 stage-ui-menu-run = 実行
 stage-ui-menu-stop = 停止
+stage-ui-menu-debug = デバッグ
+stage-ui-debug-rhai-only = デバッグは今のところ Rhai だけで使えます。Keystone のプログラムは実行中の行を伝えられません。
+stage-ui-menu-step = ステップ
+stage-ui-menu-continue = 続行
 stage-ui-menu-font-decrease = -
 stage-ui-menu-font-increase = +
+stage-ui-stone-tab = 石 {$index}
+stage-ui-inspector-title = インスペクター
+stage-ui-inspector-sensors = センサー
+stage-ui-inspector-variables = 変数
+stage-ui-inspector-no-variables = まだ変数はありません。
+stage-ui-inspector-variables-off = 変数を見るには、実行前に「変数を調べる」をオンにしてください。
+stage-ui-inspect-variables = 変数を調べる
+stage-ui-inspect-variables-hint = 実行中のスクリプトの変数をインスペクターに表示します。実行が少し遅くなります。
+stage-ui-console-title = コンソール
+stage-ui-console-clear = クリア
+stage-ui-map-seed = シード {$seed}
+stage-ui-map-seed-load = 読み込む
+stage-ui-map-seed-invalid = 「{$input}」はマップのシードではありません。整数を入力してください。
+stage-ui-map-error-title = ステージを作れませんでした
+stage-ui-map-error-body = このレイアウトは生成できませんでした。別のシードを試すか、ステージ一覧に戻ってください。
+stage-ui-blocks-palette = ブロック
+stage-ui-blocks-program = プログラム
+stage-ui-blocks-empty = ここにブロックをドラッグしてプログラムを作ろう。
+stage-ui-blocks-drop-here = ここにドロップ
+stage-ui-blocks-trash = ここにドロップすると削除
+stage-ui-blocks-preview = 生成されたコード
+stage-ui-blocks-seconds = 秒
+stage-ui-blocks-move = 移動
+stage-ui-blocks-dig = 掘る
+stage-ui-blocks-sleep = 待つ
+stage-ui-blocks-loop = ずっと繰り返す
+stage-ui-blocks-if-touched = 触れたら
+stage-ui-blocks-if-empty = 空いていたら
+stage-ui-blocks-direction-up = 上
+stage-ui-blocks-direction-down = 下
+stage-ui-blocks-direction-left = 左
+stage-ui-blocks-direction-right = 右
+stage-ui-convert-script = {$from} のプログラムを {$to} に変換する
+stage-ui-convert-script-done = 変換しました。
+stage-ui-convert-script-failed = {$from} のプログラムを変換できませんでした。変換できるのは move、dig、sleep、loop、if と変数だけです。
 stage-ui-status-command-help-open = メニュー「コマンド説明」を開きました。
 stage-ui-status-command-help-close = メニュー「コマンド説明」を閉じました。
 stage-ui-feedback-stopped = 実行を停止しました。
@@ -49,6 +89,9 @@ stage-ui-feedback-goal = ステージクリア！
 stage-ui-feedback-advance = ステージ「{$stage}」へ進みます。
 stage-ui-feedback-start = ステージ「{$stage}」が開始されました。
 stage-ui-feedback-complete = 全てのステージをクリアしました！
+stage-ui-seed = シード
+stage-ui-seed-fixed = 固定
+stage-ui-seed-fixed-hint = 次の実行でもこのシードを使い、乱数をまったく同じにします。
 
 stage-ui-tutorial-controls-hint = F1で実行、F2/F3で文字サイズ変更、矢印キーで移動、スペースでジャンプ。
 stage-ui-tutorial-ok = 了解!
@@ -58,12 +101,34 @@ stage-ui-clear-window-title = ステージクリア!
 stage-ui-clear-heading = ゴールに到達しました。
 stage-ui-clear-body = 次の挑戦へ進む前に少し休憩しましょう。
 stage-ui-clear-ok = OK
+stage-ui-clear-metric-lines = 行数
+stage-ui-clear-metric-tokens = トークン数
+stage-ui-clear-metric-commands = 命令数
+stage-ui-clear-metric-sleep = 待ち時間 (秒)
+stage-ui-clear-metric-digs = 掘った数
+stage-ui-clear-metric-par = 目標 {$par}
 
 stage-ui-command-help-button = コマンド説明
 stage-ui-command-help-title = コマンド説明
 stage-ui-command-help-close = 閉じる
 
+stage-ui-syntax-title = 簡単な書き方
+stage-ui-syntax-move = 方向は引用符なしでも書けます: `{$move-up-bare}`
+stage-ui-syntax-repeat = repeatはブロックを決まった回数だけ繰り返します。
+    {$repeat-example}
+stage-ui-syntax-forever = foreverはloopと同じく、実行が止まるまでブロックを繰り返します。
+    {$forever-example}
+stage-ui-syntax-while-touched = while_touchedはプレイヤーが石に触れている間、ブロックを繰り返します。
+    {$while-touched-example}
+
 stage-ui-error-invalid-move-direction = move命令にはleft/top/right/downのいずれかを指定してください: {$direction}
 stage-ui-error-invalid-sleep-duration = sleep命令の秒数は0以上である必要があります。
 stage-ui-error-engine = スクリプト実行エラー: {$message}
 stage-ui-error-unsupported-language = サポートされていないスクリプト言語です: {$language}
+stage-ui-error-command-not-allowed = この石は{$command}を使えません。
+stage-ui-error-location-line = （{$line}行目）
+stage-ui-error-location-line-column = （{$line}行目 {$column}文字目）
+stage-ui-warning-busy-loop = 注意: このloopは移動・掘る・sleepのどれも行わないため、石が止まってしまいます。
+stage-ui-warning-unreachable = 注意: 直前のloopが終わらないため、ここから先は実行されません。
+stage-ui-warning-unknown-direction = 注意: "{$direction}" は方向ではありません。left/up/right/downのいずれかを指定してください。
+stage-ui-warning-missing-command = 注意: この石は{$command}を使えません。
@@ -27,6 +27,7 @@ options-fullscreen-off = 关闭
 options-language-label = 脚本语言
 options-language-rhai = Rhai
 options-language-keystone = Keystone
+options-language-blocks = 积木
 options-button-back = 返回
 options-locale-label = 语言设置
 options-locale-ja = 日本語
@@ -36,8 +37,47 @@ options-locale-zh = 中文
 stage-ui-back-to-title = 返回关卡选择
 stage-ui-menu-run = 运行
 stage-ui-menu-stop = 停止
+stage-ui-menu-debug = 调试
+stage-ui-debug-rhai-only = 目前只有 Rhai 可以调试：Keystone 程序无法报告正在运行的行。
+stage-ui-menu-step = 单步
+stage-ui-menu-continue = 继续
 stage-ui-menu-font-decrease = -
 stage-ui-menu-font-increase = +
+stage-ui-stone-tab = 石头 {$index}
+stage-ui-inspector-title = 检查器
+stage-ui-inspector-sensors = 传感器
+stage-ui-inspector-variables = 变量
+stage-ui-inspector-no-variables = 还没有变量。
+stage-ui-inspector-variables-off = 要查看变量，请在运行前勾选“检查变量”。
+stage-ui-inspect-variables = 检查变量
+stage-ui-inspect-variables-hint = 运行时在检查器中显示脚本的变量。运行会稍微变慢。
+stage-ui-console-title = 控制台
+stage-ui-console-clear = 清除
+stage-ui-map-seed = 种子 {$seed}
+stage-ui-map-seed-load = 载入
+stage-ui-map-seed-invalid = “{$input}”不是地图种子，请输入整数。
+stage-ui-map-error-title = 无法生成关卡
+stage-ui-map-error-body = 无法生成这个布局。请尝试其他种子，或返回关卡列表。
+stage-ui-blocks-palette = 积木
+stage-ui-blocks-program = 程序
+stage-ui-blocks-empty = 把积木拖到这里来编写程序。
+stage-ui-blocks-drop-here = 拖到这里
+stage-ui-blocks-trash = 拖到这里删除
+stage-ui-blocks-preview = 生成的代码
+stage-ui-blocks-seconds = 秒
+stage-ui-blocks-move = 移动
+stage-ui-blocks-dig = 挖掘
+stage-ui-blocks-sleep = 等待
+stage-ui-blocks-loop = 一直重复
+stage-ui-blocks-if-touched = 如果被触碰
+stage-ui-blocks-if-empty = 如果是空的
+stage-ui-blocks-direction-up = 上
+stage-ui-blocks-direction-down = 下
+stage-ui-blocks-direction-left = 左
+stage-ui-blocks-direction-right = 右
+stage-ui-convert-script = 把我的 {$from} 程序转换为 {$to}
+stage-ui-convert-script-done = 已转换。
+stage-ui-convert-script-failed = 无法转换 {$from} 程序。只能转换 move、dig、sleep、loop、if 和变量。
 stage-ui-status-command-help-open = 打开了“命令说明”菜单。
 stage-ui-status-command-help-close = 关闭了“命令说明”菜单。
 stage-ui-feedback-stopped = 已停止运行。
@@ -49,6 +89,9 @@ stage-ui-feedback-goal = 关卡通过！
 stage-ui-feedback-advance = 进入关卡“{$stage}”。
 stage-ui-feedback-start = 关卡“{$stage}”已开始。
 stage-ui-feedback-complete = 所有关卡已通关！
+stage-ui-seed = 种子
+stage-ui-seed-fixed = 固定
+stage-ui-seed-fixed-hint = 下次运行继续使用此种子，让随机数完全重现。
 
 stage-ui-tutorial-controls-hint = F1运行，F2/F3调整字体大小，箭头键移动，空格键跳跃。
 stage-ui-tutorial-ok = 明白了！
@@ -58,12 +101,34 @@ stage-ui-clear-window-title = 关卡通过！
 stage-ui-clear-heading = 已到达终点。
 stage-ui-clear-body = 在挑战下一关之前，先休息一下吧。
 stage-ui-clear-ok = 确定
+stage-ui-clear-metric-lines = 行数
+stage-ui-clear-metric-tokens = 词元数
+stage-ui-clear-metric-commands = 指令数
+stage-ui-clear-metric-sleep = 等待时间（秒）
+stage-ui-clear-metric-digs = 挖掘次数
+stage-ui-clear-metric-par = 标准 {$par}
 
 stage-ui-command-help-button = 命令说明
 stage-ui-command-help-title = 命令说明
 stage-ui-command-help-close = 关闭
 
+stage-ui-syntax-title = 简便写法
+stage-ui-syntax-move = 方向可以不加引号书写: `{$move-up-bare}`
+stage-ui-syntax-repeat = repeat 会将代码块重复固定次数。
+    {$repeat-example}
+stage-ui-syntax-forever = forever 与 loop 相同，会一直重复代码块直到运行停止。
+    {$forever-example}
+stage-ui-syntax-while-touched = while_touched 会在玩家接触石头期间重复代码块。
+    {$while-touched-example}
+
 stage-ui-error-invalid-move-direction = move命令必须指定 left/top/right/down 其中之一: {$direction}
 stage-ui-error-invalid-sleep-duration = sleep命令的秒数必须大于等于0。
 stage-ui-error-engine = 脚本运行错误: {$message}
 stage-ui-error-unsupported-language = 不支持的脚本语言: {$language}
+stage-ui-error-command-not-allowed = 这块石头不能使用{$command}。
+stage-ui-error-location-line = （第{$line}行）
+stage-ui-error-location-line-column = （第{$line}行 第{$column}列）
+stage-ui-warning-busy-loop = 注意: 这个 loop 中没有移动、挖掘或 sleep，石头会卡住。
+stage-ui-warning-unreachable = 注意: 前面的 loop 永远不会结束，这里的代码不会执行。
+stage-ui-warning-unknown-direction = 注意: "{$direction}" 不是方向，请使用 left/up/right/down。
+stage-ui-warning-missing-command = 注意: 这块石头不能使用 {$command}。
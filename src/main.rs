@@ -47,13 +47,24 @@ fn main() {
 
     let launch_profile = LaunchProfile::from_args(env::args().collect::<Vec<_>>().as_slice());
     if launch_profile.changed {
-        println!("Launch profile: {:?}", launch_profile);
+        // stderr, so `--simulate-stage` output on stdout stays machine-readable.
+        eprintln!("Launch profile: {:?}", launch_profile);
     }
     match launch_profile.launch_type {
         LaunchType::ShowChunkGrammarAsciiMap => {
-            chunk_grammar_map::show_ascii_map(launch_profile.stage_id.unwrap_or(StageId(1)).0);
+            print!(
+                "{}",
+                chunk_grammar_map::ascii_map_report(
+                    launch_profile.stage_id.unwrap_or(StageId(1)).0,
+                    launch_profile.map_seed.unwrap_or_else(rand::random),
+                )
+            );
             return;
         }
+        LaunchType::SimulateStage => {
+            let cleared = scenes::stage::simulator::run_from_launch_profile(&launch_profile);
+            std::process::exit(if cleared { 0 } else { 1 });
+        }
         #[cfg(feature = "steam")]
         LaunchType::SteamAppInfo => {
             steam::show_steam_app_info(steam_app_id);
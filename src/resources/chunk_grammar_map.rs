@@ -1,13 +1,52 @@
+use bevy::log::debug;
 use bevy_ecs::component::Component;
-use rand::{Rng, seq::SliceRandom};
+use rand::{Rng, SeedableRng, seq::SliceRandom};
+use rand_chacha::ChaCha8Rng;
 use std::collections::{HashMap, HashSet};
 
 use serde::Deserialize;
+use thiserror::Error;
 
+use crate::resources::solution_metrics::SolutionPar;
+use crate::resources::solvability;
 use crate::resources::stage_catalog::{StageId, StageMeta};
-use crate::resources::stone_type::StoneType;
+use crate::resources::stone_type::{StoneCapabilities, StoneType};
+use crate::util::script_types::SandboxProfile;
 
 pub const MAP_SIZE: (isize, isize) = (30, 20);
+/// Layouts drawn before giving up on finding one the cat can clear.
+const MAX_LAYOUT_ATTEMPTS: usize = 32;
+/// Chunk chains tried for one layout before the failed constraint is reported.
+const MAX_PATH_ATTEMPTS: usize = 256;
+/// Middle chunks one path search may place before it gives up.
+const MAX_SEARCH_STEPS: usize = 10_000;
+
+/// Why a stage could not be laid out.
+#[derive(Debug, Clone, Error)]
+pub enum MapGenError {
+    #[error("stage-{0}.ron does not exist")]
+    UnknownStage(usize),
+    #[error("stage-{stage_id}.ron could not be parsed: {message}")]
+    Parse { stage_id: usize, message: String },
+    #[error("the stage has no {0} chunks")]
+    NoChunks(&'static str),
+    #[error("chunk \"{0}\" has no entry 'I', 'T' or 'B'")]
+    MissingEntry(String),
+    #[error("chunk \"{0}\" has no exit 'E', 'D' or 'U' inside the map")]
+    MissingExit(String),
+    #[error("no goal chunk fits between the path and the map edge")]
+    NoGoalTarget,
+    #[error("required chunk \"{0}\" did not fit in the map")]
+    MandatoryChunkDidNotFit(String),
+    #[error("no chain of middle chunks reaches the goal entry")]
+    NoPathFound,
+    #[error("none of {0} layouts can be cleared by the cat")]
+    Unsolvable(usize),
+    #[error("stones.ron could not be parsed: {0}")]
+    StoneDefinitions(String),
+    #[error("stone type {0} is not defined in stones.ron")]
+    UnknownStoneType(StoneType),
+}
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
 enum Dir {
@@ -47,7 +86,6 @@ pub enum TileKind {
 }
 
 type ExitPoint = ((isize, isize), Dir);
-type PlacedChunkExit = (PlacedChunk, ExitPoint);
 
 #[derive(Clone, Copy, Debug)]
 pub struct Tile {
@@ -64,6 +102,25 @@ struct InnerChunkTemplate {
     exits: Vec<Port>,
     tiles: Vec<Tile>,
     required_count: usize,
+    weight: u32,
+    max_count: Option<usize>,
+    min_distance_from_start: usize,
+    forbid_after: Vec<String>,
+    require_after: Vec<String>,
+}
+
+impl InnerChunkTemplate {
+    /// Whether the chunk may be placed next in `layout`, which begins with the start chunk.
+    fn may_follow(&self, layout: &[PlacedChunk]) -> bool {
+        let Some(previous) = layout.last() else {
+            return false;
+        };
+        let placed = layout.iter().filter(|chunk| chunk.id == self.id).count();
+        layout.len() >= self.min_distance_from_start
+            && self.max_count.is_none_or(|max| placed < max)
+            && !self.forbid_after.contains(&previous.id)
+            && (self.require_after.is_empty() || self.require_after.contains(&previous.id))
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -71,18 +128,75 @@ pub struct PlacedChunk {
     pub id: String,
     exits_world: Vec<((isize, isize), Dir)>, // 位置＋方向
     pub tiles_world: Vec<Tile>,
+    origin: (isize, isize),
+    size: (isize, isize),
+}
+
+impl PlacedChunk {
+    /// Whether the two chunks put different tiles on the same cell.
+    fn overlaps(&self, other: &PlacedChunk) -> bool {
+        let low = (
+            self.origin.0.max(other.origin.0),
+            self.origin.1.max(other.origin.1),
+        );
+        let high = (
+            (self.origin.0 + self.size.0).min(other.origin.0 + other.size.0),
+            (self.origin.1 + self.size.1).min(other.origin.1 + other.size.1),
+        );
+        if low.0 >= high.0 || low.1 >= high.1 {
+            return false;
+        }
+        let shared =
+            |tile: &&Tile| (low.0..high.0).contains(&tile.x) && (low.1..high.1).contains(&tile.y);
+        let tiles = self.tiles_world.iter().filter(shared).collect::<Vec<_>>();
+        other.tiles_world.iter().filter(shared).any(|tile| {
+            tiles
+                .iter()
+                .any(|own| (own.x, own.y) == (tile.x, tile.y) && own.kind != tile.kind)
+        })
+    }
+
+    /// Exits that lead to a cell inside a map of `map_size`, in template order.
+    fn exits_in(&self, map_size: (isize, isize)) -> impl Iterator<Item = ExitPoint> + '_ {
+        self.exits_world
+            .iter()
+            .copied()
+            .filter(move |((x, y), _)| (0..map_size.0).contains(x) && (0..map_size.1).contains(y))
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct ChunkTemplate {
     id: String,
+    /// Rows top to bottom. Entries are `I` (from the left), `T` (from above) and `B` (from
+    /// below); exits are `E` (to the right), `D` (down) and `U` (up).
     map: Vec<String>,
     #[serde(default)]
     required_count: usize,
+    /// Relative chance of an optional middle chunk being tried first; 0 never places it.
+    #[serde(default = "default_weight")]
+    weight: u32,
+    /// Most chunks with this id in one layout, required ones included.
+    #[serde(default)]
+    max_count: Option<usize>,
+    /// Chunks from the start chunk to this one; 1 allows it right after the start. Required
+    /// chunks are laid out first, so only other required chunks can come before them.
+    #[serde(default)]
+    min_distance_from_start: usize,
+    /// Chunk ids this chunk may not directly follow.
+    #[serde(default)]
+    forbid_after: Vec<String>,
+    /// Chunk ids this chunk has to directly follow; empty allows any.
+    #[serde(default)]
+    require_after: Vec<String>,
+}
+
+fn default_weight() -> u32 {
+    1
 }
 
 impl ChunkTemplate {
-    fn to_inner_template(&self, check_entry: bool) -> InnerChunkTemplate {
+    fn to_inner_template(&self, check_entry: bool) -> Result<InnerChunkTemplate, MapGenError> {
         let height = self.map.len() as isize;
         let width = self.map.iter().map(|row| row.len()).max().unwrap_or(0) as isize;
 
@@ -103,6 +217,7 @@ impl ChunkTemplate {
                     _ => None,
                 };
                 let Some(kind) = kind else {
+                    // ポートの向きはチャンクの外側を向く
                     match ch {
                         'I' => {
                             entry = Some(Port {
@@ -111,11 +226,25 @@ impl ChunkTemplate {
                                 dir: Dir::Left,
                             })
                         }
+                        'T' => entry = Some(Port { x, y, dir: Dir::Up }),
+                        'B' => {
+                            entry = Some(Port {
+                                x,
+                                y,
+                                dir: Dir::Down,
+                            })
+                        }
                         'E' => exits.push(Port {
                             x,
                             y,
                             dir: Dir::Right,
                         }),
+                        'D' => exits.push(Port {
+                            x,
+                            y,
+                            dir: Dir::Down,
+                        }),
+                        'U' => exits.push(Port { x, y, dir: Dir::Up }),
                         _ => {}
                     }
                     continue;
@@ -125,7 +254,7 @@ impl ChunkTemplate {
         }
 
         let entry = if check_entry {
-            entry.expect("entry point 'I' not found")
+            entry.ok_or_else(|| MapGenError::MissingEntry(self.id.clone()))?
         } else {
             Port {
                 x: 0,
@@ -134,14 +263,19 @@ impl ChunkTemplate {
             }
         };
 
-        InnerChunkTemplate {
+        Ok(InnerChunkTemplate {
             id: self.id.clone(),
             size: (width, height),
             entry,
             exits,
             tiles,
             required_count: self.required_count,
-        }
+            weight: self.weight,
+            max_count: self.max_count,
+            min_distance_from_start: self.min_distance_from_start,
+            forbid_after: self.forbid_after.clone(),
+            require_after: self.require_after.clone(),
+        })
     }
 }
 
@@ -155,7 +289,16 @@ pub struct ChunkGrammarConfig {
     map_size: (isize, isize),
     #[serde(default)]
     pub stone_type: StoneType,
+    /// Types of the stones in map order; stones past the end use `stone_type`.
+    #[serde(default)]
+    pub stone_types: Vec<StoneType>,
     pub dig_limit: Option<u32>,
+    /// Par values the clear rating compares a solution against.
+    #[serde(default)]
+    pub par: SolutionPar,
+    /// Script limits for this stage; unset fields keep their defaults.
+    #[serde(default)]
+    pub sandbox: SandboxProfile,
     pub adjustments: Option<Adjustments>,
     start_chunks: Vec<ChunkTemplate>,
     middle_chunks: Vec<ChunkTemplate>,
@@ -163,21 +306,21 @@ pub struct ChunkGrammarConfig {
 }
 
 impl ChunkGrammarConfig {
-    fn starts(&self) -> Vec<InnerChunkTemplate> {
+    fn starts(&self) -> Result<Vec<InnerChunkTemplate>, MapGenError> {
         self.start_chunks
             .iter()
             .map(|t| t.to_inner_template(false))
             .collect()
     }
 
-    fn middles(&self) -> Vec<InnerChunkTemplate> {
+    fn middles(&self) -> Result<Vec<InnerChunkTemplate>, MapGenError> {
         self.middle_chunks
             .iter()
             .map(|t| t.to_inner_template(true))
             .collect()
     }
 
-    fn goals(&self) -> Vec<InnerChunkTemplate> {
+    fn goals(&self) -> Result<Vec<InnerChunkTemplate>, MapGenError> {
         self.goal_chunks
             .iter()
             .map(|t| t.to_inner_template(true))
@@ -185,28 +328,63 @@ impl ChunkGrammarConfig {
     }
 }
 
-fn generate_random_layout(config: &ChunkGrammarConfig) -> PlacedChunkLayout {
-    let starts = config.starts();
-    let middles = config.middles();
-    let goals = config.goals();
-    try_build_random_path(
-        config.map_size,
-        config.adjustments.clone(),
-        &starts,
-        &middles,
-        &goals,
-    )
-}
+/// Lays out the stage from `config`; the same `seed` always gives the same map.
+///
+/// Layouts the cat cannot clear with the stage's stones are drawn again, up to
+/// `MAX_LAYOUT_ATTEMPTS` times.
+pub fn generate_map_from_config(
+    config: ChunkGrammarConfig,
+    seed: u64,
+    stones: &StoneCapabilities,
+) -> Result<Map, MapGenError> {
+    if let Some(err) = stones.load_error() {
+        return Err(MapGenError::StoneDefinitions(err.to_string()));
+    }
+    let starts = config.starts()?;
+    let middles = config.middles()?;
+    let goals = config.goals()?;
 
-pub fn generate_map_from_config(config: ChunkGrammarConfig) -> Map {
-    let placed_chunk_layout = generate_random_layout(&config);
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    for attempt in 1..=MAX_LAYOUT_ATTEMPTS {
+        let layout = try_build_random_path(
+            &mut rng,
+            config.map_size,
+            config.adjustments.clone(),
+            &starts,
+            &middles,
+            &goals,
+        )?;
+        let map = build_map(&config, seed, layout);
+        let stone_count = map.tile_positions(TileKind::Stone).len();
+        if let Some(stone_type) = (0..stone_count)
+            .map(|index| map.stone_type_at(index))
+            .find(|stone_type| stones.definition(stone_type).is_none())
+        {
+            return Err(MapGenError::UnknownStoneType(stone_type));
+        }
+        if solvability::is_solvable(&map, stones) {
+            return Ok(map);
+        }
+        debug!("layout attempt {attempt} is not solvable, retrying");
+    }
+    Err(MapGenError::Unsolvable(MAX_LAYOUT_ATTEMPTS))
+}
 
+fn build_map(
+    config: &ChunkGrammarConfig,
+    seed: u64,
+    placed_chunk_layout: PlacedChunkLayout,
+) -> Map {
     let mut map = Map {
         placed_chunks: placed_chunk_layout.placed_chunks,
         adjustment: placed_chunk_layout.adjustment,
         map_size: placed_chunk_layout.map_size,
-        stone_type: config.stone_type,
+        stone_type: config.stone_type.clone(),
+        stone_types: config.stone_types.clone(),
         dig_limit: config.dig_limit,
+        par: config.par,
+        sandbox: config.sandbox,
+        seed,
         boundary_margin: placed_chunk_layout.boundary_margin,
         margin_tiles: placed_chunk_layout.margin_tiles,
     };
@@ -322,27 +500,37 @@ fn adjust_goal_layout(map: &mut Map) {
     }
 }
 
-pub fn show_ascii_map(stage_id: usize) {
+/// Report for `--show-chunk-grammar-ascii-map`: the placed chunks followed by the ASCII map.
+pub fn ascii_map_report(stage_id: usize, seed: u64) -> String {
     let meta = StageMeta {
         id: StageId(stage_id),
         title: "".to_string(),
         unlocked: true,
     };
 
-    let map = meta.load_map();
+    let map = match meta.load_map(seed, &StoneCapabilities::default()) {
+        Ok(map) => map,
+        Err(err) => return format!("stage-{stage_id} seed {seed}: {err}"),
+    };
 
-    println!("== Placed Chunks ==");
-    println!(
-        "map size: {:?}, boundary margin: {:?}",
+    let mut report = String::from("== Placed Chunks ==\n");
+    report += &format!("seed: {}\n", map.seed);
+    report += &format!(
+        "map size: {:?}, boundary margin: {:?}\n",
         map.map_size, map.boundary_margin
     );
-    for chunk in &map.placed_chunks {
-        println!("- {}", chunk.id);
-    }
-    println!();
+    report += &placed_chunk_list(&map);
+    report += "\n== ASCII Map ==\n";
+    report += &ascii_map(&map);
+    report
+}
 
-    println!("== ASCII Map ==");
-    print_ascii_map(&map);
+/// One `- <id>` line per placed chunk, in placement order.
+pub fn placed_chunk_list(map: &Map) -> String {
+    map.placed_chunks
+        .iter()
+        .map(|chunk| format!("- {}\n", chunk.id))
+        .collect()
 }
 
 fn build_tile_char_map(map: &Map) -> HashMap<(isize, isize), char> {
@@ -363,32 +551,19 @@ fn build_tile_char_map(map: &Map) -> HashMap<(isize, isize), char> {
     char_map
 }
 
-/// 指定方向の出口を1つ拾う（最小実装：最初の一致を返す）
-fn pick_exit_dir(p: &PlacedChunk, want: Dir) -> Option<((isize, isize), Dir)> {
-    p.exits_world.iter().copied().find(|(_, d)| *d == want)
-}
-
 /// 既存の“出口（ワールド座標）”に、次チャンクの“entry（ローカル）”を合わせる
-fn place_next(
-    template: &InnerChunkTemplate,
-    required_entry_dir: Dir,
-    exit: ((isize, isize), Dir),
-) -> PlacedChunk {
-    assert_eq!(
-        template.entry.dir, required_entry_dir,
-        "entryの向きが合っていません"
-    );
+///
+/// entry の向きが exit と噛み合わないテンプレートは置けないので `None` を返す
+fn place_next(template: &InnerChunkTemplate, exit: ExitPoint) -> Option<PlacedChunk> {
     let ((exit_pos_x, exit_pos_y), exit_dir) = exit;
     // entry.dir と exit.dir は反対向きが正しい
-    assert_eq!(
-        required_entry_dir.opposite(),
-        exit_dir,
-        "entry/exit の向きが逆になっていません"
-    );
+    if template.entry.dir != exit_dir.opposite() {
+        return None;
+    }
 
     // 原点 = exit_world - entry_local
     let origin = (exit_pos_x - template.entry.x, exit_pos_y - template.entry.y);
-    place_chunk(template, origin)
+    Some(place_chunk(template, origin))
 }
 
 /// チャンクをワールドに敷く（原点のみ指定）
@@ -412,6 +587,8 @@ fn place_chunk(t: &InnerChunkTemplate, (origin_x, origin_y): (isize, isize)) ->
         id: t.id.to_string(),
         exits_world,
         tiles_world,
+        origin: (origin_x, origin_y),
+        size: t.size,
     }
 }
 
@@ -430,6 +607,8 @@ impl PlacedChunkLayout {
         boundary_margin: (isize, isize),
     ) -> Self {
         for chunk in &mut placed_chunks {
+            chunk.origin.0 += boundary_margin.0;
+            chunk.origin.1 += boundary_margin.1;
             for exit in &mut chunk.exits_world {
                 exit.0.0 += boundary_margin.0;
                 exit.0.1 += boundary_margin.1;
@@ -456,7 +635,12 @@ pub struct Map {
     pub adjustment: Option<Adjustments>,
     pub map_size: (isize, isize),
     pub stone_type: StoneType,
+    pub stone_types: Vec<StoneType>,
     pub dig_limit: Option<u32>,
+    pub par: SolutionPar,
+    pub sandbox: SandboxProfile,
+    /// Seed the layout and decorations were drawn from.
+    pub seed: u64,
     pub boundary_margin: (isize, isize),
     margin_tiles: Vec<Tile>,
 }
@@ -489,6 +673,8 @@ impl Map {
         boundary_margin: (isize, isize),
     ) -> Self {
         for chunk in &mut placed_chunks {
+            chunk.origin.0 += boundary_margin.0;
+            chunk.origin.1 += boundary_margin.1;
             for exit in &mut chunk.exits_world {
                 exit.0.0 += boundary_margin.0;
                 exit.0.1 += boundary_margin.1;
@@ -504,7 +690,11 @@ impl Map {
             adjustment,
             map_size: (MAP_SIZE.0, MAP_SIZE.1),
             stone_type,
+            stone_types: Vec::new(),
             dig_limit,
+            par: SolutionPar::default(),
+            sandbox: SandboxProfile::default(),
+            seed: 0,
             boundary_margin,
             margin_tiles: build_margin_tiles(boundary_margin),
         }
@@ -522,7 +712,7 @@ impl Map {
             && let stone_adjustments = &adjustment.stones
             && !stone_adjustments.is_empty()
         {
-            println!(
+            debug!(
                 "Adjusting stone position from ({}, {}) by ({}, {})",
                 x, y, stone_adjustments[0].0, stone_adjustments[0].1
             );
@@ -532,6 +722,30 @@ impl Map {
         (x, y)
     }
 
+    /// Every stone spawn in map order, each shifted by its entry in `adjustment.stones`.
+    pub fn stone_positions(&self) -> Vec<(f32, f32)> {
+        let adjustments = self
+            .adjustment
+            .as_ref()
+            .map(|adjustment| adjustment.stones.as_slice())
+            .unwrap_or_default();
+        self.tile_positions(TileKind::Stone)
+            .into_iter()
+            .enumerate()
+            .map(|(index, (x, y))| {
+                let (dx, dy) = adjustments.get(index).copied().unwrap_or_default();
+                (x as f32 + dx, y as f32 + dy)
+            })
+            .collect()
+    }
+
+    pub fn stone_type_at(&self, index: usize) -> StoneType {
+        self.stone_types
+            .get(index)
+            .unwrap_or(&self.stone_type)
+            .clone()
+    }
+
     pub fn tile_positions(&self, kind: TileKind) -> Vec<(isize, isize)> {
         let mut positions = Vec::new();
         for chunk in &self.placed_chunks {
@@ -596,18 +810,31 @@ fn build_margin_tiles(margin: (isize, isize)) -> Vec<Tile> {
 }
 
 fn try_build_random_path(
+    rng: &mut impl Rng,
     map_size: (isize, isize),
     adjustment: Option<Adjustments>,
     start_chunks: &[InnerChunkTemplate],
     mid_chunks: &[InnerChunkTemplate],
     goal_chunks: &[InnerChunkTemplate],
-) -> PlacedChunkLayout {
-    let mut rng = rand::rng();
-    let placed_start = place_chunk(
-        &start_chunks[rng.random_range(0..start_chunks.len())],
-        (0, 0),
-    );
-    let start_exit = pick_exit_dir(&placed_start, Dir::Right).unwrap();
+) -> Result<PlacedChunkLayout, MapGenError> {
+    if start_chunks.is_empty() {
+        return Err(MapGenError::NoChunks("start"));
+    }
+    if goal_chunks.is_empty() {
+        return Err(MapGenError::NoChunks("goal"));
+    }
+    let start_template = &start_chunks[rng.random_range(0..start_chunks.len())];
+    // 下へ降りるスタートは上端に置き、縦穴の分の空きを下に残す
+    let start_y = if start_template.exits.iter().any(|p| p.dir == Dir::Down) {
+        (map_size.1 - start_template.size.1).max(0)
+    } else {
+        0
+    };
+    let placed_start = place_chunk(start_template, (0, start_y));
+    let start_exit = placed_start
+        .exits_in(map_size)
+        .next()
+        .ok_or_else(|| MapGenError::MissingExit(placed_start.id.clone()))?;
 
     let mut required_templates = Vec::new();
     let mut optional_templates = Vec::new();
@@ -621,82 +848,85 @@ fn try_build_random_path(
         }
     }
 
-    print!("required_templates: ");
-    for t in &required_templates {
-        print!("{} ", t.id);
-    }
-    println!();
+    debug!(
+        "required_templates: {}",
+        required_templates
+            .iter()
+            .map(|template| template.id.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
 
-    loop {
+    // Only the last failure is reported; each attempt redraws every random choice.
+    let mut failure = MapGenError::NoPathFound;
+    for _ in 0..MAX_PATH_ATTEMPTS {
         let mut mandatory_queue = required_templates.clone();
-        mandatory_queue.shuffle(&mut rng);
+        mandatory_queue.shuffle(rng);
 
-        let mut mandatory_chunks = Vec::with_capacity(mandatory_queue.len());
+        let mut layout = vec![placed_start.clone()];
         let mut path_start_exit = start_exit;
-        let mut mandatory_failed = false;
+        let mut unfit = None;
         for template in mandatory_queue {
-            let ((current_pos_x, current_pos_y), _) = path_start_exit;
-            if current_pos_x < template.entry.x || current_pos_y < template.entry.y {
-                mandatory_failed = true;
+            if !template.may_follow(&layout) {
+                unfit = Some(template);
                 break;
             }
-            let Some((placed, next_exit)) = place_middle_chunk(template, path_start_exit, map_size)
+            let Some(placed) = place_middle_chunk(template, path_start_exit, map_size, &layout)
             else {
-                mandatory_failed = true;
+                unfit = Some(template);
+                break;
+            };
+            let Some(next_exit) = placed.exits_in(map_size).next() else {
+                unfit = Some(template);
                 break;
             };
             path_start_exit = next_exit;
-            mandatory_chunks.push(placed);
+            layout.push(placed);
         }
-        if mandatory_failed {
+        if let Some(template) = unfit {
+            failure = MapGenError::MandatoryChunkDidNotFit(template.id.clone());
             continue;
         }
 
         let goal_template = &goal_chunks[rng.random_range(0..goal_chunks.len())];
-        let Some(goal_target) =
-            random_goal_target(&mut rng, map_size, path_start_exit, goal_template)
+        let Some(goal_target) = random_goal_target(rng, map_size, path_start_exit, goal_template)
+            .filter(|goal| !layout.iter().any(|chunk| chunk.overlaps(&goal.chunk)))
         else {
+            failure = MapGenError::NoGoalTarget;
             continue;
         };
 
-        if let Some(mut mid_path) = find_path_to_goal(
-            &mut rng,
+        if find_path_to_goal(
+            rng,
             map_size,
             &optional_templates,
             path_start_exit,
-            goal_target.entry,
+            &goal_target,
+            &mut layout,
         ) {
-            let (final_exit_pos, _) = mid_path
-                .last()
-                .and_then(|chunk| pick_exit_dir(chunk, Dir::Right))
-                .unwrap_or(path_start_exit);
-            if final_exit_pos != goal_target.entry {
-                continue;
-            }
-
-            let mut layout = Vec::with_capacity(mandatory_chunks.len() + mid_path.len() + 2);
-            layout.push(placed_start.clone());
-            layout.extend(mandatory_chunks);
-            layout.append(&mut mid_path);
-            layout.push(place_chunk(goal_template, goal_target.origin));
+            layout.push(goal_target.chunk);
 
             let boundary_margin = ((MAP_SIZE.0 - map_size.0) / 2, (MAP_SIZE.1 - map_size.1) / 2);
-            return PlacedChunkLayout::new(layout, adjustment, boundary_margin);
+            return Ok(PlacedChunkLayout::new(layout, adjustment, boundary_margin));
         }
+        failure = MapGenError::NoPathFound;
     }
+    Err(failure)
 }
 
-struct GoalTarget {
-    origin: (isize, isize),
-    entry: (isize, isize),
+struct GoalTarget<'a> {
+    template: &'a InnerChunkTemplate,
+    chunk: PlacedChunk,
+    /// The goal chunk's entry; the path has to end on an exit facing it.
+    entry: ExitPoint,
 }
 
-fn random_goal_target(
+fn random_goal_target<'a>(
     rng: &mut impl Rng,
     map_size: (isize, isize),
     start_exit: ((isize, isize), Dir),
-    goal_template: &InnerChunkTemplate,
-) -> Option<GoalTarget> {
+    goal_template: &'a InnerChunkTemplate,
+) -> Option<GoalTarget<'a>> {
     let (start_pos, _) = start_exit;
     let max_origin_x = map_size.0.checked_sub(goal_template.size.0)?;
     let max_origin_y = map_size.1.checked_sub(goal_template.size.1)?;
@@ -721,17 +951,20 @@ fn random_goal_target(
         return None;
     }
     Some(GoalTarget {
-        origin: (origin_x, origin_y),
-        entry,
+        template: goal_template,
+        chunk: place_chunk(goal_template, (origin_x, origin_y)),
+        entry: (entry, goal_template.entry.dir),
     })
 }
 
+/// Places `template` on `current_exit` if it fits in the map without covering `layout`.
 fn place_middle_chunk(
     template: &InnerChunkTemplate,
     current_exit: ExitPoint,
     map_size: (isize, isize),
-) -> Option<PlacedChunkExit> {
-    let placed = place_next(template, Dir::Left, current_exit);
+    layout: &[PlacedChunk],
+) -> Option<PlacedChunk> {
+    let placed = place_next(template, current_exit)?;
     if placed
         .tiles_world
         .iter()
@@ -739,32 +972,33 @@ fn place_middle_chunk(
     {
         return None;
     }
-    let next_exit = pick_exit_dir(&placed, Dir::Right)?;
-    let ((_, next_pos_y), _) = next_exit;
-    if next_pos_y >= map_size.1 {
+    if layout.iter().any(|chunk| chunk.overlaps(&placed)) {
         return None;
     }
-    Some((placed, next_exit))
+    Some(placed)
 }
 
+/// Extends `layout` with middle chunks from `start_exit` up to the goal entry.
 fn find_path_to_goal(
     rng: &mut impl Rng,
     map_size: (isize, isize),
     mid_chunks: &[&InnerChunkTemplate],
-    start_exit: ((isize, isize), Dir),
-    goal_entry: (isize, isize),
-) -> Option<Vec<PlacedChunk>> {
-    let mut path = Vec::new();
+    start_exit: ExitPoint,
+    goal: &GoalTarget,
+    layout: &mut Vec<PlacedChunk>,
+) -> bool {
     let mut visited = HashSet::new();
     visited.insert(start_exit.0);
+    let mut steps_left = MAX_SEARCH_STEPS;
     search_path_to_goal(
         rng,
         map_size,
         mid_chunks,
         start_exit,
-        goal_entry,
-        &mut path,
+        goal,
+        layout,
         &mut visited,
+        &mut steps_left,
     )
 }
 
@@ -772,57 +1006,328 @@ fn search_path_to_goal(
     rng: &mut impl Rng,
     map_size: (isize, isize),
     candidates: &[&InnerChunkTemplate],
-    current_exit: ((isize, isize), Dir),
-    goal_entry: (isize, isize),
+    current_exit: ExitPoint,
+    goal: &GoalTarget,
     path: &mut Vec<PlacedChunk>,
     visited: &mut HashSet<(isize, isize)>,
-) -> Option<Vec<PlacedChunk>> {
-    let (current_pos, _) = current_exit;
-    if current_pos == goal_entry {
-        return Some(path.clone());
+    steps_left: &mut usize,
+) -> bool {
+    let (current_pos, current_dir) = current_exit;
+    let (goal_pos, goal_dir) = goal.entry;
+    if current_pos == goal_pos {
+        // The goal chunk follows whatever the path ends with, under the same rules.
+        return current_dir == goal_dir.opposite() && goal.template.may_follow(path);
     }
-    if current_pos.0 > goal_entry.0 {
-        return None;
+    // 左向きの出口は無いので、ゴールを右に越えたら戻れない
+    if current_pos.0 > goal_pos.0 {
+        return false;
     }
 
-    let mut shuffled_candidates = candidates.to_vec();
-    shuffled_candidates.shuffle(rng);
-
-    for template in shuffled_candidates {
-        if current_pos.0 < template.entry.x || current_pos.1 < template.entry.y {
+    for template in weighted_order(rng, candidates) {
+        if !template.may_follow(path) {
             continue;
         }
-        let Some((placed, next_exit)) = place_middle_chunk(template, current_exit, map_size) else {
+        let Some(placed) = place_middle_chunk(template, current_exit, map_size, path) else {
             continue;
         };
-        let (next_pos, _) = next_exit;
-        if next_pos.0 > goal_entry.0 {
-            continue;
-        }
-        if !visited.insert(next_pos) {
+        if placed.overlaps(&goal.chunk) {
             continue;
         }
+        // 出口が複数あるチャンクは、先に書かれた出口から順に試す
+        let next_exits = placed.exits_in(map_size).collect::<Vec<_>>();
         path.push(placed);
-        if let Some(result) = search_path_to_goal(
-            rng, map_size, candidates, next_exit, goal_entry, path, visited,
-        ) {
-            return Some(result);
+        for next_exit in next_exits {
+            let (next_pos, _) = next_exit;
+            if next_pos.0 > goal_pos.0 || !visited.insert(next_pos) {
+                continue;
+            }
+            if *steps_left == 0 {
+                path.pop();
+                return false;
+            }
+            *steps_left -= 1;
+            if search_path_to_goal(
+                rng, map_size, candidates, next_exit, goal, path, visited, steps_left,
+            ) {
+                return true;
+            }
+            visited.remove(&next_pos);
         }
         path.pop();
-        visited.remove(&next_pos);
     }
 
-    None
+    false
+}
+
+/// `candidates` in a random order where heavier chunks tend to come first; weight 0 is left out.
+fn weighted_order<'a>(
+    rng: &mut impl Rng,
+    candidates: &[&'a InnerChunkTemplate],
+) -> Vec<&'a InnerChunkTemplate> {
+    // Efraimidis-Spirakis: sorting by u^(1/w) draws without replacement in proportion to w.
+    let mut keyed = candidates
+        .iter()
+        .filter(|template| template.weight > 0)
+        .map(|&template| {
+            let key = rng.random::<f64>().powf(1.0 / f64::from(template.weight));
+            (key, template)
+        })
+        .collect::<Vec<_>>();
+    keyed.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    keyed.into_iter().map(|(_, template)| template).collect()
 }
 
-pub fn print_ascii_map(map: &Map) {
+/// The map drawn one character per tile, top row first.
+pub fn ascii_map(map: &Map) -> String {
     let tile_map = build_tile_char_map(map);
     let (map_width, map_height) = map.map_size;
+    let mut out = String::new();
     for y in (0..map_height).rev() {
         for x in 0..map_width {
-            let ch = tile_map.get(&(x, y)).copied().unwrap_or('.');
-            print!("{ch}");
+            out.push(tile_map.get(&(x, y)).copied().unwrap_or('.'));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(stage_id: usize, seed: u64) -> Vec<((isize, isize), TileKind)> {
+        let meta = StageMeta {
+            id: StageId(stage_id),
+            title: String::new(),
+            unlocked: true,
+        };
+        let map = meta
+            .load_map(seed, &StoneCapabilities::default())
+            .expect("stage should generate");
+        assert_eq!(map.seed, seed);
+        map.map_iter().collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_layout() {
+        for stage_id in 1..=12 {
+            assert_eq!(layout(stage_id, 1234), layout(stage_id, 1234));
+        }
+        // Stage 1 has more than one possible layout, so some other seed must differ.
+        assert!((0..8).any(|seed| layout(1, seed) != layout(1, 1234)));
+    }
+
+    #[test]
+    fn bad_configs_fail_instead_of_hanging() {
+        let config = |middle: &str| -> ChunkGrammarConfig {
+            let source = format!(
+                "(
+                    map_size: (10, 5),
+                    start_chunks: [ChunkTemplate(id: \"start\", map: [\"@E\", \"##\"])],
+                    middle_chunks: [{middle}],
+                    goal_chunks: [ChunkTemplate(id: \"goal\", map: [\"I.G\", \"###\"])],
+                )"
+            );
+            ron::de::from_str(&source).expect("test stage should parse")
+        };
+        let stones = StoneCapabilities::default();
+
+        let too_wide = config(
+            "ChunkTemplate(id: \"wide\", required_count: 1, map: [\"I..........E\", \"############\"])",
+        );
+        assert!(matches!(
+            generate_map_from_config(too_wide, 0, &stones),
+            Err(MapGenError::MandatoryChunkDidNotFit(id)) if id == "wide"
+        ));
+
+        let no_entry = config("ChunkTemplate(id: \"closed\", map: [\".E\", \"##\"])");
+        assert!(matches!(
+            generate_map_from_config(no_entry, 0, &stones),
+            Err(MapGenError::MissingEntry(id)) if id == "closed"
+        ));
+
+        let undefined_stone: ChunkGrammarConfig = ron::de::from_str(
+            "(
+                map_size: (5, 5),
+                stone_type: Missing,
+                start_chunks: [ChunkTemplate(id: \"start\", map: [\"@SE\", \"###\"])],
+                middle_chunks: [],
+                goal_chunks: [ChunkTemplate(id: \"goal\", map: [\"I.G\", \"###\"])],
+            )",
+        )
+        .expect("test stage should parse");
+        assert!(matches!(
+            generate_map_from_config(undefined_stone, 0, &stones),
+            Err(MapGenError::UnknownStoneType(stone_type)) if stone_type.as_str() == "Missing"
+        ));
+
+        let broken_stones = StoneCapabilities::from_ron("[(id: Type1");
+        assert!(matches!(
+            generate_map_from_config(config(""), 0, &broken_stones),
+            Err(MapGenError::StoneDefinitions(_))
+        ));
+
+        let missing = StageMeta {
+            id: StageId(99),
+            title: String::new(),
+            unlocked: true,
+        };
+        assert!(matches!(
+            missing.load_map(0, &stones),
+            Err(MapGenError::UnknownStage(99))
+        ));
+    }
+
+    #[test]
+    fn shaft_chunks_connect_vertically() {
+        let source = "(
+            map_size: (12, 10),
+            start_chunks: [ChunkTemplate(id: \"start\", map: [\"@...\", \"###D\"])],
+            middle_chunks: [
+                ChunkTemplate(id: \"shaft\", required_count: 1, map: [
+                    \"T...\",
+                    \"....\",
+                    \"...E\",
+                    \"####\",
+                ]),
+                ChunkTemplate(id: \"floor\", map: [\"IE\", \"##\"]),
+                ChunkTemplate(id: \"step\", map: [\"I..\", \"#.E\"]),
+            ],
+            goal_chunks: [ChunkTemplate(id: \"goal\", map: [\"I.G\", \"###\"])],
+        )";
+        let config: ChunkGrammarConfig =
+            ron::de::from_str(source).expect("test stage should parse");
+        let stones = StoneCapabilities::default();
+        let map = generate_map_from_config(config, 0, &stones).expect("the shaft should connect");
+
+        let (_, spawn_y) = map.tile_positions(TileKind::PlayerSpawn)[0];
+        let shaft = map
+            .placed_chunks
+            .iter()
+            .find(|chunk| chunk.id == "shaft")
+            .expect("the shaft is required");
+        assert!(shaft.tiles_world.iter().all(|tile| tile.y < spawn_y));
+        assert!(solvability::is_solvable(&map, &stones));
+
+        // A chunk entered from above never hangs off a sideways exit.
+        let sideways = "(
+            map_size: (12, 10),
+            start_chunks: [ChunkTemplate(id: \"start\", map: [\"@E\", \"##\"])],
+            middle_chunks: [ChunkTemplate(id: \"shaft\", required_count: 1, map: [\"T.\", \".E\", \"##\"])],
+            goal_chunks: [ChunkTemplate(id: \"goal\", map: [\"I.G\", \"###\"])],
+        )";
+        let config: ChunkGrammarConfig =
+            ron::de::from_str(sideways).expect("test stage should parse");
+        assert!(matches!(
+            generate_map_from_config(config, 0, &stones),
+            Err(MapGenError::MandatoryChunkDidNotFit(id)) if id == "shaft"
+        ));
+    }
+
+    #[test]
+    fn template_rules_shape_the_path() {
+        let source = "(
+            map_size: (16, 4),
+            start_chunks: [ChunkTemplate(id: \"start\", map: [\"@E\", \"##\"])],
+            middle_chunks: [
+                ChunkTemplate(id: \"floor\", map: [\"IE\", \"##\"]),
+                ChunkTemplate(id: \"pit\", weight: 4, forbid_after: [\"pit\"], map: [\"I.E\", \"#.#\"]),
+                ChunkTemplate(id: \"bridge\", require_after: [\"pit\"], map: [\"IE\", \"##\"]),
+                ChunkTemplate(id: \"late\", min_distance_from_start: 3, max_count: Some(1), map: [\"IE\", \"##\"]),
+                ChunkTemplate(id: \"never\", weight: 0, map: [\"IE\", \"##\"]),
+            ],
+            goal_chunks: [ChunkTemplate(id: \"goal\", map: [\"I.G\", \"###\"])],
+        )";
+        let stones = StoneCapabilities::default();
+        for seed in 0..16 {
+            let config: ChunkGrammarConfig =
+                ron::de::from_str(source).expect("test stage should parse");
+            let map =
+                generate_map_from_config(config, seed, &stones).expect("stage should generate");
+            let ids = map
+                .placed_chunks
+                .iter()
+                .map(|chunk| chunk.id.as_str())
+                .collect::<Vec<_>>();
+
+            assert!(!ids.contains(&"never"), "seed {seed}: {ids:?}");
+            assert!(ids.iter().filter(|id| **id == "late").count() <= 1);
+            assert!(
+                ids.iter()
+                    .position(|id| *id == "late")
+                    .is_none_or(|index| index >= 3)
+            );
+            for pair in ids.windows(2) {
+                assert_ne!(pair, ["pit", "pit"], "seed {seed}: {ids:?}");
+                if pair[1] == "bridge" {
+                    assert_eq!(pair[0], "pit", "seed {seed}: {ids:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn goal_chunks_follow_their_rules() {
+        let source = "(
+            map_size: (16, 4),
+            start_chunks: [ChunkTemplate(id: \"start\", map: [\"@E\", \"##\"])],
+            middle_chunks: [
+                ChunkTemplate(id: \"floor\", map: [\"IE\", \"##\"]),
+                ChunkTemplate(id: \"long\", map: [\"I.E\", \"###\"]),
+                ChunkTemplate(id: \"bridge\", map: [\"IE\", \"##\"]),
+            ],
+            goal_chunks: [ChunkTemplate(id: \"goal\", require_after: [\"bridge\"], map: [\"I.G\", \"###\"])],
+        )";
+        let stones = StoneCapabilities::default();
+        for seed in 0..16 {
+            let config: ChunkGrammarConfig =
+                ron::de::from_str(source).expect("test stage should parse");
+            let map =
+                generate_map_from_config(config, seed, &stones).expect("stage should generate");
+            let ids = map
+                .placed_chunks
+                .iter()
+                .map(|chunk| chunk.id.as_str())
+                .collect::<Vec<_>>();
+            assert!(ids.ends_with(&["bridge", "goal"]), "seed {seed}: {ids:?}");
+        }
+    }
+
+    #[test]
+    fn weights_set_how_often_chunks_come_first() {
+        let template = |id: &str, weight| {
+            ChunkTemplate {
+                id: id.to_string(),
+                map: vec!["IE".to_string(), "##".to_string()],
+                required_count: 0,
+                weight,
+                max_count: None,
+                min_distance_from_start: 0,
+                forbid_after: Vec::new(),
+                require_after: Vec::new(),
+            }
+            .to_inner_template(true)
+            .expect("test chunk should convert")
+        };
+        let (heavy, light, never) = (
+            template("heavy", 9),
+            template("light", 1),
+            template("never", 0),
+        );
+        let candidates = [&never, &light, &heavy];
+
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let draws = 2000;
+        let mut heavy_first = 0;
+        for _ in 0..draws {
+            let order = weighted_order(&mut rng, &candidates);
+            assert!(order.iter().all(|template| template.id != "never"));
+            assert_eq!(order.len(), 2);
+            if order[0].id == "heavy" {
+                heavy_first += 1;
+            }
         }
-        println!();
+        // 9 in 10 draws; the bounds sit well over four standard deviations out.
+        assert!((1720..=1880).contains(&heavy_first), "{heavy_first}");
     }
 }
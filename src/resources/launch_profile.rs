@@ -1,12 +1,13 @@
 use bevy::prelude::*;
 
-use crate::resources::stage_catalog::StageId;
+use crate::resources::{script_engine::Language, stage_catalog::StageId};
 
 #[derive(Debug, Clone, Default)]
 pub enum LaunchType {
     #[default]
     Normal,
     ShowChunkGrammarAsciiMap,
+    SimulateStage,
     SteamAppInfo,
 }
 
@@ -18,6 +19,14 @@ pub struct LaunchProfile {
     pub skip_title: bool,
     pub render_physics: bool,
     pub stage_id: Option<StageId>,
+    /// Scripts for `--simulate-stage`, one per stone in map order; `--script` may repeat.
+    pub script_paths: Vec<String>,
+    pub script_language: Option<Language>,
+    pub player_inputs_path: Option<String>,
+    /// Seed of the `rand` sensor for `--simulate-stage`, to replay a reported run.
+    pub rand_seed: Option<u64>,
+    /// Seed of the generated stage layout, to load a map a player reported.
+    pub map_seed: Option<u64>,
 }
 
 impl LaunchProfile {
@@ -37,6 +46,10 @@ impl LaunchProfile {
                     launch_profile.launch_type = LaunchType::ShowChunkGrammarAsciiMap;
                     changed = true;
                 }
+                "--simulate-stage" => {
+                    launch_profile.launch_type = LaunchType::SimulateStage;
+                    changed = true;
+                }
                 "--steam-app-info" => {
                     launch_profile.launch_type = LaunchType::SteamAppInfo;
                     changed = true;
@@ -89,6 +102,46 @@ impl LaunchProfile {
                         warn!("--stage-id flag provided without a value");
                     }
                 }
+                _ if is_value_flag(arg, "--script") => {
+                    if let Some(value) = flag_value(args, &mut index, "--script") {
+                        launch_profile.script_paths.push(value);
+                        changed = true;
+                    }
+                }
+                _ if is_value_flag(arg, "--player-inputs") => {
+                    if let Some(value) = flag_value(args, &mut index, "--player-inputs") {
+                        launch_profile.player_inputs_path = Some(value);
+                        changed = true;
+                    }
+                }
+                _ if is_value_flag(arg, "--rand-seed") => {
+                    if let Some(value) = flag_value(args, &mut index, "--rand-seed") {
+                        match value.parse::<u64>() {
+                            Ok(seed) => launch_profile.rand_seed = Some(seed),
+                            Err(err) => warn!("Invalid rand seed '{value}': {err}"),
+                        }
+                        changed = true;
+                    }
+                }
+                _ if is_value_flag(arg, "--seed") => {
+                    if let Some(value) = flag_value(args, &mut index, "--seed") {
+                        match value.parse::<u64>() {
+                            Ok(seed) => launch_profile.map_seed = Some(seed),
+                            Err(err) => warn!("Invalid map seed '{value}': {err}"),
+                        }
+                        changed = true;
+                    }
+                }
+                _ if is_value_flag(arg, "--language") => {
+                    if let Some(value) = flag_value(args, &mut index, "--language") {
+                        match value.to_ascii_lowercase().as_str() {
+                            "rhai" => launch_profile.script_language = Some(Language::Rhai),
+                            "keystone" => launch_profile.script_language = Some(Language::Keystone),
+                            _ => warn!("Invalid script language '{value}'"),
+                        }
+                        changed = true;
+                    }
+                }
                 _ => {}
             }
             index += 1;
@@ -98,3 +151,28 @@ impl LaunchProfile {
         launch_profile
     }
 }
+
+fn is_value_flag(arg: &str, name: &str) -> bool {
+    arg == name
+        || arg
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.starts_with('='))
+}
+
+/// Reads the value of `--name=value` or `--name value`, advancing `index` for the latter.
+fn flag_value(args: &[String], index: &mut usize, name: &str) -> Option<String> {
+    let arg = args[*index].as_str();
+    if let Some(value) = arg
+        .strip_prefix(name)
+        .and_then(|rest| rest.strip_prefix('='))
+    {
+        return Some(value.to_string());
+    }
+    if *index + 1 < args.len() {
+        *index += 1;
+        Some(args[*index].clone())
+    } else {
+        warn!("{name} flag provided without a value");
+        None
+    }
+}
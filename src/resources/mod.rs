@@ -7,6 +7,8 @@ pub mod launch_profile;
 pub mod locale_resources;
 pub mod script_engine;
 pub mod settings;
+pub mod solution_metrics;
+pub mod solvability;
 pub mod stage_catalog;
 pub mod stage_progress;
 pub mod stage_scripts;
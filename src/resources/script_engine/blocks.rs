@@ -0,0 +1,260 @@
+//! Block programs built in the visual editor and the Rhai/Keystone source they compile to.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use super::Language;
+use crate::util::script_types::MoveDirection;
+
+const INDENT: &str = "    ";
+
+/// Position of a block: indices from the top level down through nested bodies.
+pub type BlockPath = Vec<usize>;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Block {
+    Move(MoveDirection),
+    Dig(MoveDirection),
+    Sleep(f32),
+    Loop(Vec<Block>),
+    IfTouched(Vec<Block>),
+    IfEmpty(MoveDirection, Vec<Block>),
+}
+
+impl Block {
+    /// Blocks offered in the palette, limited to what the stone can do.
+    pub fn palette(allowed_commands: Option<&HashSet<String>>) -> Vec<Block> {
+        [
+            Block::Move(MoveDirection::Top),
+            Block::Dig(MoveDirection::Top),
+            Block::Sleep(1.0),
+            Block::Loop(Vec::new()),
+            Block::IfTouched(Vec::new()),
+            Block::IfEmpty(MoveDirection::Top, Vec::new()),
+        ]
+        .into_iter()
+        .filter(|block| {
+            block
+                .capability()
+                .is_none_or(|capability| allowed_commands.is_none_or(|s| s.contains(capability)))
+        })
+        .collect()
+    }
+
+    /// Stone capability the block needs, matching the names in `StoneCapabilities`.
+    pub fn capability(&self) -> Option<&'static str> {
+        match self {
+            Block::Move(_) => Some("move"),
+            Block::Dig(_) => Some("dig"),
+            Block::Sleep(_) => Some("sleep"),
+            Block::Loop(_) => None,
+            Block::IfTouched(_) => Some("is_touched"),
+            Block::IfEmpty(_, _) => Some("is_empty"),
+        }
+    }
+
+    pub fn body(&self) -> Option<&Vec<Block>> {
+        match self {
+            Block::Loop(body) | Block::IfTouched(body) | Block::IfEmpty(_, body) => Some(body),
+            _ => None,
+        }
+    }
+
+    pub fn body_mut(&mut self) -> Option<&mut Vec<Block>> {
+        match self {
+            Block::Loop(body) | Block::IfTouched(body) | Block::IfEmpty(_, body) => Some(body),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BlockProgram {
+    pub blocks: Vec<Block>,
+}
+
+impl BlockProgram {
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn get(&self, path: &[usize]) -> Option<&Block> {
+        let (index, parent) = path.split_last()?;
+        self.list(parent)?.get(*index)
+    }
+
+    /// Inserts `block` at `path`; an index past the end of its list appends.
+    pub fn insert(&mut self, path: &[usize], block: Block) -> bool {
+        let Some((index, parent)) = path.split_last() else {
+            return false;
+        };
+        let Some(list) = self.list_mut(parent) else {
+            return false;
+        };
+        list.insert((*index).min(list.len()), block);
+        true
+    }
+
+    pub fn remove(&mut self, path: &[usize]) -> Option<Block> {
+        let (index, parent) = path.split_last()?;
+        let list = self.list_mut(parent)?;
+        (*index < list.len()).then(|| list.remove(*index))
+    }
+
+    /// Moves the block at `from` so it ends up at `to`, where `to` is given before the removal.
+    pub fn move_block(&mut self, from: &[usize], to: &[usize]) -> bool {
+        // A block cannot be dropped into its own body.
+        if from.is_empty() || to.starts_with(from) {
+            return false;
+        }
+        let Some(block) = self.remove(from) else {
+            return false;
+        };
+
+        let mut target = to.to_vec();
+        let depth = from.len() - 1;
+        if target.len() > depth && target[..depth] == from[..depth] && target[depth] > from[depth] {
+            target[depth] -= 1;
+        }
+
+        if self.insert(&target, block.clone()) {
+            true
+        } else {
+            self.insert(from, block);
+            false
+        }
+    }
+
+    pub fn to_source(&self, language: Language) -> String {
+        let mut source = String::new();
+        write_blocks(&mut source, &self.blocks, language, 0);
+        source
+    }
+
+    fn list(&self, parent: &[usize]) -> Option<&Vec<Block>> {
+        let mut list = &self.blocks;
+        for &index in parent {
+            list = list.get(index)?.body()?;
+        }
+        Some(list)
+    }
+
+    fn list_mut(&mut self, parent: &[usize]) -> Option<&mut Vec<Block>> {
+        let mut list = &mut self.blocks;
+        for &index in parent {
+            list = list.get_mut(index)?.body_mut()?;
+        }
+        Some(list)
+    }
+}
+
+fn write_blocks(out: &mut String, blocks: &[Block], language: Language, depth: usize) {
+    for block in blocks {
+        let indent = INDENT.repeat(depth);
+        let header = match (block, language) {
+            (Block::Move(dir), Language::Rhai) => format!("move(\"{}\");", direction_name(*dir)),
+            (Block::Move(dir), Language::Keystone) => format!("move {}", direction_name(*dir)),
+            (Block::Dig(dir), Language::Rhai) => format!("dig(\"{}\");", direction_name(*dir)),
+            (Block::Dig(dir), Language::Keystone) => format!("dig {}", direction_name(*dir)),
+            (Block::Sleep(seconds), Language::Rhai) => {
+                format!("sleep({});", seconds_literal(*seconds))
+            }
+            (Block::Sleep(seconds), Language::Keystone) => {
+                format!("sleep {}", seconds_literal(*seconds))
+            }
+            (Block::Loop(_), _) => "loop {".to_string(),
+            (Block::IfTouched(_), _) => "if is_touched() {".to_string(),
+            (Block::IfEmpty(dir, _), Language::Rhai) => {
+                format!("if is_empty(\"{}\") {{", direction_name(*dir))
+            }
+            (Block::IfEmpty(dir, _), Language::Keystone) => {
+                format!("if is_empty({}) {{", direction_name(*dir))
+            }
+        };
+        let _ = writeln!(out, "{indent}{header}");
+
+        if let Some(body) = block.body() {
+            write_blocks(out, body, language, depth + 1);
+            let _ = writeln!(out, "{indent}}}");
+        }
+    }
+}
+
+/// Direction keyword both languages accept (`MoveDirection` displays `Top` as "top").
+fn direction_name(dir: MoveDirection) -> &'static str {
+    match dir {
+        MoveDirection::Left => "left",
+        MoveDirection::Top => "up",
+        MoveDirection::Right => "right",
+        MoveDirection::Down => "down",
+    }
+}
+
+/// Whole seconds are written as integers so they read like the command reference.
+fn seconds_literal(seconds: f32) -> String {
+    if seconds.fract() == 0.0 {
+        format!("{seconds:.0}")
+    } else {
+        format!("{seconds}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::script_engine::RhaiScriptExecutor;
+    use crate::util::script_types::{SandboxProfile, ScriptCommand, ScriptRunner};
+
+    fn sample() -> BlockProgram {
+        BlockProgram {
+            blocks: vec![
+                Block::Sleep(1.5),
+                Block::IfEmpty(MoveDirection::Top, vec![Block::Move(MoveDirection::Top)]),
+                Block::Move(MoveDirection::Right),
+            ],
+        }
+    }
+
+    #[test]
+    fn generated_rhai_runs() {
+        let source = sample().to_source(Language::Rhai);
+        assert_eq!(
+            source,
+            "sleep(1.5);\nif is_empty(\"up\") {\n    move(\"up\");\n}\nmove(\"right\");\n"
+        );
+        assert_eq!(
+            sample().to_source(Language::Keystone),
+            "sleep 1.5\nif is_empty(up) {\n    move up\n}\nmove right\n"
+        );
+
+        let commands = RhaiScriptExecutor::new()
+            .run(&source, None, &SandboxProfile::default())
+            .expect("generated source should run");
+        assert!(matches!(
+            commands.as_slice(),
+            [
+                ScriptCommand::Sleep(_),
+                ScriptCommand::Move(MoveDirection::Right)
+            ]
+        ));
+    }
+
+    #[test]
+    fn palette_follows_capabilities_and_blocks_move() {
+        let allowed: HashSet<String> = ["move", "sleep"].map(String::from).into();
+        let palette = Block::palette(Some(&allowed));
+        assert!(palette.iter().all(|block| !matches!(
+            block,
+            Block::Dig(_) | Block::IfTouched(_) | Block::IfEmpty(_, _)
+        )));
+        assert!(palette.contains(&Block::Loop(Vec::new())));
+
+        let mut program = sample();
+        assert!(!program.move_block(&[1], &[1, 0]));
+        assert!(program.move_block(&[0], &[1, 1]));
+        assert_eq!(program.get(&[0, 1]), Some(&Block::Sleep(1.5)));
+        assert_eq!(program.blocks.len(), 2);
+    }
+}
@@ -1,6 +1,7 @@
+use super::{Language, Token, command_limit_error, tokenize};
 use crate::util::script_types::{
-    MoveDirection, PLAYER_TOUCHED_STATE_KEY, ScriptCommand, ScriptExecutionError, ScriptProgram,
-    ScriptRunner, ScriptState, ScriptStateValue, ScriptStepper,
+    MoveDirection, PLAYER_TOUCHED_STATE_KEY, SandboxProfile, ScriptCommand, ScriptExecutionError,
+    ScriptProgram, ScriptRunner, ScriptState, ScriptStateValue, ScriptStepper, SourceLocation,
 };
 use keystone_lang::*;
 use std::{
@@ -16,10 +17,17 @@ use std::{
 struct StandardApi {
     inner: Arc<Mutex<ScriptState>>,
     shared_signals: Arc<Mutex<HashSet<String>>>,
+    /// Commands the stone supports; `None` allows everything.
+    allowed_commands: Option<Arc<HashSet<String>>>,
+    /// First sensor the script queried without the capability for it.
+    blocked_sensor: Arc<Mutex<Option<&'static str>>>,
 }
 
 impl ExternalApi for StandardApi {
     fn is_touched(&self) -> bool {
+        if !self.allows_sensor("is_touched") {
+            return false;
+        }
         let state = self.inner.lock().unwrap();
         state
             .get(PLAYER_TOUCHED_STATE_KEY)
@@ -28,18 +36,27 @@ impl ExternalApi for StandardApi {
     }
 
     fn is_empty(&self, dir: Direction) -> bool {
+        if !self.allows_sensor("is_empty") {
+            return false;
+        }
         let key = format!("is-empty-{}", dir_to_str(dir));
         let state = self.inner.lock().unwrap();
         state.get(&key).and_then(|v| v.as_bool()).unwrap_or(false)
     }
 
     fn send_signal(&self, channel: &str) {
+        if !self.allows_sensor("signal") {
+            return;
+        }
         if let Ok(mut g) = self.shared_signals.lock() {
             g.insert(channel.to_owned());
         }
     }
 
     fn receive_signal(&self, channel: &str) -> bool {
+        if !self.allows_sensor("signal") {
+            return false;
+        }
         if let Ok(mut g) = self.shared_signals.lock() {
             g.remove(channel)
         } else {
@@ -49,9 +66,80 @@ impl ExternalApi for StandardApi {
 }
 
 impl StandardApi {
+    /// Keeps the values Keystone has a type for; the others cannot reach a Keystone script.
     fn write(&self, state: &ScriptState) {
         if let Ok(mut inner) = self.inner.lock() {
-            *inner = state.clone();
+            *inner = state
+                .iter()
+                .filter(|(_, value)| keystone_type(value).is_some())
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+        }
+    }
+
+    /// Same signals and a copy of the state, restricted to `allowed_commands`.
+    /// Each program gets its own state so stones never read each other's sensors.
+    fn restricted(&self, allowed_commands: Option<&HashSet<String>>) -> Self {
+        let state = self
+            .inner
+            .lock()
+            .map(|state| state.clone())
+            .unwrap_or_default();
+        Self {
+            inner: Arc::new(Mutex::new(state)),
+            shared_signals: self.shared_signals.clone(),
+            allowed_commands: allowed_commands.cloned().map(Arc::new),
+            blocked_sensor: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Same state and restrictions, but signals of its own, so a preflight never wakes the
+    /// receivers of live programs.
+    fn isolated(&self) -> Self {
+        let state = self
+            .inner
+            .lock()
+            .map(|state| state.clone())
+            .unwrap_or_default();
+        Self {
+            inner: Arc::new(Mutex::new(state)),
+            shared_signals: Arc::new(Mutex::new(HashSet::new())),
+            allowed_commands: self.allowed_commands.clone(),
+            blocked_sensor: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn allows(&self, command: &str) -> bool {
+        self.allowed_commands
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(command))
+    }
+
+    fn allows_sensor(&self, sensor: &'static str) -> bool {
+        if self.allows(sensor) {
+            return true;
+        }
+        if let Ok(mut blocked) = self.blocked_sensor.lock() {
+            blocked.get_or_insert(sensor);
+        }
+        false
+    }
+
+    /// Rejects commands and sensor queries the stone cannot perform.
+    fn check(
+        &self,
+        command: Option<&ScriptCommand>,
+        source: &str,
+    ) -> Result<(), ScriptExecutionError> {
+        let sensor = self.blocked_sensor.lock().ok().and_then(|blocked| *blocked);
+        let command = sensor.or_else(|| {
+            command
+                .map(command_capability)
+                .filter(|capability| !self.allows(capability))
+        });
+        match command {
+            Some(command) => Err(not_allowed_error(command, source)),
+            None => Ok(()),
         }
     }
 }
@@ -68,6 +156,7 @@ impl KeystoneScriptExecutor {
             api: StandardApi {
                 inner: Arc::new(Mutex::new(inner_state)),
                 shared_signals,
+                ..Default::default()
             },
         }
     }
@@ -81,15 +170,35 @@ impl Default for KeystoneScriptExecutor {
 }
 
 impl ScriptRunner for KeystoneScriptExecutor {
-    #[allow(dead_code, unused)]
     fn run(
         &self,
         source: &str,
         allowed_commands: Option<&HashSet<String>>,
+        sandbox: &SandboxProfile,
     ) -> Result<Vec<ScriptCommand>, ScriptExecutionError> {
-        Err(ScriptExecutionError::UnsupportedLanguage(
-            "Keystone scripting is not yet implemented".to_string(),
-        ))
+        // Buffered runs get their own state so they never consume signals of live programs.
+        let api = StandardApi::default().restricted(allowed_commands);
+        let api_dyn = Arc::new(api.clone()) as Arc<dyn ExternalApi + Send + Sync>;
+        let iter = eval(source, api_dyn).map_err(|err| map_error(err, source))?;
+
+        let mut commands = Vec::new();
+        for (step, event) in iter.enumerate() {
+            if step >= sandbox.max_operations {
+                return Err(ScriptExecutionError::Engine(
+                    "Too many operations. Check for loops that never end.".to_string(),
+                ));
+            }
+            let command = map_event(event.map_err(|err| map_error(err, source))?);
+            api.check(command.as_ref(), source)?;
+            if let Some(command) = command {
+                if commands.len() >= sandbox.max_commands {
+                    return Err(command_limit_error(sandbox.max_commands));
+                }
+                commands.push(command);
+            }
+        }
+        api.check(None, source)?;
+        Ok(commands)
     }
 }
 
@@ -97,47 +206,60 @@ impl ScriptStepper for KeystoneScriptExecutor {
     fn compile_step(
         &self,
         source: &str,
-        _allowed_commands: Option<&HashSet<String>>,
+        allowed_commands: Option<&HashSet<String>>,
+        sandbox: &SandboxProfile,
+        // Keystone scripts have no `rand` sensor.
+        _rand_seed: u64,
     ) -> Result<Box<dyn ScriptProgram>, ScriptExecutionError> {
-        let api_dyn = Arc::new(self.api.clone()) as Arc<dyn ExternalApi + Send + Sync>;
-        let res = eval(source, api_dyn);
-        match res {
-            Ok(iter) => {
-                let max_step = 100000;
-                let mut step = 0;
-                let preflight = iter.clone();
-                for res in preflight {
-                    step += 1;
-                    if max_step < step {
-                        break;
-                    }
-                    if let Err(e) = res {
-                        return Err(map_error(e));
-                    }
-                }
-                Ok(Box::new(KeystoneScriptProgram::spawn(
-                    iter,
-                    self.api.clone(),
-                )))
+        let api = self.api.restricted(allowed_commands);
+        let preflight_api = api.isolated();
+        let preflight_dyn = Arc::new(preflight_api.clone()) as Arc<dyn ExternalApi + Send + Sync>;
+        let preflight = eval(source, preflight_dyn).map_err(|err| map_error(err, source))?;
+        for (step, res) in preflight.enumerate() {
+            if step >= sandbox.max_operations {
+                break;
+            }
+            match res {
+                Ok(event) => preflight_api.check(map_event(event).as_ref(), source)?,
+                Err(e) => return Err(map_error(e, source)),
             }
-            Err(err) => Err(map_error(err)),
+        }
+        preflight_api.check(None, source)?;
+
+        let api_dyn = Arc::new(api.clone()) as Arc<dyn ExternalApi + Send + Sync>;
+        let iter = eval(source, api_dyn).map_err(|err| map_error(err, source))?;
+        Ok(Box::new(KeystoneScriptProgram::spawn(
+            iter,
+            api,
+            source.to_string(),
+        )))
+    }
+
+    fn clear_signals(&self) {
+        if let Ok(mut signals) = self.api.shared_signals.lock() {
+            signals.clear();
         }
     }
 }
 
+type StepResult = Result<Option<ScriptCommand>, ScriptExecutionError>;
+
+/// Keeps the default `current_location`: keystone-lang events carry no source span, so a
+/// command cannot be traced back to the line that issued it.
 struct KeystoneScriptProgram {
-    receiver: Mutex<Receiver<Option<ScriptCommand>>>,
+    receiver: Mutex<Receiver<StepResult>>,
     stop_flag: Arc<AtomicBool>,
     api: StandardApi,
     resume_tx: mpsc::SyncSender<()>,
 }
 
 impl KeystoneScriptProgram {
-    fn spawn(iter: EventIterator, api: StandardApi) -> Self {
+    fn spawn(iter: EventIterator, api: StandardApi, source: String) -> Self {
         let stop_flag = Arc::new(AtomicBool::new(false));
         let stop_flag_inner = stop_flag.clone();
+        let thread_api = api.clone();
 
-        let (tx, rx) = mpsc::sync_channel::<Option<ScriptCommand>>(1);
+        let (tx, rx) = mpsc::sync_channel::<StepResult>(1);
         let (resume_tx, resume_rx) = mpsc::sync_channel::<()>(0);
 
         std::thread::spawn(move || {
@@ -150,13 +272,23 @@ impl KeystoneScriptProgram {
                     break;
                 }
 
-                let command = map_event(event.expect("script error"));
+                let step = match event {
+                    // Branches the preflight never reached may still use disallowed commands.
+                    Ok(event) => {
+                        let command = map_event(event);
+                        thread_api
+                            .check(command.as_ref(), &source)
+                            .map(|()| command)
+                    }
+                    Err(err) => Err(map_error(err, &source)),
+                };
+                let failed = step.is_err();
 
-                if tx.send(command).is_err() {
-                    break;
+                if tx.send(step).is_err() || failed {
+                    return;
                 }
             }
-            let _ = tx.send(None);
+            let _ = tx.send(Ok(None));
         });
 
         Self {
@@ -169,21 +301,28 @@ impl KeystoneScriptProgram {
 }
 
 impl ScriptProgram for KeystoneScriptProgram {
-    fn next(&mut self, state: &ScriptState) -> Option<ScriptCommand> {
+    fn next(&mut self, state: &ScriptState) -> StepResult {
         if self.stop_flag.load(Ordering::SeqCst) {
-            return None;
+            return Ok(None);
         }
 
         self.api.write(state);
 
         if self.resume_tx.send(()).is_err() {
-            return None;
+            return Ok(None);
         }
 
-        if let Ok(rx) = self.receiver.lock() {
-            rx.recv().ok().flatten()
-        } else {
-            None
+        let step = match self.receiver.lock() {
+            Ok(rx) => rx.recv().ok(),
+            Err(_) => None,
+        };
+        match step {
+            Some(Err(err)) => {
+                self.stop_flag.store(true, Ordering::SeqCst);
+                Err(err)
+            }
+            Some(Ok(command)) => Ok(command),
+            None => Ok(None),
         }
     }
 }
@@ -197,6 +336,24 @@ fn map_event(event: Event) -> Option<ScriptCommand> {
     }
 }
 
+fn command_capability(command: &ScriptCommand) -> &'static str {
+    match command {
+        ScriptCommand::Move(_) => "move",
+        ScriptCommand::Sleep(_) => "sleep",
+        ScriptCommand::Dig(_) => "dig",
+    }
+}
+
+fn not_allowed_error(command: &str, source: &str) -> ScriptExecutionError {
+    let error = ScriptExecutionError::CommandNotAllowed {
+        command: command.to_string(),
+    };
+    match find_identifier(source, command) {
+        Some(location) => error.at(location),
+        None => error,
+    }
+}
+
 fn map_direction(dir: Direction) -> Option<MoveDirection> {
     match dir {
         Direction::Up => Some(MoveDirection::Top),
@@ -207,7 +364,104 @@ fn map_direction(dir: Direction) -> Option<MoveDirection> {
     }
 }
 
-fn map_error(err: Error) -> ScriptExecutionError {
+/// Keystone type a sensor value is read as. Keystone has no lists, and its integers are unsigned.
+fn keystone_type(value: &ScriptStateValue) -> Option<Type> {
+    match value {
+        ScriptStateValue::Bool(_) => Some(Type::Boolean),
+        ScriptStateValue::Float(_) => Some(Type::Float),
+        ScriptStateValue::Int(value) => (*value >= 0).then_some(Type::Uint),
+        ScriptStateValue::String(_) => Some(Type::String),
+        ScriptStateValue::Direction(_) => Some(Type::Direction),
+        ScriptStateValue::List(_) => None,
+    }
+}
+
+/// Rhai names keystone-lang has no hook for: sensors missing from `ExternalApi`, whose values
+/// already reach `StandardApi`, and the console output of `print` and `debug`, which its
+/// events cannot carry.
+const RHAI_ONLY_NAMES: [&str; 8] = [
+    "rand",
+    "position",
+    "x",
+    "y",
+    "digs_left",
+    "goal_direction",
+    "print",
+    "debug",
+];
+
+fn map_error(err: Error, source: &str) -> ScriptExecutionError {
+    match error_location(&err, source) {
+        Some(location) => map_error_kind(err).at(location),
+        None => map_error_kind(err),
+    }
+}
+
+/// keystone-lang errors carry no positions, so recover what we can from the source and messages.
+fn error_location(err: &Error, source: &str) -> Option<SourceLocation> {
+    match err {
+        Error::NameError { name } => find_identifier(source, name),
+        Error::SyntaxError { messages } => messages
+            .iter()
+            .find_map(|message| location_in_message(message)),
+        _ => None,
+    }
+}
+
+/// First use of `name` as an identifier, so mentions in comments and strings are skipped.
+fn find_identifier(source: &str, name: &str) -> Option<SourceLocation> {
+    let tokens = tokenize(source, Language::Keystone, true).ok()?;
+    let spanned = tokens
+        .iter()
+        .find(|spanned| matches!(&spanned.token, Token::Ident(ident) if ident == name))?;
+    Some(
+        SourceLocation::line(spanned.line)
+            .with_column(spanned.column)
+            .with_span(name.chars().count()),
+    )
+}
+
+/// Understands both `--> 3:5` and `line 3, column 5` styles.
+fn location_in_message(message: &str) -> Option<SourceLocation> {
+    if let Some(index) = message.find("-->") {
+        let mut parts = message[index + 3..].trim_start().splitn(2, ':');
+        let line = leading_number(parts.next()?)?;
+        let mut location = SourceLocation::line(line);
+        if let Some(column) = parts.next().and_then(leading_number) {
+            location = location.with_column(column);
+        }
+        return Some(location);
+    }
+
+    let lower = message.to_ascii_lowercase();
+    let line = number_after(&lower, "line")?;
+    let mut location = SourceLocation::line(line);
+    if let Some(column) = number_after(&lower, "column") {
+        location = location.with_column(column);
+    }
+    Some(location)
+}
+
+/// Number following `label` as a whole word, so `line` does not match inside `newline`.
+fn number_after(text: &str, label: &str) -> Option<usize> {
+    text.match_indices(label).find_map(|(index, _)| {
+        let before = text[..index].chars().next_back();
+        if before.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return None;
+        }
+        leading_number(text[index + label.len()..].trim_start_matches([' ', ':']))
+    })
+}
+
+fn leading_number(text: &str) -> Option<usize> {
+    let digits = text
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect::<String>();
+    digits.parse().ok().filter(|value| *value > 0)
+}
+
+fn map_error_kind(err: Error) -> ScriptExecutionError {
     match err {
         Error::InvalidOperandType { op, typ } => ScriptExecutionError::Engine(format!(
             "Cannot use type {} with operator '{}'",
@@ -225,11 +479,16 @@ fn map_error(err: Error) -> ScriptExecutionError {
             type_to_str(right),
             op_to_str(op)
         )),
+        Error::NameError { name } if RHAI_ONLY_NAMES.contains(&name.as_str()) => {
+            ScriptExecutionError::Engine(format!(
+                "'{name}' can only be used in Rhai scripts for now."
+            ))
+        }
         Error::NameError { name } => {
             ScriptExecutionError::Engine(format!("Name '{}' is not defined.", name))
         }
         Error::SyntaxError { messages } => {
-            ScriptExecutionError::Engine(format!("Syntax error occurred. {}", messages[0]))
+            ScriptExecutionError::Engine(format!("Syntax error occurred. {}", messages.join(" ")))
         }
         Error::TooLargeNumber => ScriptExecutionError::Engine("Too large Number used.".to_string()),
         Error::UnexpectedType {
@@ -297,3 +556,231 @@ fn dir_to_str(dir: Direction) -> String {
         _ => String::from("unknown"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities(commands: &[&str]) -> HashSet<String> {
+        commands.iter().map(|command| command.to_string()).collect()
+    }
+
+    fn is_not_allowed(err: &ScriptExecutionError, command: &str) -> bool {
+        matches!(
+            err.kind(),
+            ScriptExecutionError::CommandNotAllowed { command: found } if found == command
+        )
+    }
+
+    #[test]
+    fn names_in_comments_and_strings_are_not_located() {
+        let location = find_identifier("// foo\nlet s = \"foo\"\nfoo\n", "foo")
+            .expect("the bare use is found");
+        assert_eq!((location.line, location.column), (3, Some(1)));
+        assert!(find_identifier("// foo only here\n", "foo").is_none());
+    }
+
+    #[test]
+    fn rhai_only_names_are_named_in_errors() {
+        let executor = KeystoneScriptExecutor::default();
+        let err = executor
+            .compile_step(
+                "move up\nif x > 1 {\n    move down\n}\n",
+                None,
+                &SandboxProfile::default(),
+                0,
+            )
+            .err()
+            .expect("x is not a Keystone sensor");
+        assert!(matches!(
+            err.kind(),
+            ScriptExecutionError::Engine(message) if message.contains("Rhai")
+        ));
+        assert_eq!(err.location().map(|location| location.line), Some(2));
+
+        let err = executor
+            .compile_step("print(\"hi\")\n", None, &SandboxProfile::default(), 0)
+            .err()
+            .expect("Keystone output does not reach the console");
+        assert!(matches!(
+            err.kind(),
+            ScriptExecutionError::Engine(message) if message.starts_with("'print'")
+        ));
+
+        // A variable of the same name is the script's own.
+        let commands = executor
+            .run(
+                "let x = 2\nif x > 1 {\n    move down\n}\n",
+                None,
+                &SandboxProfile::default(),
+            )
+            .expect("the variable shadows nothing");
+        assert!(matches!(
+            commands.as_slice(),
+            [ScriptCommand::Move(MoveDirection::Down)]
+        ));
+    }
+
+    #[test]
+    fn message_labels_match_whole_words() {
+        let location = location_in_message("unexpected newline at line 3, column 5")
+            .expect("the line label is found");
+        assert_eq!((location.line, location.column), (3, Some(5)));
+        assert!(location_in_message("unexpected newline 7").is_none());
+    }
+
+    #[test]
+    fn buffered_runs_stop_at_sandbox_limits() {
+        let executor = KeystoneScriptExecutor::default();
+        let sandbox = SandboxProfile {
+            max_operations: 64,
+            max_commands: 4,
+            ..SandboxProfile::default()
+        };
+        let is_engine_error = |err: &ScriptExecutionError, prefix: &str| matches!(err.kind(), ScriptExecutionError::Engine(message) if message.starts_with(prefix));
+
+        let commands = executor
+            .run("move up\nsleep 1\ndig left\n", None, &sandbox)
+            .expect("a short script finishes");
+        assert!(matches!(
+            commands.as_slice(),
+            [
+                ScriptCommand::Move(MoveDirection::Top),
+                ScriptCommand::Sleep(_),
+                ScriptCommand::Dig(MoveDirection::Left),
+            ]
+        ));
+
+        let err = executor
+            .run("loop {\n    move up\n}\n", None, &sandbox)
+            .expect_err("an endless mover hits the command limit");
+        assert!(is_engine_error(&err, "Too many commands"));
+
+        let err = executor
+            .run("let x = 0\nloop {\n    x = 1\n}\n", None, &sandbox)
+            .expect_err("an endless loop hits the operation limit");
+        assert!(is_engine_error(&err, "Too many operations"));
+    }
+
+    /// Steps over statements that emit nothing, up to the next command.
+    fn next_command(
+        program: &mut Box<dyn ScriptProgram>,
+        state: &ScriptState,
+    ) -> Option<ScriptCommand> {
+        (0..16).find_map(|_| program.next(state).expect("script should run"))
+    }
+
+    #[test]
+    fn preflight_signals_do_not_reach_other_stones() {
+        let executor = KeystoneScriptExecutor::default();
+        let sandbox = SandboxProfile::default();
+        let mut sender = executor
+            .compile_step("move up\nsend_signal(\"go\")\n", None, &sandbox, 0)
+            .expect("sender should compile");
+        let mut receiver = executor
+            .compile_step(
+                "loop {\n    if receive_signal(\"go\") {\n        move left\n    }\n    sleep 1\n}\n",
+                None,
+                &sandbox,
+                0,
+            )
+            .expect("receiver should compile");
+
+        // Compiling the sender ran its preflight, which must not have sent anything.
+        let state = ScriptState::default();
+        assert!(matches!(
+            next_command(&mut receiver, &state),
+            Some(ScriptCommand::Sleep(_))
+        ));
+
+        assert!(matches!(
+            next_command(&mut sender, &state),
+            Some(ScriptCommand::Move(MoveDirection::Top))
+        ));
+        assert!(next_command(&mut sender, &state).is_none());
+        assert!(matches!(
+            next_command(&mut receiver, &state),
+            Some(ScriptCommand::Move(MoveDirection::Left))
+        ));
+    }
+
+    #[test]
+    fn state_values_map_to_keystone_types() {
+        let type_name = |value: ScriptStateValue| keystone_type(&value).map(type_to_str);
+        assert_eq!(type_name(true.into()).as_deref(), Some("Boolean"));
+        assert_eq!(type_name(0.5_f32.into()).as_deref(), Some("Float"));
+        assert_eq!(type_name(3_i64.into()).as_deref(), Some("Uint"));
+        assert_eq!(type_name((-1_i64).into()), None);
+        assert_eq!(type_name("sand".into()).as_deref(), Some("String"));
+        assert_eq!(
+            type_name(MoveDirection::Left.into()).as_deref(),
+            Some("Direction")
+        );
+        assert_eq!(type_name(vec![1_i64, 2].into()), None);
+
+        let api = StandardApi::default();
+        api.write(&ScriptState::from([
+            (PLAYER_TOUCHED_STATE_KEY.to_string(), true.into()),
+            ("distances".to_string(), vec![1_i64, 2].into()),
+        ]));
+        assert!(api.is_touched());
+        let state = api.inner.lock().unwrap();
+        assert!(!state.contains_key("distances"));
+    }
+
+    #[test]
+    fn stones_cannot_use_missing_capabilities() {
+        let executor = KeystoneScriptExecutor::default();
+        let movers = capabilities(&["move", "sleep"]);
+
+        let err = executor
+            .run(
+                "move up\ndig left\n",
+                Some(&movers),
+                &SandboxProfile::default(),
+            )
+            .expect_err("a mover cannot dig");
+        assert!(is_not_allowed(&err, "dig"));
+        assert_eq!(err.location().map(|location| location.line), Some(2));
+
+        let err = executor
+            .compile_step("dig left\n", Some(&movers), &SandboxProfile::default(), 0)
+            .err()
+            .expect("a mover cannot dig");
+        assert!(is_not_allowed(&err, "dig"));
+
+        assert!(
+            executor
+                .run(
+                    "move up\nsleep 1\n",
+                    Some(&movers),
+                    &SandboxProfile::default()
+                )
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn branches_missed_by_preflight_fail_at_runtime() {
+        let executor = KeystoneScriptExecutor::default();
+        let source = "loop {\n    if is_touched() {\n        dig left\n    }\n    move up\n}\n";
+        let mut program = executor
+            .compile_step(
+                source,
+                Some(&capabilities(&["move", "is_touched"])),
+                &SandboxProfile::default(),
+                0,
+            )
+            .expect("the preflight never touches the player");
+
+        let touched = ScriptState::from([(
+            PLAYER_TOUCHED_STATE_KEY.to_string(),
+            ScriptStateValue::Bool(true),
+        )]);
+        let err = (0..32)
+            .find_map(|_| program.next(&touched).err())
+            .expect("dig should be rejected once the branch runs");
+        assert!(is_not_allowed(&err, "dig"));
+        assert!(matches!(program.next(&touched), Ok(None)));
+    }
+}
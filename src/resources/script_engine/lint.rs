@@ -0,0 +1,613 @@
+//! Checks run before a script starts, for mistakes that would otherwise show up as a stuck stone
+//! or a run that never ends.
+//!
+//! Rhai scripts are checked on the AST the engine compiles. keystone-lang only exposes `eval`, so
+//! Keystone scripts are scanned with the translator's tokenizer instead. Warnings never stop a run.
+
+use std::collections::HashSet;
+
+use rhai::{AST, ASTNode, Expr, Position, Stmt};
+
+use super::{
+    Language,
+    rhai_executor::{MOVE_BARE_MARKER, position_location, syntax_engine},
+    translator::{Spanned, Token, tokenize},
+};
+use crate::util::script_types::{MoveDirection, SourceLocation};
+
+/// Script names and the stone capability each one needs.
+const COMMAND_CAPABILITIES: [(&str, &str); 18] = [
+    ("move", "move"),
+    ("move_left", "move"),
+    ("move_right", "move"),
+    ("move_top", "move"),
+    ("move_down", "move"),
+    ("sleep", "sleep"),
+    ("dig", "dig"),
+    ("is_touched", "is_touched"),
+    ("while_touched", "is_touched"),
+    ("is_empty", "is_empty"),
+    ("rand", "rand"),
+    ("position", "position"),
+    ("x", "position"),
+    ("y", "position"),
+    ("digs_left", "digs_left"),
+    ("goal_direction", "goal_direction"),
+    ("send_signal", "signal"),
+    ("receive_signal", "signal"),
+];
+
+/// Commands that hand control back to the game, so a loop calling them cannot spin.
+const YIELDING_COMMANDS: [&str; 7] = [
+    "move",
+    "move_left",
+    "move_right",
+    "move_top",
+    "move_down",
+    "sleep",
+    "dig",
+];
+
+/// Calls that return at once; any other call may be a script function that moves.
+const INSTANT_CALLS: [&str; 12] = [
+    "is_touched",
+    "is_empty",
+    "rand",
+    "position",
+    "x",
+    "y",
+    "digs_left",
+    "goal_direction",
+    "send_signal",
+    "receive_signal",
+    "print",
+    "debug",
+];
+
+const LOOP_EXITS: [&str; 4] = ["break", "return", "throw", "exit"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptWarning {
+    pub kind: ScriptWarningKind,
+    pub location: SourceLocation,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptWarningKind {
+    /// A `loop` or `forever` body never moves, digs or sleeps.
+    BusyLoop,
+    /// Code after a loop that has no way out.
+    UnreachableCode,
+    UnknownDirection {
+        direction: String,
+    },
+    /// A command or sensor the stone lacks; the run fails once it is reached.
+    MissingCommand {
+        command: String,
+    },
+}
+
+/// Warnings for `source`, in source order. Scripts the tokenizer cannot read get none; running
+/// them reports the error instead.
+pub fn lint(
+    source: &str,
+    language: Language,
+    allowed_commands: Option<&HashSet<String>>,
+) -> Vec<ScriptWarning> {
+    let mut warnings = match language {
+        Language::Rhai => lint_rhai(source, allowed_commands),
+        Language::Keystone => lint_keystone(source, allowed_commands),
+    };
+    warnings.sort_by_key(|warning| (warning.location.line, warning.location.column));
+    warnings
+}
+
+/// A loop met while walking a Rhai AST.
+struct RhaiLoop {
+    /// Index of the loop node in the walk path.
+    depth: usize,
+    position: Position,
+    /// `loop` and `forever`; counted and conditional loops end on their own.
+    endless: bool,
+    yields: bool,
+    exits: bool,
+    /// First statement after the loop in the same block.
+    next: Option<Position>,
+}
+
+fn lint_rhai(source: &str, allowed: Option<&HashSet<String>>) -> Vec<ScriptWarning> {
+    let Ok(ast) = syntax_engine().compile(source) else {
+        return Vec::new();
+    };
+
+    let mut loops = Vec::<RhaiLoop>::new();
+    let mut open = Vec::<usize>::new();
+    let mut warnings = Vec::new();
+    let mut reported = HashSet::new();
+    ast.walk(&mut |path: &[ASTNode]| {
+        let depth = path.len() - 1;
+        let node = path[depth];
+        // Loops still on the path enclose this node.
+        while open
+            .last()
+            .is_some_and(|&index| loops[index].depth >= depth)
+        {
+            open.pop();
+        }
+
+        match node {
+            // `break` is custom syntax so it can leave `repeat` and `forever` too.
+            ASTNode::Expr(Expr::Custom(custom, _))
+                if custom.tokens.first().is_some_and(|token| token == "break") =>
+            {
+                if let Some(&index) = open.last() {
+                    loops[index].exits = true;
+                }
+            }
+            ASTNode::Stmt(Stmt::Return(..)) => {
+                open.iter().for_each(|&index| loops[index].exits = true);
+            }
+            _ => {}
+        }
+
+        if let Some((name, position, is_call)) = rhai_command(node) {
+            if is_call && LOOP_EXITS.contains(&name) {
+                open.iter().for_each(|&index| loops[index].exits = true);
+            }
+            if YIELDING_COMMANDS.contains(&name) || (is_call && !INSTANT_CALLS.contains(&name)) {
+                open.iter().for_each(|&index| loops[index].yields = true);
+            }
+            if let Some(allowed) = allowed
+                && let Some((_, capability)) = COMMAND_CAPABILITIES
+                    .iter()
+                    .find(|(command, _)| *command == name)
+                && !allowed.contains(*capability)
+                && reported.insert(name.to_string())
+            {
+                let kind = ScriptWarningKind::MissingCommand {
+                    command: name.to_string(),
+                };
+                warnings.extend(rhai_warning(kind, position));
+            }
+            if matches!(name, "move" | "dig" | "is_empty")
+                && let Some((direction, position)) = rhai_direction(node)
+                && MoveDirection::from_str(direction).is_none()
+            {
+                let kind = ScriptWarningKind::UnknownDirection {
+                    direction: direction.to_string(),
+                };
+                warnings.extend(rhai_warning(kind, position));
+            }
+        }
+
+        if let Some(endless) = rhai_loop(node) {
+            open.push(loops.len());
+            loops.push(RhaiLoop {
+                depth,
+                position: node.position(),
+                endless,
+                yields: false,
+                exits: false,
+                next: if endless {
+                    next_statement(&ast, path)
+                } else {
+                    None
+                },
+            });
+        }
+        true
+    });
+
+    let mut unreachable = HashSet::new();
+    for endless in loops.iter().filter(|rhai_loop| rhai_loop.endless) {
+        if !endless.yields {
+            warnings.extend(rhai_warning(ScriptWarningKind::BusyLoop, endless.position));
+        }
+        // A loop that is itself unreachable already has a warning above it.
+        if !endless.exits
+            && let Some(next) = endless.next
+            && !unreachable.contains(&endless.position)
+        {
+            unreachable.insert(next);
+            warnings.extend(rhai_warning(ScriptWarningKind::UnreachableCode, next));
+        }
+    }
+    warnings
+}
+
+fn rhai_warning(kind: ScriptWarningKind, position: Position) -> Option<ScriptWarning> {
+    position_location(position).map(|location| ScriptWarning { kind, location })
+}
+
+/// Whether the node is a loop that never ends by itself.
+fn rhai_loop(node: ASTNode) -> Option<bool> {
+    match node {
+        ASTNode::Stmt(Stmt::While(flow, _)) => Some(matches!(
+            flow.expr,
+            Expr::Unit(..) | Expr::BoolConstant(true, _)
+        )),
+        ASTNode::Stmt(Stmt::Do(..) | Stmt::For(..)) => Some(false),
+        ASTNode::Expr(Expr::Custom(custom, _)) => match custom.tokens.first()?.as_str() {
+            "forever" => Some(true),
+            "repeat" | "while_touched" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Name and position of a function call or custom syntax keyword, and whether it is a call.
+/// Operators are calls in the AST too, but never commands.
+fn rhai_command(node: ASTNode<'_>) -> Option<(&str, Position, bool)> {
+    match node {
+        ASTNode::Stmt(Stmt::FnCall(call, position))
+        | ASTNode::Expr(Expr::FnCall(call, position))
+            if call.op_token.is_none()
+                && call
+                    .name
+                    .starts_with(|c: char| c.is_alphabetic() || c == '_') =>
+        {
+            Some((call.name.as_str(), *position, true))
+        }
+        ASTNode::Expr(Expr::Custom(custom, position)) => {
+            Some((custom.tokens.first()?.as_str(), *position, false))
+        }
+        _ => None,
+    }
+}
+
+/// A direction written out in the script: `move("up")`, `move up` or `dig("left")`.
+fn rhai_direction(node: ASTNode<'_>) -> Option<(&str, Position)> {
+    match node {
+        ASTNode::Stmt(Stmt::FnCall(call, _)) | ASTNode::Expr(Expr::FnCall(call, _)) => {
+            match call.args.first()? {
+                Expr::StringConstant(direction, position) => Some((direction.as_str(), *position)),
+                _ => None,
+            }
+        }
+        ASTNode::Expr(Expr::Custom(custom, _)) => {
+            let bare = matches!(
+                custom.inputs.get(1),
+                Some(Expr::StringConstant(marker, _)) if marker.as_str() == MOVE_BARE_MARKER
+            );
+            match custom.inputs.first()? {
+                Expr::StringConstant(direction, position) => Some((direction.as_str(), *position)),
+                Expr::Variable(variable, _, position) if bare => {
+                    Some((variable.1.as_str(), *position))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Position of the statement after the loop at the end of `path`, if it has one.
+fn next_statement(ast: &AST, path: &[ASTNode]) -> Option<Position> {
+    let mut depth = path.len() - 1;
+    // Custom syntax loops are expressions wrapped in a statement.
+    if matches!(path[depth], ASTNode::Expr(Expr::Custom(..))) {
+        depth = depth.checked_sub(1)?;
+    }
+    let ASTNode::Stmt(statement) = path[depth] else {
+        return None;
+    };
+    let blocks = match depth.checked_sub(1) {
+        Some(parent) => statement_blocks(path[parent]),
+        None => vec![ast.statements()],
+    };
+    blocks.into_iter().find_map(|block| {
+        let index = block
+            .iter()
+            .position(|item| std::ptr::eq(item, statement))?;
+        block[index + 1..]
+            .iter()
+            .find(|item| !matches!(item, Stmt::Noop(..)))
+            .map(Stmt::position)
+    })
+}
+
+/// Statement lists directly inside `node`.
+fn statement_blocks(node: ASTNode<'_>) -> Vec<&[Stmt]> {
+    match node {
+        ASTNode::Stmt(Stmt::If(flow, _) | Stmt::TryCatch(flow, _)) => {
+            vec![flow.body.statements(), flow.branch.statements()]
+        }
+        ASTNode::Stmt(Stmt::While(flow, _) | Stmt::Do(flow, ..)) => vec![flow.body.statements()],
+        ASTNode::Stmt(Stmt::For(for_loop, _)) => vec![for_loop.2.body.statements()],
+        ASTNode::Stmt(Stmt::Block(block)) | ASTNode::Expr(Expr::Stmt(block)) => {
+            vec![block.statements()]
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn lint_keystone(source: &str, allowed: Option<&HashSet<String>>) -> Vec<ScriptWarning> {
+    let Ok(tokens) = tokenize(source, Language::Keystone, true) else {
+        return Vec::new();
+    };
+
+    let mut warnings = Vec::new();
+    check_loops(&tokens, 0, tokens.len(), &mut warnings);
+    check_directions(&tokens, &mut warnings);
+    if let Some(allowed) = allowed {
+        check_commands(&tokens, allowed, &mut warnings);
+    }
+    warnings
+}
+
+fn warning(kind: ScriptWarningKind, token: &Spanned) -> ScriptWarning {
+    ScriptWarning {
+        kind,
+        location: SourceLocation::line(token.line).with_column(token.column),
+    }
+}
+
+fn ident(token: &Spanned) -> Option<&str> {
+    match &token.token {
+        Token::Ident(name) => Some(name),
+        _ => None,
+    }
+}
+
+fn is_symbol(token: Option<&Spanned>, symbol: &'static str) -> bool {
+    token.is_some_and(|token| token.token == Token::Symbol(symbol))
+}
+
+/// Index of the `}` closing the `{` at `open`, or the end of the tokens if it is missing.
+fn closing_brace(tokens: &[Spanned], open: usize) -> usize {
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate().skip(open) {
+        match token.token {
+            Token::Symbol("{") => depth += 1,
+            Token::Symbol("}") => {
+                depth -= 1;
+                if depth == 0 {
+                    return index;
+                }
+            }
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+/// Looks for busy and endless Keystone loops among the statements of `tokens[start..end]`.
+fn check_loops(tokens: &[Spanned], start: usize, end: usize, warnings: &mut Vec<ScriptWarning>) {
+    let mut index = start;
+    let mut unreachable_reported = false;
+    while index < end {
+        let token = &tokens[index];
+        let is_endless = ident(token) == Some("loop") && is_symbol(tokens.get(index + 1), "{");
+        if !is_endless {
+            if token.token == Token::Symbol("{") {
+                let close = closing_brace(tokens, index);
+                check_loops(tokens, index + 1, close.min(end), warnings);
+                index = close + 1;
+            } else {
+                index += 1;
+            }
+            continue;
+        }
+
+        let close = closing_brace(tokens, index + 1);
+        let body = &tokens[index + 2..close.min(end)];
+        if !yields(body) {
+            warnings.push(warning(ScriptWarningKind::BusyLoop, token));
+        }
+        check_loops(tokens, index + 2, close.min(end), warnings);
+        index = close + 1;
+
+        let exits = body
+            .iter()
+            .any(|token| ident(token).is_some_and(|name| LOOP_EXITS.contains(&name)));
+        if exits || unreachable_reported {
+            continue;
+        }
+        let next = tokens[index.min(end)..end]
+            .iter()
+            .find(|token| token.token != Token::Newline);
+        if let Some(next) = next
+            && next.token != Token::Symbol("}")
+        {
+            warnings.push(warning(ScriptWarningKind::UnreachableCode, next));
+            unreachable_reported = true;
+        }
+    }
+}
+
+fn yields(body: &[Spanned]) -> bool {
+    body.iter().enumerate().any(|(index, token)| {
+        ident(token).is_some_and(|name| {
+            YIELDING_COMMANDS.contains(&name)
+                || (is_symbol(body.get(index + 1), "(") && !INSTANT_CALLS.contains(&name))
+        })
+    })
+}
+
+/// Direction literals given to `move`, `dig` and `is_empty`.
+fn check_directions(tokens: &[Spanned], warnings: &mut Vec<ScriptWarning>) {
+    for (index, token) in tokens.iter().enumerate() {
+        if !matches!(ident(token), Some("move" | "dig" | "is_empty")) {
+            continue;
+        }
+        let argument = if is_symbol(tokens.get(index + 1), "(") {
+            index + 2
+        } else {
+            index + 1
+        };
+        if let Some(argument) = tokens.get(argument)
+            && let Token::Str(direction) = &argument.token
+            && MoveDirection::from_str(direction).is_none()
+        {
+            let kind = ScriptWarningKind::UnknownDirection {
+                direction: direction.clone(),
+            };
+            warnings.push(warning(kind, argument));
+        }
+    }
+}
+
+/// First use of each command or sensor outside the stone's capabilities.
+fn check_commands(
+    tokens: &[Spanned],
+    allowed: &HashSet<String>,
+    warnings: &mut Vec<ScriptWarning>,
+) {
+    // Variables may share a name with a sensor, such as `x`.
+    let variables = tokens
+        .windows(2)
+        .filter(|pair| ident(&pair[0]) == Some("let"))
+        .filter_map(|pair| ident(&pair[1]))
+        .collect::<HashSet<_>>();
+    let mut reported = HashSet::new();
+    for token in tokens {
+        let Some(name) = ident(token) else {
+            continue;
+        };
+        if variables.contains(name) {
+            continue;
+        }
+        let Some((_, capability)) = COMMAND_CAPABILITIES
+            .iter()
+            .find(|(command, _)| *command == name)
+        else {
+            continue;
+        };
+        if !allowed.contains(*capability) && reported.insert(name) {
+            let kind = ScriptWarningKind::MissingCommand {
+                command: name.to_string(),
+            };
+            warnings.push(warning(kind, token));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str, language: Language) -> Vec<(usize, ScriptWarningKind)> {
+        lint(source, language, None)
+            .into_iter()
+            .map(|warning| (warning.location.line, warning.kind))
+            .collect()
+    }
+
+    #[test]
+    fn endless_loops_are_flagged() {
+        let rhai = "loop {\n    if is_touched() { print(\"hi\"); }\n}\nmove(\"up\");\nfn walk() { move_left(); }";
+        assert_eq!(
+            kinds(rhai, Language::Rhai),
+            vec![
+                (1, ScriptWarningKind::BusyLoop),
+                (4, ScriptWarningKind::UnreachableCode),
+            ]
+        );
+
+        let keystone = "loop {\n    if is_touched {\n        move up\n    }\n}\n";
+        assert!(kinds(keystone, Language::Keystone).is_empty());
+        assert!(
+            kinds(
+                "loop { walk(); if x { break; } }\nsleep(1);",
+                Language::Rhai
+            )
+            .is_empty()
+        );
+        assert!(
+            kinds(
+                "forever { move up; if is_touched() { break; } }\nsleep(1);",
+                Language::Rhai
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn repeat_bodies_and_bare_moves_are_checked() {
+        let allowed: HashSet<String> = ["move", "sleep"].map(String::from).into();
+        let warnings = lint(
+            "repeat 3 {\n    forever { x(); }\n    dig(\"down\");\n}\nmove north;\nlet x = 1;",
+            Language::Rhai,
+            Some(&allowed),
+        )
+        .into_iter()
+        .map(|warning| (warning.location.line, warning.kind))
+        .collect::<Vec<_>>();
+        assert_eq!(
+            warnings,
+            vec![
+                (2, ScriptWarningKind::BusyLoop),
+                (
+                    2,
+                    ScriptWarningKind::MissingCommand {
+                        command: "x".to_string()
+                    }
+                ),
+                (
+                    3,
+                    ScriptWarningKind::MissingCommand {
+                        command: "dig".to_string()
+                    }
+                ),
+                (3, ScriptWarningKind::UnreachableCode),
+                (
+                    5,
+                    ScriptWarningKind::UnknownDirection {
+                        direction: "north".to_string()
+                    }
+                ),
+            ]
+        );
+
+        // A Keystone variable may be called `x` without needing the position sensor.
+        let keystone = "let x = 0\nloop {\n    x = x + 1\n    move up\n}\n";
+        assert!(lint(keystone, Language::Keystone, Some(&allowed)).is_empty());
+    }
+
+    #[test]
+    fn directions_and_capabilities_are_checked() {
+        let allowed: HashSet<String> = ["move", "sleep"].map(String::from).into();
+        let warnings = lint(
+            "move(\"north\");\nif is_touched() { dig(\"left\"); dig(\"down\"); }",
+            Language::Rhai,
+            Some(&allowed),
+        );
+        let kinds = warnings
+            .iter()
+            .map(|warning| {
+                (
+                    warning.location.line,
+                    warning.location.column,
+                    &warning.kind,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                (
+                    1,
+                    Some(6),
+                    &ScriptWarningKind::UnknownDirection {
+                        direction: "north".to_string()
+                    }
+                ),
+                (
+                    2,
+                    Some(4),
+                    &ScriptWarningKind::MissingCommand {
+                        command: "is_touched".to_string()
+                    }
+                ),
+                (
+                    2,
+                    Some(19),
+                    &ScriptWarningKind::MissingCommand {
+                        command: "dig".to_string()
+                    }
+                ),
+            ]
+        );
+    }
+}
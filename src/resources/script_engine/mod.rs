@@ -1,16 +1,30 @@
+mod blocks;
 mod keystone_executor;
+mod lint;
 mod rhai_executor;
+mod translator;
 
 use bevy::prelude::Resource;
 use serde::{Deserialize, Serialize};
 
+pub use blocks::{Block, BlockPath, BlockProgram};
 pub use keystone_executor::KeystoneScriptExecutor;
+pub use lint::{ScriptWarning, ScriptWarningKind, lint};
 pub use rhai_executor::RhaiScriptExecutor;
+pub use translator::translate;
+pub(crate) use translator::{Token, tokenize};
 
 use crate::util::script_types::{
-    ScriptCommand, ScriptExecutionError, ScriptProgram, ScriptRunner, ScriptStepper,
+    SandboxProfile, ScriptCommand, ScriptExecutionError, ScriptProgram, ScriptRunner, ScriptStepper,
 };
 
+fn command_limit_error(limit: usize) -> ScriptExecutionError {
+    ScriptExecutionError::Engine(format!(
+        "Too many commands emitted (>{}). Add yields/sleeps or reduce loop counts.",
+        limit
+    ))
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub enum Language {
     Rhai,
@@ -58,10 +72,11 @@ impl ScriptExecutor {
         language: Language,
         source: &str,
         allowed_commands: Option<&std::collections::HashSet<String>>,
+        sandbox: &SandboxProfile,
     ) -> Result<Vec<ScriptCommand>, ScriptExecutionError> {
         match language {
-            Language::Rhai => self.runner.run(source, allowed_commands),
-            Language::Keystone => self.ks_runner.run(source, allowed_commands),
+            Language::Rhai => self.runner.run(source, allowed_commands, sandbox),
+            Language::Keystone => self.ks_runner.run(source, allowed_commands, sandbox),
         }
     }
 
@@ -70,10 +85,29 @@ impl ScriptExecutor {
         language: Language,
         source: &str,
         allowed_commands: Option<&std::collections::HashSet<String>>,
+        sandbox: &SandboxProfile,
+        rand_seed: u64,
     ) -> Result<Box<dyn ScriptProgram>, ScriptExecutionError> {
         match language {
-            Language::Rhai => self.stepper.compile_step(source, allowed_commands),
-            Language::Keystone => self.ks_stepper.compile_step(source, allowed_commands),
+            Language::Rhai => {
+                self.stepper
+                    .compile_step(source, allowed_commands, sandbox, rand_seed)
+            }
+            Language::Keystone => {
+                self.ks_stepper
+                    .compile_step(source, allowed_commands, sandbox, rand_seed)
+            }
         }
     }
+
+    /// Programs compiled for one run share their signals, so start each run with none pending.
+    pub fn clear_signals(&self) {
+        self.stepper.clear_signals();
+        self.ks_stepper.clear_signals();
+    }
+
+    pub fn set_inspection(&self, enabled: bool) {
+        self.stepper.set_inspection(enabled);
+        self.ks_stepper.set_inspection(enabled);
+    }
 }
@@ -1,8 +1,18 @@
+use super::command_limit_error;
 use crate::util::script_types::{
-    MoveDirection, PLAYER_TOUCHED_STATE_KEY, ScriptCommand, ScriptExecutionError, ScriptProgram,
-    ScriptRunner, ScriptState, ScriptStateValue, ScriptStepper,
+    DIGS_LEFT_STATE_KEY, GOAL_DX_STATE_KEY, GOAL_DY_STATE_KEY, MoveDirection,
+    PLAYER_TOUCHED_STATE_KEY, POSITION_X_STATE_KEY, POSITION_Y_STATE_KEY, RAND_STATE_KEY,
+    SandboxProfile, ScriptCommand, ScriptExecutionError, ScriptProgram, ScriptRunner, ScriptState,
+    ScriptStateValue, ScriptStepper, SourceLocation,
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rhai::{
+    AST, ASTNode, Dynamic, Engine, EvalAltResult, EvalContext, Expr, Expression,
+    FLOAT as RhaiFloat, INT as RhaiInt, NativeCallContext, OptimizationLevel, ParseError,
+    ParseErrorType, Position, Scope, Stmt,
+    debugger::{DebuggerCommand, DebuggerEvent},
 };
-use rhai::{Dynamic, Engine, EvalAltResult, FLOAT as RhaiFloat, INT as RhaiInt, Position};
 use std::{
     collections::HashSet,
     sync::{
@@ -15,27 +25,41 @@ use std::{
 };
 
 /// Rhai-based implementation of the `ScriptRunner` boundary.
-pub struct RhaiScriptExecutor;
+pub struct RhaiScriptExecutor {
+    /// Signal set shared by every program this executor compiles.
+    shared_signals: Arc<Mutex<HashSet<String>>>,
+    /// Whether new programs snapshot their variables; off keeps the debugger hook out of runs.
+    inspect: AtomicBool,
+}
 
 impl RhaiScriptExecutor {
     pub fn new() -> Self {
-        Self
+        Self::with_signals(Arc::new(Mutex::new(HashSet::new())))
+    }
+
+    pub fn with_signals(shared_signals: Arc<Mutex<HashSet<String>>>) -> Self {
+        Self {
+            shared_signals,
+            inspect: AtomicBool::new(false),
+        }
     }
 
     fn parse_commands(
         &self,
         source: &str,
         allowed_commands: Option<&HashSet<String>>,
+        sandbox: &SandboxProfile,
     ) -> Result<Vec<ScriptCommand>, ScriptExecutionError> {
-        let script = source.trim();
+        let script = source.trim_end();
 
-        let emitter = CommandEmitter::recorder(MAX_COMMANDS);
+        let emitter = CommandEmitter::recorder(sandbox.max_commands);
         let state = SharedScriptState::default();
-        let mut engine = base_engine(Some(MAX_OPS as u64));
+        let mut engine = base_engine(Some(sandbox.max_operations as u64), sandbox);
         register_commands(&mut engine, emitter.clone(), state, allowed_commands);
 
+        let ast = compile_script(&engine, script).map_err(|err| map_engine_error(err.into()))?;
         let _ = engine
-            .eval::<Dynamic>(script)
+            .eval_ast::<Dynamic>(&ast)
             .map_err(|err| map_engine_error(*err))?;
 
         drop(engine);
@@ -48,14 +72,16 @@ impl RhaiScriptExecutor {
         &self,
         source: &str,
         allowed_commands: Option<&HashSet<String>>,
+        sandbox: &SandboxProfile,
     ) -> Result<(), ScriptExecutionError> {
-        let script = source.trim();
-        let emitter = CommandEmitter::recorder(PREFLIGHT_MAX_COMMANDS);
+        let script = source.trim_end();
+        let emitter = CommandEmitter::recorder(PREFLIGHT_MAX_COMMANDS.min(sandbox.max_commands));
         let state = SharedScriptState::default();
-        let mut engine = base_engine(Some(PREFLIGHT_MAX_OPS as u64));
+        let mut engine = base_engine(Some(sandbox.max_operations as u64), sandbox);
         register_commands(&mut engine, emitter.clone(), state, allowed_commands);
 
-        match engine.eval::<Dynamic>(script) {
+        let ast = compile_script(&engine, script).map_err(|err| map_engine_error(err.into()))?;
+        match engine.eval_ast::<Dynamic>(&ast) {
             Ok(_) => Ok(()),
             Err(err) => match *err {
                 EvalAltResult::ErrorTooManyOperations(..) => Ok(()),
@@ -81,8 +107,9 @@ impl ScriptRunner for RhaiScriptExecutor {
         &self,
         source: &str,
         allowed_commands: Option<&HashSet<String>>,
+        sandbox: &SandboxProfile,
     ) -> Result<Vec<ScriptCommand>, ScriptExecutionError> {
-        self.parse_commands(source, allowed_commands)
+        self.parse_commands(source, allowed_commands, sandbox)
     }
 }
 
@@ -91,13 +118,28 @@ impl ScriptStepper for RhaiScriptExecutor {
         &self,
         source: &str,
         allowed_commands: Option<&HashSet<String>>,
+        sandbox: &SandboxProfile,
+        rand_seed: u64,
     ) -> Result<Box<dyn ScriptProgram>, ScriptExecutionError> {
-        self.preflight(source, allowed_commands)?;
+        self.preflight(source, allowed_commands, sandbox)?;
         Ok(Box::new(RhaiScriptProgram::spawn(
-            source.trim().to_string(),
+            source.trim_end().to_string(),
             allowed_commands.cloned(),
+            SharedScriptState::new(self.shared_signals.clone(), rand_seed),
+            sandbox,
+            self.inspect.load(Ordering::Relaxed),
         )?))
     }
+
+    fn clear_signals(&self) {
+        if let Ok(mut signals) = self.shared_signals.lock() {
+            signals.clear();
+        }
+    }
+
+    fn set_inspection(&self, enabled: bool) {
+        self.inspect.store(enabled, Ordering::Relaxed);
+    }
 }
 
 #[derive(Clone)]
@@ -108,18 +150,74 @@ const INVALID_DIG_PREFIX: &str = "__invalid_dig__:";
 const INVALID_SLEEP_PREFIX: &str = "__invalid_sleep__:";
 const COMMAND_LIMIT_PREFIX: &str = "__command_limit__:";
 const STOP_REQUEST_TOKEN: &str = "__stop_requested__";
+const MOVE_CALL_MARKER: &str = "$$call";
+pub(super) const MOVE_BARE_MARKER: &str = "$$bare";
 
 #[derive(Clone)]
 struct CommandEmitter {
     target: CommandEmitterTarget,
+    /// Call site of the command being emitted, for the debugger.
+    location: Option<SourceLocation>,
 }
 
-#[derive(Clone, Default)]
+/// Sensor values for the script plus the signal set it talks through.
+/// The default has its own signals, so buffered runs never consume those of live programs.
+#[derive(Clone)]
 struct SharedScriptState {
     inner: Arc<Mutex<ScriptState>>,
+    signals: Arc<Mutex<HashSet<String>>>,
+    /// Source of `rand()`, owned by the program so its draws only depend on the seed.
+    rng: Arc<Mutex<ChaCha8Rng>>,
+    /// Value `rand()` returned last, for the inspector.
+    last_rand: Arc<Mutex<Option<RhaiFloat>>>,
+}
+
+impl Default for SharedScriptState {
+    fn default() -> Self {
+        Self::new(Arc::default(), 0)
+    }
 }
 
 impl SharedScriptState {
+    fn new(signals: Arc<Mutex<HashSet<String>>>, rand_seed: u64) -> Self {
+        Self {
+            inner: Arc::default(),
+            signals,
+            rng: Arc::new(Mutex::new(ChaCha8Rng::seed_from_u64(rand_seed))),
+            last_rand: Arc::default(),
+        }
+    }
+
+    /// Draws the next value in `[0, 1)`.
+    fn rand(&self) -> RhaiFloat {
+        let value = self
+            .rng
+            .lock()
+            .map(|mut rng| rng.random::<RhaiFloat>())
+            .unwrap_or(0.0);
+        if let Ok(mut last) = self.last_rand.lock() {
+            *last = Some(value);
+        }
+        value
+    }
+
+    fn last_rand(&self) -> Option<RhaiFloat> {
+        self.last_rand.lock().ok().and_then(|last| *last)
+    }
+
+    fn send_signal(&self, channel: &str) {
+        if let Ok(mut signals) = self.signals.lock() {
+            signals.insert(channel.to_owned());
+        }
+    }
+
+    /// Consumes the signal, so each send wakes a single receiver.
+    fn receive_signal(&self, channel: &str) -> bool {
+        self.signals
+            .lock()
+            .is_ok_and(|mut signals| signals.remove(channel))
+    }
+
     fn write(&self, state: &ScriptState) {
         if let Ok(mut inner) = self.inner.lock() {
             *inner = state.clone();
@@ -137,6 +235,63 @@ impl SharedScriptState {
             })
             .unwrap_or(false)
     }
+
+    fn value(&self, key: &str) -> Option<ScriptStateValue> {
+        self.inner
+            .lock()
+            .ok()
+            .and_then(|state| state.get(key).cloned())
+    }
+
+    /// Grid values are whole numbers; floats from older sensors are rounded.
+    fn int(&self, key: &str) -> Option<RhaiInt> {
+        self.value(key).and_then(|value| {
+            value
+                .as_int()
+                .or_else(|| value.as_float().map(|value| value.round() as i64))
+        })
+    }
+
+    fn goal_direction(&self) -> String {
+        let dx = self.int(GOAL_DX_STATE_KEY).unwrap_or(0);
+        let dy = self.int(GOAL_DY_STATE_KEY).unwrap_or(0);
+        let direction = if dx == 0 && dy == 0 {
+            ""
+        } else if dx.abs() >= dy.abs() {
+            if dx > 0 { "right" } else { "left" }
+        } else if dy > 0 {
+            "up"
+        } else {
+            "down"
+        };
+        direction.to_string()
+    }
+}
+
+impl From<ScriptStateValue> for Dynamic {
+    fn from(value: ScriptStateValue) -> Self {
+        match value {
+            ScriptStateValue::Bool(value) => Dynamic::from_bool(value),
+            ScriptStateValue::Float(value) => Dynamic::from_float(value as RhaiFloat),
+            ScriptStateValue::Int(value) => Dynamic::from_int(value),
+            ScriptStateValue::String(value) => Dynamic::from(value),
+            // Scripts name directions the way `move` and `goal_direction` do.
+            ScriptStateValue::Direction(direction) => Dynamic::from(direction_name(direction)),
+            // `Dynamic::from` would wrap the value as an opaque custom type, so go through `Into`.
+            ScriptStateValue::List(values) => {
+                Dynamic::from_array(values.into_iter().map(Into::into).collect())
+            }
+        }
+    }
+}
+
+fn direction_name(direction: MoveDirection) -> &'static str {
+    match direction {
+        MoveDirection::Left => "left",
+        MoveDirection::Top => "up",
+        MoveDirection::Right => "right",
+        MoveDirection::Down => "down",
+    }
 }
 
 #[derive(Clone)]
@@ -149,11 +304,12 @@ impl CommandEmitter {
     fn recorder(max: usize) -> Self {
         Self {
             target: CommandEmitterTarget::Recorder(CommandRecorder::with_limit(max)),
+            location: None,
         }
     }
 
     fn stream(
-        sender: SyncSender<ScriptCommand>,
+        sender: SyncSender<StreamedCommand>,
         stop_flag: Arc<AtomicBool>,
         resume: Arc<Mutex<Receiver<()>>>,
     ) -> Self {
@@ -163,13 +319,22 @@ impl CommandEmitter {
                 stop_flag,
                 resume,
             }),
+            location: None,
+        }
+    }
+
+    /// Copy of this emitter that tags commands with the given call site.
+    fn at(&self, position: Position) -> Self {
+        Self {
+            target: self.target.clone(),
+            location: position_location(position),
         }
     }
 
     fn emit(&self, command: ScriptCommand) -> Result<(), Box<EvalAltResult>> {
         match &self.target {
             CommandEmitterTarget::Recorder(recorder) => recorder.push(command),
-            CommandEmitterTarget::Stream(stream) => stream.send(command),
+            CommandEmitterTarget::Stream(stream) => stream.send((command, self.location)),
         }
     }
 
@@ -223,15 +388,17 @@ impl CommandRecorder {
     }
 }
 
+type StreamedCommand = (ScriptCommand, Option<SourceLocation>);
+
 #[derive(Clone)]
 struct CommandStream {
-    sender: SyncSender<ScriptCommand>,
+    sender: SyncSender<StreamedCommand>,
     stop_flag: Arc<AtomicBool>,
     resume: Arc<Mutex<Receiver<()>>>,
 }
 
 impl CommandStream {
-    fn send(&self, command: ScriptCommand) -> Result<(), Box<EvalAltResult>> {
+    fn send(&self, command: StreamedCommand) -> Result<(), Box<EvalAltResult>> {
         if self.stop_flag.load(Ordering::Relaxed) {
             return Err(Box::new(EvalAltResult::ErrorRuntime(
                 STOP_REQUEST_TOKEN.into(),
@@ -281,18 +448,176 @@ fn wait_for_resume(
     }
 }
 
-fn base_engine(max_operations: Option<u64>) -> Engine {
+fn base_engine(max_operations: Option<u64>, sandbox: &SandboxProfile) -> Engine {
     let mut engine = Engine::new();
     if let Some(max_ops) = max_operations {
         engine.set_max_operations(max_ops);
     }
-    engine.set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH);
-    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_expr_depths(sandbox.max_expr_depth, sandbox.max_expr_depth);
+    engine.set_max_call_levels(sandbox.max_call_levels);
+    // Scripts run on a background thread; keep a runaway one from exhausting memory.
+    engine.set_max_string_size(sandbox.max_string_size);
+    engine.set_max_array_size(sandbox.max_array_size);
+    engine.set_max_map_size(sandbox.max_map_size);
+    engine.set_max_variables(sandbox.max_variables);
+    // Only running programs show output; validation runs stay quiet.
+    engine.on_print(|_| {});
+    engine.on_debug(|_, _, _| {});
+    register_beginner_syntax(&mut engine);
     engine
 }
 
-fn streaming_engine(stop_flag: &Arc<AtomicBool>) -> Engine {
-    let mut engine = base_engine(None);
+/// Engine that parses the game's syntax but runs nothing, for checks that read the AST as written.
+pub(super) fn syntax_engine() -> Engine {
+    let mut engine = base_engine(None, &SandboxProfile::default());
+    engine.set_optimization_level(OptimizationLevel::None);
+    engine
+}
+
+/// `repeat N { }`, `forever { }`, `while_touched { }` and `move up`, so early stages read like
+/// Keystone. `move("up")` keeps working through the same syntax.
+fn register_beginner_syntax(engine: &mut Engine) {
+    // Rhai only parses its own `break` and `continue` inside native loops. As custom syntax they
+    // raise the same signal wherever they are, and `compile_script` rejects strays.
+    for (keyword, is_break) in [("break", true), ("continue", false)] {
+        engine.disable_symbol(keyword);
+        engine
+            .register_custom_syntax([keyword], false, move |_, _| {
+                Err(EvalAltResult::LoopBreak(is_break, Dynamic::UNIT, Position::NONE).into())
+            })
+            .expect("loop exit syntax should register");
+    }
+    engine
+        .register_custom_syntax(["repeat", "$expr$", "$block$"], false, |context, inputs| {
+            let count =
+                context
+                    .eval_expression_tree(&inputs[0])?
+                    .as_int()
+                    .map_err(|type_name| {
+                        EvalAltResult::ErrorMismatchDataType(
+                            "i64".to_string(),
+                            type_name.to_string(),
+                            inputs[0].position(),
+                        )
+                    })?;
+            for _ in 0..count {
+                if !loop_body(context, &inputs[1])? {
+                    break;
+                }
+            }
+            Ok(Dynamic::UNIT)
+        })
+        .expect("repeat syntax should register");
+    engine
+        .register_custom_syntax(["forever", "$block$"], false, |context, inputs| {
+            while loop_body(context, &inputs[0])? {}
+            Ok(Dynamic::UNIT)
+        })
+        .expect("forever syntax should register");
+    engine
+        .register_custom_syntax(["while_touched", "$block$"], false, |context, inputs| {
+            while context.call_native_fn::<bool>("is_touched", ())?
+                && loop_body(context, &inputs[0])?
+            {}
+            Ok(Dynamic::UNIT)
+        })
+        .expect("while_touched syntax should register");
+
+    // `move` followed by `(` takes an expression, anything else a bare direction. The variant
+    // marker ends parsing and records where `move` was written.
+    engine.register_custom_syntax_with_state_raw(
+        "move",
+        |symbols, look_ahead, _| {
+            Ok(match symbols.len() {
+                1 if look_ahead == "(" => Some("$expr$".into()),
+                1 => Some("$ident$".into()),
+                2 if symbols[1].as_str() == "$expr$" => Some(MOVE_CALL_MARKER.into()),
+                2 => Some(MOVE_BARE_MARKER.into()),
+                _ => None,
+            })
+        },
+        false,
+        |context, inputs, _| {
+            let call_site = inputs[1].position();
+            let direction = match inputs[1].get_string_value() {
+                Some(MOVE_BARE_MARKER) => inputs[0]
+                    .get_string_value()
+                    .map(|name| Dynamic::from(name.to_owned()))
+                    .unwrap_or_default(),
+                _ => context.eval_expression_tree(&inputs[0])?,
+            };
+
+            let previous_tag = std::mem::replace(context.tag_mut(), Dynamic::from(call_site));
+            let result = context.call_native_fn::<Dynamic>("move", (direction,));
+            *context.tag_mut() = previous_tag;
+            result.map_err(|mut err| {
+                if err.position().is_none() {
+                    err.set_position(call_site);
+                }
+                err
+            })
+        },
+    );
+}
+
+/// Runs one pass of a custom syntax loop; `false` once `break` leaves it.
+fn loop_body(context: &mut EvalContext, body: &Expression) -> Result<bool, Box<EvalAltResult>> {
+    match context.eval_expression_tree(body) {
+        Ok(_) => Ok(true),
+        Err(err) => match *err {
+            EvalAltResult::LoopBreak(is_break, ..) => Ok(!is_break),
+            _ => Err(err),
+        },
+    }
+}
+
+/// Compiles `script`, refusing `break` and `continue` outside a loop the way Rhai's parser does
+/// for its own; one reaching the top of a run would otherwise abort the engine.
+fn compile_script(engine: &Engine, script: &str) -> Result<AST, ParseError> {
+    let ast = engine.compile(script)?;
+    let mut stray = None;
+    ast.walk(&mut |path: &[ASTNode]| {
+        let (node, enclosing) = path.split_last().expect("walked nodes have a path");
+        let exits_loop = matches!(
+            node,
+            ASTNode::Expr(Expr::Custom(custom, _))
+                if custom.tokens.first().is_some_and(|token| token == "break" || token == "continue")
+        );
+        if exits_loop && !enclosing.iter().any(is_loop) {
+            stray = Some(node.position());
+        }
+        stray.is_none()
+    });
+    match stray {
+        Some(position) => Err(ParseError(Box::new(ParseErrorType::LoopBreak), position)),
+        None => Ok(ast),
+    }
+}
+
+fn is_loop(node: &ASTNode) -> bool {
+    match node {
+        ASTNode::Stmt(Stmt::While(..) | Stmt::Do(..) | Stmt::For(..)) => true,
+        ASTNode::Expr(Expr::Custom(custom, _)) => custom
+            .tokens
+            .first()
+            .is_some_and(|token| ["repeat", "forever", "while_touched"].contains(&token.as_str())),
+        _ => false,
+    }
+}
+
+/// Where a command was called from; calls made by custom syntax pass it through the tag.
+fn call_site(ctx: &NativeCallContext) -> Position {
+    let position = ctx.call_position();
+    if !position.is_none() {
+        return position;
+    }
+    ctx.tag()
+        .and_then(|tag| tag.clone().try_cast::<Position>())
+        .unwrap_or(position)
+}
+
+fn streaming_engine(stop_flag: &Arc<AtomicBool>, sandbox: &SandboxProfile) -> Engine {
+    let mut engine = base_engine(None, sandbox);
     let stop_flag = stop_flag.clone();
     engine.on_progress(move |_| {
         if stop_flag.load(Ordering::Relaxed) {
@@ -315,8 +640,8 @@ fn register_commands(
     {
         let emitter = emitter.clone();
         if allowed_commands.is_none_or(|s| s.contains("move")) {
-            engine.register_fn("move_left", move || {
-                record_move(&emitter, MoveDirection::Left)
+            engine.register_fn("move_left", move |ctx: NativeCallContext| {
+                record_move(&emitter.at(ctx.call_position()), MoveDirection::Left)
             });
         } else {
             engine.register_fn(
@@ -328,8 +653,8 @@ fn register_commands(
     {
         let emitter = emitter.clone();
         if allowed_commands.is_none_or(|s| s.contains("move")) {
-            engine.register_fn("move_right", move || {
-                record_move(&emitter, MoveDirection::Right)
+            engine.register_fn("move_right", move |ctx: NativeCallContext| {
+                record_move(&emitter.at(ctx.call_position()), MoveDirection::Right)
             });
         } else {
             engine.register_fn(
@@ -341,8 +666,8 @@ fn register_commands(
     {
         let emitter = emitter.clone();
         if allowed_commands.is_none_or(|s| s.contains("move")) {
-            engine.register_fn("move_top", move || {
-                record_move(&emitter, MoveDirection::Top)
+            engine.register_fn("move_top", move |ctx: NativeCallContext| {
+                record_move(&emitter.at(ctx.call_position()), MoveDirection::Top)
             });
         } else {
             engine.register_fn(
@@ -354,8 +679,8 @@ fn register_commands(
     {
         let emitter = emitter.clone();
         if allowed_commands.is_none_or(|s| s.contains("move")) {
-            engine.register_fn("move_down", move || {
-                record_move(&emitter, MoveDirection::Down)
+            engine.register_fn("move_down", move |ctx: NativeCallContext| {
+                record_move(&emitter.at(ctx.call_position()), MoveDirection::Down)
             });
         } else {
             engine.register_fn(
@@ -367,8 +692,8 @@ fn register_commands(
     {
         let emitter = emitter.clone();
         if allowed_commands.is_none_or(|s| s.contains("move")) {
-            engine.register_fn("move", move |direction: &str| {
-                move_named(direction, &emitter)
+            engine.register_fn("move", move |ctx: NativeCallContext, direction: &str| {
+                move_named(direction, &emitter.at(call_site(&ctx)))
             });
         } else {
             engine.register_fn(
@@ -380,9 +705,12 @@ fn register_commands(
     {
         let emitter = emitter.clone();
         if allowed_commands.is_none_or(|s| s.contains("sleep")) {
-            engine.register_fn("sleep", move |duration: RhaiFloat| {
-                sleep_for(duration, &emitter)
-            });
+            engine.register_fn(
+                "sleep",
+                move |ctx: NativeCallContext, duration: RhaiFloat| {
+                    sleep_for(duration, &emitter.at(ctx.call_position()))
+                },
+            );
         } else {
             engine.register_fn(
                 "sleep",
@@ -395,8 +723,8 @@ fn register_commands(
     {
         let emitter = emitter.clone();
         if allowed_commands.is_none_or(|s| s.contains("sleep")) {
-            engine.register_fn("sleep", move |duration: RhaiInt| {
-                sleep_for(duration as RhaiFloat, &emitter)
+            engine.register_fn("sleep", move |ctx: NativeCallContext, duration: RhaiInt| {
+                sleep_for(duration as RhaiFloat, &emitter.at(ctx.call_position()))
             });
         } else {
             engine.register_fn(
@@ -418,7 +746,9 @@ fn register_commands(
     {
         let emitter = emitter.clone();
         if allowed_commands.is_none_or(|s| s.contains("dig")) {
-            engine.register_fn("dig", move |direction: &str| dig_named(direction, &emitter));
+            engine.register_fn("dig", move |ctx: NativeCallContext, direction: &str| {
+                dig_named(direction, &emitter.at(ctx.call_position()))
+            });
         } else {
             engine.register_fn(
                 "dig",
@@ -442,6 +772,64 @@ fn register_commands(
             engine.register_fn("is_empty", move |_: &str| -> bool { false });
         }
     }
+    {
+        let state = state.clone();
+        if allowed_commands.is_none_or(|s| s.contains("rand")) {
+            engine.register_fn("rand", move || state.rand());
+        } else {
+            engine.register_fn("rand", move || -> RhaiFloat { 0.0 });
+        }
+    }
+    if allowed_commands.is_none_or(|s| s.contains("position")) {
+        let position_state = state.clone();
+        engine.register_fn("position", move || -> rhai::Array {
+            vec![
+                Dynamic::from_int(position_state.int(POSITION_X_STATE_KEY).unwrap_or(0)),
+                Dynamic::from_int(position_state.int(POSITION_Y_STATE_KEY).unwrap_or(0)),
+            ]
+        });
+        let x_state = state.clone();
+        engine.register_fn("x", move || x_state.int(POSITION_X_STATE_KEY).unwrap_or(0));
+        let y_state = state.clone();
+        engine.register_fn("y", move || y_state.int(POSITION_Y_STATE_KEY).unwrap_or(0));
+    } else {
+        engine.register_fn("position", move || -> rhai::Array {
+            vec![Dynamic::from_int(0), Dynamic::from_int(0)]
+        });
+        engine.register_fn("x", move || -> RhaiInt { 0 });
+        engine.register_fn("y", move || -> RhaiInt { 0 });
+    }
+    {
+        let state = state.clone();
+        if allowed_commands.is_none_or(|s| s.contains("digs_left")) {
+            engine.register_fn("digs_left", move || {
+                state.int(DIGS_LEFT_STATE_KEY).unwrap_or(-1)
+            });
+        } else {
+            engine.register_fn("digs_left", move || -> RhaiInt { 0 });
+        }
+    }
+    {
+        let state = state.clone();
+        if allowed_commands.is_none_or(|s| s.contains("goal_direction")) {
+            engine.register_fn("goal_direction", move || state.goal_direction());
+        } else {
+            engine.register_fn("goal_direction", move || -> String { String::new() });
+        }
+    }
+    if allowed_commands.is_none_or(|s| s.contains("signal")) {
+        let send_state = state.clone();
+        engine.register_fn("send_signal", move |channel: &str| {
+            send_state.send_signal(channel)
+        });
+        let receive_state = state;
+        engine.register_fn("receive_signal", move |channel: &str| {
+            receive_state.receive_signal(channel)
+        });
+    } else {
+        engine.register_fn("send_signal", move |_: &str| {});
+        engine.register_fn("receive_signal", move |_: &str| -> bool { false });
+    }
 }
 
 fn record_dig(
@@ -508,6 +896,34 @@ fn sleep_for(
 }
 
 fn map_engine_error(error: EvalAltResult) -> ScriptExecutionError {
+    // Errors raised inside script-defined functions are wrapped; report the inner cause.
+    let error = match error {
+        EvalAltResult::ErrorInFunctionCall(_, _, inner, _) => return map_engine_error(*inner),
+        other => other,
+    };
+    match source_location(&error) {
+        Some(location) => map_engine_error_kind(error).at(location),
+        None => map_engine_error_kind(error),
+    }
+}
+
+pub(super) fn position_location(position: Position) -> Option<SourceLocation> {
+    let location = SourceLocation::line(position.line()?);
+    Some(match position.position() {
+        Some(column) => location.with_column(column),
+        None => location,
+    })
+}
+
+fn source_location(error: &EvalAltResult) -> Option<SourceLocation> {
+    let mut location = position_location(error.position())?;
+    if let EvalAltResult::ErrorVariableNotFound(name, _) = error {
+        location = location.with_span(name.chars().count());
+    }
+    Some(location)
+}
+
+fn map_engine_error_kind(error: EvalAltResult) -> ScriptExecutionError {
     match error {
         EvalAltResult::ErrorRuntime(value, _) => {
             let message = value.to_string();
@@ -523,11 +939,11 @@ fn map_engine_error(error: EvalAltResult) -> ScriptExecutionError {
             } else if message.starts_with(INVALID_SLEEP_PREFIX) {
                 ScriptExecutionError::InvalidSleepDuration
             } else if let Some(limit) = message.strip_prefix(COMMAND_LIMIT_PREFIX) {
-                let limit = limit.parse::<usize>().unwrap_or(MAX_COMMANDS);
-                ScriptExecutionError::Engine(format!(
-                    "Too many commands emitted (>{}). Add yields/sleeps or reduce loop counts.",
+                command_limit_error(
                     limit
-                ))
+                        .parse::<usize>()
+                        .unwrap_or(SandboxProfile::default().max_commands),
+                )
             } else {
                 ScriptExecutionError::Engine(message)
             }
@@ -537,35 +953,134 @@ fn map_engine_error(error: EvalAltResult) -> ScriptExecutionError {
 }
 
 // --------- Limits & defaults ---------
-const MAX_OPS: usize = 100_000; // max Rhai VM operations per evaluation (buffered)
-const PREFLIGHT_MAX_OPS: usize = 100_000; // ops limit for quick validation
 const PREFLIGHT_MAX_COMMANDS: usize = 512; // cap preview commands to avoid long scans
-const MAX_EXPR_DEPTH: usize = 64; // max expression depth
-const MAX_CALL_LEVELS: usize = 32; // max call stack depth
-const MAX_COMMANDS: usize = 5_000; // cap recorded commands to prevent OOM
 const STREAM_CHANNEL_SIZE: usize = 1; // backpressure so scripts yield one step at a time
+const MAX_PENDING_OUTPUT: usize = 256; // printed lines kept until the console collects them
+
+// --------- Script output ---------
+/// Lines from `print` and `debug`, held until the console takes them.
+#[derive(Clone, Default)]
+struct ScriptOutput(Arc<Mutex<Vec<String>>>);
+
+impl ScriptOutput {
+    fn attach(&self, engine: &mut Engine) {
+        let output = self.clone();
+        engine.on_print(move |text| output.push(text.to_string()));
+        let output = self.clone();
+        engine.on_debug(move |text, _, position| {
+            let line = match position.line() {
+                Some(line) => format!("[line {line}] {text}"),
+                None => text.to_string(),
+            };
+            output.push(line);
+        });
+    }
+
+    fn push(&self, line: String) {
+        if let Ok(mut lines) = self.0.lock() {
+            // A print in a tight loop must not grow without bound.
+            if lines.len() >= MAX_PENDING_OUTPUT {
+                lines.remove(0);
+            }
+            lines.push(line);
+        }
+    }
+
+    fn take(&self) -> Vec<String> {
+        self.0
+            .lock()
+            .map(|mut lines| std::mem::take(&mut *lines))
+            .unwrap_or_default()
+    }
+}
+
+// --------- Variable inspection ---------
+/// Copies the script scope at the next statement after each `request`, so a
+/// running program can be inspected without stopping it.
+#[derive(Clone, Default)]
+struct ScopeSnapshot {
+    requested: Arc<AtomicBool>,
+    variables: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+impl ScopeSnapshot {
+    // `register_debugger` is flagged as volatile rather than actually deprecated.
+    #[allow(deprecated)]
+    fn attach(&self, engine: &mut Engine) {
+        let snapshot = self.clone();
+        engine.register_debugger(
+            |_, debugger| debugger,
+            move |context, event, _, _, _| {
+                // Function exit events see the callee's (empty) scope, not the script's.
+                if matches!(event, DebuggerEvent::Step)
+                    && snapshot.requested.swap(false, Ordering::Relaxed)
+                {
+                    snapshot.capture(context.scope());
+                }
+                Ok(DebuggerCommand::StepInto)
+            },
+        );
+    }
+
+    fn capture(&self, scope: &Scope) {
+        let mut variables: Vec<(String, String)> = Vec::new();
+        for (name, _, value) in scope.iter_raw() {
+            // Later entries shadow earlier ones with the same name.
+            variables.retain(|(existing, _)| existing != name);
+            variables.push((name.to_string(), value.to_string()));
+        }
+        if let Ok(mut slot) = self.variables.lock() {
+            *slot = variables;
+        }
+    }
+
+    fn request(&self) {
+        self.requested.store(true, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> Vec<(String, String)> {
+        self.variables
+            .lock()
+            .map(|variables| variables.clone())
+            .unwrap_or_default()
+    }
+}
 
 // --------- Step program implementation ---------
 struct RhaiScriptProgram {
-    receiver: Mutex<Receiver<ScriptCommand>>,
+    receiver: Mutex<Receiver<StreamedCommand>>,
     stop_flag: Arc<AtomicBool>,
     handle: Option<JoinHandle<()>>,
     resume_tx: SyncSender<()>,
     shared_state: SharedScriptState,
+    error: Arc<Mutex<Option<ScriptExecutionError>>>,
+    current_location: Option<SourceLocation>,
+    /// Only present when the program was compiled for inspection.
+    variables: Option<ScopeSnapshot>,
+    output: ScriptOutput,
 }
 
 impl RhaiScriptProgram {
     fn spawn(
         source: String,
         allowed_commands: Option<HashSet<String>>,
+        shared_state: SharedScriptState,
+        sandbox: &SandboxProfile,
+        inspect: bool,
     ) -> Result<Self, ScriptExecutionError> {
-        let (sender, receiver) = mpsc::sync_channel::<ScriptCommand>(STREAM_CHANNEL_SIZE);
+        let (sender, receiver) = mpsc::sync_channel::<StreamedCommand>(STREAM_CHANNEL_SIZE);
         let stop_flag = Arc::new(AtomicBool::new(false));
         let (resume_tx, resume_rx) = mpsc::sync_channel::<()>(1);
         let resume_rx = Arc::new(Mutex::new(resume_rx));
-        let shared_state = SharedScriptState::default();
+        let error = Arc::new(Mutex::new(None));
 
-        let mut engine = streaming_engine(&stop_flag);
+        let mut engine = streaming_engine(&stop_flag, sandbox);
+        let variables = inspect.then(ScopeSnapshot::default);
+        if let Some(variables) = &variables {
+            variables.attach(&mut engine);
+        }
+        let output = ScriptOutput::default();
+        output.attach(&mut engine);
         let emitter = CommandEmitter::stream(sender, stop_flag.clone(), resume_rx.clone());
         register_commands(
             &mut engine,
@@ -574,21 +1089,25 @@ impl RhaiScriptProgram {
             allowed_commands.as_ref(),
         );
 
-        let ast = engine
-            .compile(source.as_str())
-            .map_err(|err| ScriptExecutionError::Engine(err.to_string()))?;
+        let ast =
+            compile_script(&engine, source.as_str()).map_err(|err| map_engine_error(err.into()))?;
 
         let handle = std::thread::spawn({
             let resume = resume_rx.clone();
             let stop_flag = stop_flag.clone();
+            let error = error.clone();
             move || {
                 if wait_for_resume(&resume, &stop_flag).is_err() {
                     return;
                 }
 
                 let result = engine.eval_ast::<Dynamic>(&ast);
-                if let Err(err) = result {
-                    eprintln!("Script execution stopped: {}", map_engine_error(*err));
+                // Errors caused by stopping the program are not the script's fault.
+                if let Err(err) = result
+                    && !stop_flag.load(Ordering::SeqCst)
+                    && let Ok(mut slot) = error.lock()
+                {
+                    *slot = Some(map_engine_error(*err));
                 }
             }
         });
@@ -599,24 +1118,43 @@ impl RhaiScriptProgram {
             handle: Some(handle),
             resume_tx,
             shared_state,
+            error,
+            current_location: None,
+            variables,
+            output,
         })
     }
 
     fn stop_and_join(&mut self) {
         self.stop_flag.store(true, Ordering::SeqCst);
+        self.join();
+    }
+
+    fn join(&mut self) {
         if let Some(handle) = self.handle.take() {
             let _ = handle.join();
         }
     }
+
+    /// Reports the error that ended the script, once.
+    fn take_error(&mut self) -> Result<Option<ScriptCommand>, ScriptExecutionError> {
+        match self.error.lock().ok().and_then(|mut slot| slot.take()) {
+            Some(err) => Err(err),
+            None => Ok(None),
+        }
+    }
 }
 
 impl ScriptProgram for RhaiScriptProgram {
-    fn next(&mut self, state: &ScriptState) -> Option<ScriptCommand> {
+    fn next(&mut self, state: &ScriptState) -> Result<Option<ScriptCommand>, ScriptExecutionError> {
         self.shared_state.write(state);
+        if let Some(variables) = &self.variables {
+            variables.request();
+        }
 
         match self.resume_tx.try_send(()) {
             Ok(_) | Err(TrySendError::Full(_)) => {}
-            Err(TrySendError::Disconnected(_)) => return None,
+            Err(TrySendError::Disconnected(_)) => return self.take_error(),
         }
 
         let result = {
@@ -628,14 +1166,40 @@ impl ScriptProgram for RhaiScriptProgram {
         };
 
         match result {
-            Ok(command) => Some(command),
-            Err(RecvTimeoutError::Timeout) => None,
+            Ok((command, location)) => {
+                self.current_location = location;
+                Ok(Some(command))
+            }
+            Err(RecvTimeoutError::Timeout) => Ok(None),
             Err(RecvTimeoutError::Disconnected) => {
-                self.stop_and_join();
-                None
+                self.join();
+                self.take_error()
             }
         }
     }
+
+    fn current_location(&self) -> Option<SourceLocation> {
+        self.current_location
+    }
+
+    fn variables(&self) -> Option<Vec<(String, String)>> {
+        self.variables.as_ref().map(ScopeSnapshot::get)
+    }
+
+    fn sensor_values(&self) -> ScriptState {
+        self.shared_state
+            .last_rand()
+            .map(|value| {
+                let value = ScriptStateValue::Float(value as f32);
+                (RAND_STATE_KEY.to_string(), value)
+            })
+            .into_iter()
+            .collect()
+    }
+
+    fn take_output(&mut self) -> Vec<String> {
+        self.output.take()
+    }
 }
 
 impl Drop for RhaiScriptProgram {
@@ -647,14 +1211,471 @@ impl Drop for RhaiScriptProgram {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::util::script_types::MoveDirection;
+    use crate::util::script_types::{MoveDirection, stone_rand_seed};
     use std::thread;
 
+    #[test]
+    fn errors_report_source_line() {
+        let executor = RhaiScriptExecutor::new();
+
+        let syntax = executor
+            .compile_step(
+                "\nmove_left();\nlet = 3;",
+                None,
+                &SandboxProfile::default(),
+                0,
+            )
+            .err()
+            .expect("syntax error expected");
+        assert_eq!(syntax.location().map(|l| l.line), Some(3));
+
+        let runtime = executor
+            .compile_step(
+                "move_left();\nfn walk() {\n  move(\"sideways\");\n}\nwalk();",
+                None,
+                &SandboxProfile::default(),
+                0,
+            )
+            .err()
+            .expect("runtime error expected");
+        assert_eq!(runtime.location().map(|l| l.line), Some(3));
+        assert!(matches!(
+            runtime.kind(),
+            ScriptExecutionError::InvalidMoveDirection { .. }
+        ));
+    }
+
+    #[test]
+    fn sandbox_limits_memory_and_operations() {
+        let executor = RhaiScriptExecutor::new();
+        let sandbox: SandboxProfile = ron::de::from_str("(max_string_size: 16, max_array_size: 4)")
+            .expect("profile should parse");
+        assert_eq!(sandbox.max_commands, SandboxProfile::default().max_commands);
+
+        let grow_string = "let s = \"\"; loop { s += \"meow\"; move_left(); }";
+        assert!(
+            executor
+                .compile_step(grow_string, None, &sandbox, 0)
+                .is_err()
+        );
+        let grow_array = "let a = []; for i in 0..10 { a.push(i); }";
+        assert!(
+            executor
+                .compile_step(grow_array, None, &sandbox, 0)
+                .is_err()
+        );
+        assert!(
+            executor
+                .compile_step(grow_array, None, &SandboxProfile::default(), 0)
+                .is_ok()
+        );
+
+        // Buffered runs use the stage profile too.
+        let three_moves = "move_left(); move_left(); move_left();";
+        let few_commands = SandboxProfile {
+            max_commands: 2,
+            ..SandboxProfile::default()
+        };
+        assert!(executor.run(three_moves, None, &few_commands).is_err());
+        assert!(executor.run(grow_array, None, &sandbox).is_err());
+        assert!(
+            executor
+                .run(three_moves, None, &SandboxProfile::default())
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn runtime_errors_are_reported_by_next() {
+        let executor = RhaiScriptExecutor::new();
+        let mut program = executor
+            .compile_step(
+                r#"move_left(); if is_touched() { move("sideways"); }"#,
+                None,
+                &SandboxProfile::default(),
+                0,
+            )
+            .expect("script should compile");
+
+        let mut touched_state = ScriptState::default();
+        touched_state.insert(
+            PLAYER_TOUCHED_STATE_KEY.to_string(),
+            ScriptStateValue::Bool(true),
+        );
+
+        let mut error = None;
+        for _ in 0..50 {
+            match program.next(&touched_state) {
+                Ok(_) => thread::sleep(Duration::from_millis(2)),
+                Err(err) => {
+                    error = Some(err);
+                    break;
+                }
+            }
+        }
+        assert!(matches!(
+            error.as_ref().map(ScriptExecutionError::kind),
+            Some(ScriptExecutionError::InvalidMoveDirection { .. })
+        ));
+    }
+
+    #[test]
+    fn commands_carry_their_call_site() {
+        let executor = RhaiScriptExecutor::new();
+        let mut program = executor
+            .compile_step(
+                "move_left();\n\n  sleep(1);",
+                None,
+                &SandboxProfile::default(),
+                0,
+            )
+            .expect("script should compile");
+
+        let state = ScriptState::default();
+        let mut lines = Vec::new();
+        for _ in 0..50 {
+            if program.next(&state).expect("script should run").is_some() {
+                lines.push(program.current_location().map(|l| (l.line, l.column)));
+            }
+            if lines.len() == 2 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        assert_eq!(lines, vec![Some((1, Some(1))), Some((3, Some(3)))]);
+    }
+
+    #[test]
+    fn beginner_syntax_runs_like_plain_rhai() {
+        let executor = RhaiScriptExecutor::new();
+        let mut program = executor
+            .compile_step(
+                "repeat 2 {\n  move up\n}\nwhile_touched { sleep(1); }\nforever { move(\"left\"); }",
+                None,
+                &SandboxProfile::default(),
+                0,
+            )
+            .expect("script should compile");
+
+        let state = ScriptState::default();
+        let mut steps = Vec::new();
+        for _ in 0..50 {
+            if let Some(ScriptCommand::Move(direction)) =
+                program.next(&state).expect("script should run")
+            {
+                let location = program.current_location().map(|l| (l.line, l.column));
+                steps.push((direction, location));
+            }
+            if steps.len() == 3 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        assert_eq!(
+            steps,
+            vec![
+                (MoveDirection::Top, Some((2, Some(3)))),
+                (MoveDirection::Top, Some((2, Some(3)))),
+                (MoveDirection::Left, Some((5, Some(11)))),
+            ]
+        );
+
+        let error = executor
+            .compile_step("move sideways", None, &SandboxProfile::default(), 0)
+            .err()
+            .expect("unknown direction expected");
+        assert!(matches!(
+            error.kind(),
+            ScriptExecutionError::InvalidMoveDirection { .. }
+        ));
+        assert_eq!(
+            error.location().map(|l| (l.line, l.column)),
+            Some((1, Some(1)))
+        );
+    }
+
+    #[test]
+    fn break_and_continue_leave_beginner_loops() {
+        let executor = RhaiScriptExecutor::new();
+        let commands = executor
+            .run(
+                "let n = 0;\nrepeat 5 {\n  n += 1;\n  if n == 2 { continue; }\n  if n == 4 { break; }\n  move_left();\n}\nforever { move up; break; }\nloop { break; }",
+                None,
+                &SandboxProfile::default(),
+            )
+            .expect("script should run");
+        assert!(matches!(
+            commands.as_slice(),
+            [
+                ScriptCommand::Move(MoveDirection::Left),
+                ScriptCommand::Move(MoveDirection::Left),
+                ScriptCommand::Move(MoveDirection::Top),
+            ]
+        ));
+
+        let error = executor
+            .compile_step(
+                "move up;\nif true { break; }",
+                None,
+                &SandboxProfile::default(),
+                0,
+            )
+            .err()
+            .expect("break outside a loop should not compile");
+        assert_eq!(error.location().map(|l| l.line), Some(2));
+    }
+
+    #[test]
+    fn variables_are_snapshotted_while_running() {
+        let executor = RhaiScriptExecutor::new();
+        let source = "let steps = 3;\nloop { steps += 1; move_left(); }";
+        let uninspected = executor
+            .compile_step(source, None, &SandboxProfile::default(), 0)
+            .expect("script should compile");
+        assert!(uninspected.variables().is_none());
+
+        executor.set_inspection(true);
+        let mut program = executor
+            .compile_step(source, None, &SandboxProfile::default(), 0)
+            .expect("script should compile");
+
+        let state = ScriptState::default();
+        let mut steps = None;
+        for _ in 0..50 {
+            program.next(&state).expect("script should run");
+            steps = program
+                .variables()
+                .and_then(|vars| vars.into_iter().find(|(name, _)| name == "steps"));
+            if steps.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        let (_, value) = steps.expect("steps should be visible");
+        assert!(value.parse::<i64>().is_ok_and(|steps| steps >= 3));
+    }
+
+    #[test]
+    fn programs_report_their_last_rand_draw() {
+        let executor = RhaiScriptExecutor::new();
+        let mut program = executor
+            .compile_step(
+                "loop { let roll = rand(); move_left(); }",
+                None,
+                &SandboxProfile::default(),
+                5,
+            )
+            .expect("script should compile");
+        assert!(program.sensor_values().is_empty());
+
+        let state = ScriptState::default();
+        for _ in 0..50 {
+            if program.next(&state).expect("script should run").is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        let roll = program
+            .sensor_values()
+            .get(RAND_STATE_KEY)
+            .and_then(ScriptStateValue::as_float);
+        assert!(roll.is_some_and(|roll| (0.0..1.0).contains(&roll)));
+    }
+
+    #[test]
+    fn sensors_read_script_state_within_capabilities() {
+        let executor = RhaiScriptExecutor::new();
+        let allowed: HashSet<String> = ["move", "position", "digs_left", "goal_direction"]
+            .map(String::from)
+            .into();
+        let mut program = executor
+            .compile_step(
+                r#"loop {
+                    if x() == 2 && position()[1] == -1 && digs_left() == 3
+                        && goal_direction() == "left" && rand() == 0.0 {
+                        move_left();
+                    }
+                }"#,
+                Some(&allowed),
+                &SandboxProfile::default(),
+                0,
+            )
+            .expect("script should compile");
+
+        let mut state = ScriptState::default();
+        for (key, value) in [
+            (POSITION_X_STATE_KEY, 2.0),
+            (POSITION_Y_STATE_KEY, -1.0),
+            (DIGS_LEFT_STATE_KEY, 3.0),
+            (GOAL_DX_STATE_KEY, -4.0),
+            (GOAL_DY_STATE_KEY, 1.0),
+        ] {
+            state.insert(key.to_string(), ScriptStateValue::Float(value));
+        }
+
+        let mut command = None;
+        for _ in 0..50 {
+            command = program.next(&state).expect("script should run");
+            if command.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        assert!(matches!(
+            command,
+            Some(ScriptCommand::Move(MoveDirection::Left))
+        ));
+    }
+
+    #[test]
+    fn print_and_debug_reach_program_output() {
+        let executor = RhaiScriptExecutor::new();
+        let mut program = executor
+            .compile_step(
+                "print(\"hello\");\ndebug(42);\nmove_left();",
+                None,
+                &SandboxProfile::default(),
+                0,
+            )
+            .expect("script should compile");
+
+        let state = ScriptState::default();
+        for _ in 0..50 {
+            if program.next(&state).expect("script should run").is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        assert_eq!(program.take_output(), ["hello", "[line 2] 42"]);
+        assert!(program.take_output().is_empty());
+    }
+
+    #[test]
+    fn state_values_convert_to_rhai_values() {
+        let value = ScriptStateValue::from(vec![
+            ScriptStateValue::Int(3),
+            ScriptStateValue::Direction(MoveDirection::Top),
+            ScriptStateValue::from("sand"),
+            ScriptStateValue::Bool(true),
+        ]);
+        let dynamic: Dynamic = value.into();
+        let array = dynamic.into_array().expect("a list becomes an array");
+
+        assert_eq!(array[0].as_int(), Ok(3));
+        assert_eq!(array[1].clone().into_string().as_deref(), Ok("up"));
+        assert_eq!(array[2].clone().into_string().as_deref(), Ok("sand"));
+        assert_eq!(array[3].as_bool(), Ok(true));
+    }
+
+    #[test]
+    fn signals_pass_between_programs_of_one_executor() {
+        let executor = RhaiScriptExecutor::new();
+        let allowed: HashSet<String> = ["move", "signal"].map(String::from).into();
+        let mut sender = executor
+            .compile_step(
+                r#"send_signal("go"); move_down();"#,
+                Some(&allowed),
+                &SandboxProfile::default(),
+                0,
+            )
+            .expect("sender should compile");
+        let mut receiver = executor
+            .compile_step(
+                r#"loop { if receive_signal("go") { move_left(); } }"#,
+                Some(&allowed),
+                &SandboxProfile::default(),
+                0,
+            )
+            .expect("receiver should compile");
+        // Without the capability the signal is never seen, and it is left for `receiver`.
+        let mut blocked = executor
+            .compile_step(
+                r#"loop { if receive_signal("go") { move_right(); } }"#,
+                Some(&["move".to_string()].into()),
+                &SandboxProfile::default(),
+                0,
+            )
+            .expect("blocked receiver should compile");
+
+        let state = ScriptState::default();
+        let mut received = None;
+        for _ in 0..50 {
+            sender.next(&state).expect("sender should run");
+            assert!(blocked.next(&state).expect("should run").is_none());
+            received = receiver.next(&state).expect("receiver should run");
+            if received.is_some() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        assert!(matches!(
+            received,
+            Some(ScriptCommand::Move(MoveDirection::Left))
+        ));
+
+        // Each send is consumed by a single receive.
+        for _ in 0..5 {
+            thread::sleep(Duration::from_millis(2));
+            assert!(receiver.next(&state).expect("should run").is_none());
+        }
+    }
+
+    #[test]
+    fn each_stone_of_a_run_steps_its_own_program() {
+        let executor = RhaiScriptExecutor::new();
+        let sources = ["move_left();", "move_right();", r#"dig("down");"#];
+        let mut programs = sources
+            .iter()
+            .enumerate()
+            .map(|(stone, source)| {
+                executor
+                    .compile_step(
+                        source,
+                        None,
+                        &SandboxProfile::default(),
+                        stone_rand_seed(7, stone),
+                    )
+                    .expect("every stone should compile")
+            })
+            .collect::<Vec<_>>();
+
+        let state = ScriptState::default();
+        let mut commands = vec![None; programs.len()];
+        for _ in 0..50 {
+            for (stone, program) in programs.iter_mut().enumerate() {
+                if let Some(command) = program.next(&state).expect("stone should run") {
+                    commands[stone].get_or_insert(command);
+                }
+            }
+            if commands.iter().all(Option::is_some) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        assert!(matches!(
+            commands.as_slice(),
+            [
+                Some(ScriptCommand::Move(MoveDirection::Left)),
+                Some(ScriptCommand::Move(MoveDirection::Right)),
+                Some(ScriptCommand::Dig(MoveDirection::Down)),
+            ]
+        ));
+
+        // Stone 0 keeps the run seed, so single-stone replays are unchanged.
+        assert_eq!(stone_rand_seed(7, 0), 7);
+        assert_ne!(stone_rand_seed(7, 1), stone_rand_seed(7, 2));
+    }
+
     #[test]
     fn touched_reflects_latest_state_between_steps() {
         let executor = RhaiScriptExecutor::new();
         let mut program = executor
-            .compile_step(r#"loop { if is_touched() { move_down(); } }"#, None)
+            .compile_step(
+                r#"loop { if is_touched() { move_down(); } }"#,
+                None,
+                &SandboxProfile::default(),
+                0,
+            )
             .expect("script should compile");
 
         let mut touched_state = ScriptState::default();
@@ -664,7 +1685,7 @@ mod tests {
         );
 
         // First tick should see `touched = true` and emit a move command.
-        let command = program.next(&touched_state);
+        let command = program.next(&touched_state).expect("script should run");
         match command {
             Some(ScriptCommand::Move(MoveDirection::Down)) => {}
             other => panic!("expected move down, got {other:?}"),
@@ -680,11 +1701,44 @@ mod tests {
         // Allow the worker thread to run a few frames; it must not produce more moves.
         for _ in 0..5 {
             thread::sleep(Duration::from_millis(2));
-            let next = program.next(&untouched_state);
+            let next = program.next(&untouched_state).expect("script should run");
             assert!(
                 next.is_none(),
                 "touched=false should yield no commands, got {next:?}"
             );
         }
     }
+
+    #[test]
+    fn same_seed_replays_the_same_rand_sequence() {
+        let executor = RhaiScriptExecutor::new();
+        let moves = |rand_seed| {
+            let mut program = executor
+                .compile_step(
+                    "loop { if rand() < 0.5 { move_left(); } else { move_right(); } }",
+                    None,
+                    &SandboxProfile::default(),
+                    rand_seed,
+                )
+                .expect("script should compile");
+            let state = ScriptState::default();
+            let mut moves = Vec::new();
+            for _ in 0..200 {
+                if let Some(ScriptCommand::Move(direction)) =
+                    program.next(&state).expect("script should run")
+                {
+                    moves.push(direction);
+                }
+                if moves.len() == 16 {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(2));
+            }
+            assert_eq!(moves.len(), 16);
+            moves
+        };
+
+        assert_eq!(moves(7), moves(7));
+        assert_ne!(moves(7), moves(8));
+    }
 }
@@ -0,0 +1,665 @@
+//! Source-to-source conversion between Rhai and Keystone.
+//!
+//! Covers the subset both languages share in the game: move/dig/sleep, `loop`, `if`/`else`,
+//! `is_touched`, `is_empty`, and `let` variables with arithmetic, comparisons and logic.
+//! `//` comments are kept on their own line before the statement that follows them.
+
+use std::fmt::Write;
+
+use super::Language;
+use crate::util::script_types::{MoveDirection, ScriptExecutionError, SourceLocation};
+
+const INDENT: &str = "    ";
+
+// Longest first so `<=` is not read as `<` followed by `=`.
+const SYMBOLS: [&str; 25] = [
+    "&&", "||", "==", "!=", "<=", ">=", "+=", "-=", "*=", "/=", "{", "}", "(", ")", ";", ",", "=",
+    "<", ">", "+", "-", "*", "/", "%", "!",
+];
+
+/// Converts `source` written in `from` into equivalent `to` source.
+pub fn translate(
+    source: &str,
+    from: Language,
+    to: Language,
+) -> Result<String, ScriptExecutionError> {
+    let mut comments = Vec::new();
+    let tokens = scan(source, from, false, Some(&mut comments))?;
+    comments.reverse();
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        from,
+        comments,
+    };
+    let statements = parser.statements(false)?;
+
+    let mut out = String::new();
+    write_statements(&mut out, &statements, to, 0);
+    Ok(out)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token {
+    Ident(String),
+    Number(String),
+    Str(String),
+    Symbol(&'static str),
+    Newline,
+}
+
+pub(crate) struct Spanned {
+    pub(crate) token: Token,
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CommandKind {
+    Move,
+    Dig,
+    Sleep,
+}
+
+#[derive(Debug)]
+enum Stmt {
+    Command(CommandKind, Expr),
+    Loop(Vec<Stmt>),
+    If(Expr, Vec<Stmt>, Option<Vec<Stmt>>),
+    Let(String, Expr),
+    Assign(String, Expr),
+    /// Text after `//`.
+    Comment(String),
+}
+
+#[derive(Debug)]
+enum Expr {
+    Number(String),
+    Bool(bool),
+    Dir(MoveDirection),
+    Var(String),
+    Call(&'static str, Vec<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Group(Box<Expr>),
+}
+
+fn unsupported(message: String, line: usize, column: usize) -> ScriptExecutionError {
+    ScriptExecutionError::Engine(message).at(SourceLocation::line(line).with_column(column))
+}
+
+/// Splits `source` into tokens. `lenient` skips characters outside the shared subset instead of
+/// failing, for callers that only look for a few constructs.
+pub(crate) fn tokenize(
+    source: &str,
+    from: Language,
+    lenient: bool,
+) -> Result<Vec<Spanned>, ScriptExecutionError> {
+    scan(source, from, lenient, None)
+}
+
+/// `tokenize`, also collecting each comment with the index of the token it comes before.
+fn scan(
+    source: &str,
+    from: Language,
+    lenient: bool,
+    mut comments: Option<&mut Vec<(usize, String)>>,
+) -> Result<Vec<Spanned>, ScriptExecutionError> {
+    let mut tokens = Vec::new();
+    let chars = source.chars().collect::<Vec<_>>();
+    let (mut i, mut line, mut line_start) = (0, 1, 0);
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i - line_start + 1;
+        let mut push = |token| {
+            tokens.push(Spanned {
+                token,
+                line,
+                column,
+            })
+        };
+
+        if c == '\n' {
+            // Rhai statements end with `;`, Keystone statements with a line break.
+            if from == Language::Keystone {
+                push(Token::Newline);
+            }
+            i += 1;
+            line += 1;
+            line_start = i;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            let start = i + 2;
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            if let Some(comments) = comments.as_deref_mut() {
+                let text = chars[start.min(i)..i].iter().collect::<String>();
+                comments.push((tokens.len(), text.trim_end().to_string()));
+            }
+        } else if c == '"' {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && chars[i] != '"' && chars[i] != '\n' {
+                i += 1;
+            }
+            if chars.get(i) != Some(&'"') {
+                return Err(unsupported(
+                    "Unterminated string.".to_string(),
+                    line,
+                    column,
+                ));
+            }
+            push(Token::Str(chars[start..i].iter().collect()));
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            push(Token::Number(chars[start..i].iter().collect()));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            push(Token::Ident(chars[start..i].iter().collect()));
+        } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| {
+            symbol
+                .chars()
+                .enumerate()
+                .all(|(offset, expected)| chars.get(i + offset) == Some(&expected))
+        }) {
+            push(Token::Symbol(symbol));
+            i += symbol.len();
+        } else if lenient {
+            i += 1;
+        } else {
+            return Err(unsupported(
+                format!("Character '{c}' cannot be converted."),
+                line,
+                column,
+            ));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Spanned>,
+    pos: usize,
+    from: Language,
+    /// Comments not yet placed, last one first, with the index of the token they precede.
+    comments: Vec<(usize, String)>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|spanned| &spanned.token)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn error(&self, message: String) -> ScriptExecutionError {
+        match self.tokens.get(self.pos).or(self.tokens.last()) {
+            Some(spanned) => unsupported(message, spanned.line, spanned.column),
+            None => ScriptExecutionError::Engine(message),
+        }
+    }
+
+    fn eat(&mut self, symbol: &'static str) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), ScriptExecutionError> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(format!("Expected '{symbol}'.")))
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while matches!(self.peek(), Some(Token::Newline | Token::Symbol(";"))) {
+            self.pos += 1;
+        }
+    }
+
+    /// Comments written before the current token, including any inside the last statement.
+    fn take_comments(&mut self, statements: &mut Vec<Stmt>) {
+        while let Some((_, text)) = self.comments.pop_if(|(index, _)| *index <= self.pos) {
+            statements.push(Stmt::Comment(text));
+        }
+    }
+
+    fn statements(&mut self, in_block: bool) -> Result<Vec<Stmt>, ScriptExecutionError> {
+        let mut statements = Vec::new();
+        loop {
+            self.skip_separators();
+            self.take_comments(&mut statements);
+            match self.peek() {
+                None if in_block => return Err(self.error("Expected '}'.".to_string())),
+                None => return Ok(statements),
+                Some(Token::Symbol("}")) if in_block => {
+                    self.pos += 1;
+                    return Ok(statements);
+                }
+                _ => statements.push(self.statement()?),
+            }
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, ScriptExecutionError> {
+        self.expect("{")?;
+        self.statements(true)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ScriptExecutionError> {
+        let Some(Token::Ident(name)) = self.peek().cloned() else {
+            return Err(self.error("Expected a command.".to_string()));
+        };
+        self.pos += 1;
+
+        match name.as_str() {
+            "loop" => Ok(Stmt::Loop(self.block()?)),
+            "if" => self.if_statement(),
+            "let" => {
+                let Some(Token::Ident(variable)) = self.advance() else {
+                    return Err(self.error("Expected a variable name.".to_string()));
+                };
+                self.expect("=")?;
+                Ok(Stmt::Let(variable, self.expr()?))
+            }
+            "move" => Ok(Stmt::Command(CommandKind::Move, self.command_argument()?)),
+            "dig" => Ok(Stmt::Command(CommandKind::Dig, self.command_argument()?)),
+            "sleep" => Ok(Stmt::Command(CommandKind::Sleep, self.command_argument()?)),
+            "move_left" | "move_right" | "move_top" | "move_down"
+                if self.from == Language::Rhai =>
+            {
+                self.expect("(")?;
+                self.expect(")")?;
+                let direction =
+                    MoveDirection::from_str(&name["move_".len()..]).expect("suffix is a direction");
+                Ok(Stmt::Command(CommandKind::Move, Expr::Dir(direction)))
+            }
+            _ => {
+                for (compound, op) in [("+=", "+"), ("-=", "-"), ("*=", "*"), ("/=", "/")] {
+                    if self.eat(compound) {
+                        let operand = match self.expr()? {
+                            operand @ Expr::Binary(..) => Expr::Group(Box::new(operand)),
+                            operand => operand,
+                        };
+                        let value =
+                            Expr::Binary(op, Box::new(Expr::Var(name.clone())), Box::new(operand));
+                        return Ok(Stmt::Assign(name, value));
+                    }
+                }
+                if self.eat("=") {
+                    return Ok(Stmt::Assign(name, self.expr()?));
+                }
+                self.pos -= 1;
+                Err(self.error(format!("'{name}' cannot be converted.")))
+            }
+        }
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, ScriptExecutionError> {
+        let condition = self.expr()?;
+        let then = self.block()?;
+
+        // `else` may start on the line after the closing brace.
+        let resume = self.pos;
+        self.skip_separators();
+        if self.peek() != Some(&Token::Ident("else".to_string())) {
+            self.pos = resume;
+            return Ok(Stmt::If(condition, then, None));
+        }
+        self.pos += 1;
+
+        let otherwise = if self.peek() == Some(&Token::Ident("if".to_string())) {
+            self.pos += 1;
+            vec![self.if_statement()?]
+        } else {
+            self.block()?
+        };
+        Ok(Stmt::If(condition, then, Some(otherwise)))
+    }
+
+    /// Rhai passes arguments in parentheses; Keystone writes them after the command.
+    fn command_argument(&mut self) -> Result<Expr, ScriptExecutionError> {
+        if self.eat("(") {
+            let argument = self.expr()?;
+            self.expect(")")?;
+            Ok(argument)
+        } else {
+            self.expr()
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ScriptExecutionError> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, ScriptExecutionError> {
+        const LEVELS: [&[&str]; 5] = [
+            &["||"],
+            &["&&"],
+            &["==", "!=", "<=", ">=", "<", ">"],
+            &["+", "-"],
+            &["*", "/", "%"],
+        ];
+        let Some(operators) = LEVELS.get(level) else {
+            return self.unary();
+        };
+
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Symbol(symbol)) if operators.contains(symbol) => *symbol,
+                Some(Token::Ident(word)) if word == "or" && level == 0 => "||",
+                Some(Token::Ident(word)) if word == "and" && level == 1 => "&&",
+                _ => return Ok(left),
+            };
+            self.pos += 1;
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ScriptExecutionError> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.peek() == Some(&Token::Ident("not".to_string())) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, ScriptExecutionError> {
+        let token = self.peek().cloned();
+        match token {
+            Some(Token::Number(number)) => {
+                self.pos += 1;
+                Ok(Expr::Number(number))
+            }
+            Some(Token::Str(text)) => match MoveDirection::from_str(&text) {
+                Some(direction) => {
+                    self.pos += 1;
+                    Ok(Expr::Dir(direction))
+                }
+                None => Err(self.error(format!("Text \"{text}\" cannot be converted."))),
+            },
+            Some(Token::Symbol("(")) => {
+                self.pos += 1;
+                let inner = self.expr()?;
+                self.expect(")")?;
+                Ok(Expr::Group(Box::new(inner)))
+            }
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                self.identifier(name)
+            }
+            _ => Err(self.error("Expected a value.".to_string())),
+        }
+    }
+
+    fn identifier(&mut self, name: String) -> Result<Expr, ScriptExecutionError> {
+        match name.as_str() {
+            "true" => return Ok(Expr::Bool(true)),
+            "false" => return Ok(Expr::Bool(false)),
+            "is_touched" => {
+                // Keystone may query the sensor without parentheses.
+                if self.eat("(") {
+                    self.expect(")")?;
+                }
+                return Ok(Expr::Call("is_touched", Vec::new()));
+            }
+            "is_empty" => {
+                let argument = self.command_argument()?;
+                return Ok(Expr::Call("is_empty", vec![argument]));
+            }
+            _ => {}
+        }
+
+        if self.from == Language::Keystone
+            && let Some(direction) = MoveDirection::from_str(&name)
+        {
+            return Ok(Expr::Dir(direction));
+        }
+        if self.peek() == Some(&Token::Symbol("(")) {
+            self.pos -= 1;
+            return Err(self.error(format!("'{name}' cannot be converted.")));
+        }
+        Ok(Expr::Var(name))
+    }
+}
+
+fn write_statements(out: &mut String, statements: &[Stmt], to: Language, depth: usize) {
+    let indent = INDENT.repeat(depth);
+    let end = if to == Language::Rhai { ";" } else { "" };
+
+    for statement in statements {
+        match statement {
+            Stmt::Command(kind, argument) => {
+                let name = match kind {
+                    CommandKind::Move => "move",
+                    CommandKind::Dig => "dig",
+                    CommandKind::Sleep => "sleep",
+                };
+                let argument = expr_source(argument, to);
+                let _ = match to {
+                    Language::Rhai => writeln!(out, "{indent}{name}({argument});"),
+                    Language::Keystone => writeln!(out, "{indent}{name} {argument}"),
+                };
+            }
+            Stmt::Loop(body) => {
+                let _ = writeln!(out, "{indent}loop {{");
+                write_statements(out, body, to, depth + 1);
+                let _ = writeln!(out, "{indent}}}");
+            }
+            Stmt::If(condition, then, otherwise) => {
+                let _ = writeln!(out, "{indent}if {} {{", expr_source(condition, to));
+                write_statements(out, then, to, depth + 1);
+                match otherwise {
+                    Some(otherwise) => {
+                        let _ = writeln!(out, "{indent}}} else {{");
+                        write_statements(out, otherwise, to, depth + 1);
+                        let _ = writeln!(out, "{indent}}}");
+                    }
+                    None => {
+                        let _ = writeln!(out, "{indent}}}");
+                    }
+                }
+            }
+            Stmt::Let(name, value) => {
+                let _ = writeln!(out, "{indent}let {name} = {}{end}", expr_source(value, to));
+            }
+            Stmt::Assign(name, value) => {
+                let _ = writeln!(out, "{indent}{name} = {}{end}", expr_source(value, to));
+            }
+            Stmt::Comment(text) => {
+                let _ = writeln!(out, "{indent}//{text}");
+            }
+        }
+    }
+}
+
+fn expr_source(expr: &Expr, to: Language) -> String {
+    match expr {
+        Expr::Number(number) => number.clone(),
+        Expr::Bool(value) => value.to_string(),
+        Expr::Dir(direction) => {
+            let name = match direction {
+                MoveDirection::Left => "left",
+                MoveDirection::Top => "up",
+                MoveDirection::Right => "right",
+                MoveDirection::Down => "down",
+            };
+            match to {
+                Language::Rhai => format!("\"{name}\""),
+                Language::Keystone => name.to_string(),
+            }
+        }
+        Expr::Var(name) => name.clone(),
+        Expr::Call(name, arguments) => {
+            let arguments = arguments
+                .iter()
+                .map(|argument| expr_source(argument, to))
+                .collect::<Vec<_>>();
+            format!("{name}({})", arguments.join(", "))
+        }
+        Expr::Not(inner) => match to {
+            Language::Rhai => format!("!{}", expr_source(inner, to)),
+            Language::Keystone => format!("not {}", expr_source(inner, to)),
+        },
+        Expr::Neg(inner) => format!("-{}", expr_source(inner, to)),
+        Expr::Binary(op, left, right) => {
+            let op = match (*op, to) {
+                ("&&", Language::Keystone) => "and",
+                ("||", Language::Keystone) => "or",
+                (op, _) => op,
+            };
+            format!("{} {op} {}", expr_source(left, to), expr_source(right, to))
+        }
+        Expr::Group(inner) => format!("({})", expr_source(inner, to)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::script_engine::{KeystoneScriptExecutor, RhaiScriptExecutor};
+    use crate::util::script_types::{SandboxProfile, ScriptCommand, ScriptRunner};
+
+    const RHAI: &str = r#"let steps = 0;
+// walk until blocked
+loop {
+    if is_empty("up") && !is_touched() {
+        move("up");
+        steps += 1;
+    } else if steps > 2 {
+        dig("left");
+    } else {
+        move_right();
+        sleep(1.5);
+    }
+}
+"#;
+
+    const KEYSTONE: &str = "let steps = 0
+// walk until blocked
+loop {
+    if is_empty(up) and not is_touched() {
+        move up
+        steps = steps + 1
+    } else {
+        if steps > 2 {
+            dig left
+        } else {
+            move right
+            sleep 1.5
+        }
+    }
+}
+";
+
+    #[test]
+    fn rhai_converts_to_keystone_and_back() {
+        let keystone =
+            translate(RHAI, Language::Rhai, Language::Keystone).expect("Rhai should convert");
+        assert_eq!(keystone, KEYSTONE);
+
+        let rhai = translate(&keystone, Language::Keystone, Language::Rhai)
+            .expect("Keystone should convert");
+        assert!(rhai.contains("if is_empty(\"up\") && !is_touched() {"));
+        assert!(rhai.contains("        move(\"up\");\n        steps = steps + 1;"));
+    }
+
+    #[test]
+    fn converted_rhai_runs_in_keystone() {
+        let keystone = translate(
+            "let n = 0;\nif n < 1 {\n    move(\"down\");\n    n += 1;\n}\nsleep(2);\ndig(\"left\");\n",
+            Language::Rhai,
+            Language::Keystone,
+        )
+        .expect("Rhai should convert");
+        let commands = KeystoneScriptExecutor::default()
+            .run(&keystone, None, &SandboxProfile::default())
+            .expect("converted source should run");
+        assert!(matches!(
+            commands.as_slice(),
+            [
+                ScriptCommand::Move(MoveDirection::Down),
+                ScriptCommand::Sleep(_),
+                ScriptCommand::Dig(MoveDirection::Left)
+            ]
+        ));
+    }
+
+    #[test]
+    fn converted_keystone_runs_in_rhai() {
+        let rhai = translate(
+            "let n = 0\nif n < 1 {\n    move down\n    sleep 2\n}\n",
+            Language::Keystone,
+            Language::Rhai,
+        )
+        .expect("Keystone should convert");
+        let commands = RhaiScriptExecutor::new()
+            .run(&rhai, None, &SandboxProfile::default())
+            .expect("converted source should run");
+        assert!(matches!(
+            commands.as_slice(),
+            [
+                ScriptCommand::Move(MoveDirection::Down),
+                ScriptCommand::Sleep(_)
+            ]
+        ));
+    }
+
+    #[test]
+    fn comments_are_carried_over() {
+        let keystone = translate(
+            "// start\nloop {\n    move(\"up\"); // climb\n    // wait a little\n}\nsleep(1); // done\n",
+            Language::Rhai,
+            Language::Keystone,
+        )
+        .expect("Rhai should convert");
+        assert_eq!(
+            keystone,
+            "// start\nloop {\n    move up\n    // climb\n    // wait a little\n}\nsleep 1\n// done\n"
+        );
+
+        let rhai = translate(&keystone, Language::Keystone, Language::Rhai)
+            .expect("Keystone should convert");
+        assert!(rhai.contains("    move(\"up\");\n    // climb\n    // wait a little\n}"));
+    }
+
+    #[test]
+    fn unsupported_code_reports_its_line() {
+        let err = translate(
+            "move_left();\nfn walk() {}\n",
+            Language::Rhai,
+            Language::Keystone,
+        )
+        .expect_err("functions are outside the subset");
+        assert_eq!(err.location().map(|location| location.line), Some(2));
+    }
+}
@@ -12,6 +12,15 @@ fn clamp_unit(value: f32) -> f32 {
     value.clamp(0.0, 1.0)
 }
 
+/// How scripts are written in the stage editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum EditorMode {
+    #[default]
+    Text,
+    /// Drag-and-drop blocks that generate source for `script_language`.
+    Blocks,
+}
+
 #[derive(Resource, Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct GameSettings {
     master_volume: f32,
@@ -19,6 +28,8 @@ pub struct GameSettings {
     music_volume: f32,
     pub fullscreen: bool,
     pub script_language: Language,
+    #[serde(default)]
+    pub editor_mode: EditorMode,
     pub locale: Option<String>,
 }
 
@@ -30,6 +41,7 @@ impl Default for GameSettings {
             music_volume: 0.7,
             fullscreen: false,
             script_language: Language::Rhai,
+            editor_mode: EditorMode::Text,
             locale: None,
         }
     }
@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    resources::script_engine::{Language, Token, tokenize},
+    util::script_types::{ScriptCommand, ScriptExecutionError},
+};
+
+/// How a cleared run was solved, across the scripts of every stone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SolutionMetrics {
+    /// Lines holding code; blank and comment-only lines are not counted.
+    pub lines: usize,
+    pub tokens: usize,
+    /// `ScriptCommand`s the programs emitted.
+    pub commands: usize,
+    pub sleep_seconds: f32,
+    /// Digs the stones finished, which can be fewer than the dig commands emitted.
+    pub digs: u32,
+}
+
+impl SolutionMetrics {
+    /// Adds the size of one stone's script, counted with the translator's tokens.
+    /// A script the tokenizer cannot read is an error, so it is never measured as empty.
+    pub fn add_source(
+        &mut self,
+        source: &str,
+        language: Language,
+    ) -> Result<(), ScriptExecutionError> {
+        let tokens = tokenize(source, language, true)?;
+        let tokens = tokens
+            .iter()
+            .filter(|spanned| spanned.token != Token::Newline)
+            .collect::<Vec<_>>();
+        self.tokens += tokens.len();
+        self.lines += tokens
+            .iter()
+            .map(|spanned| spanned.line)
+            .collect::<HashSet<_>>()
+            .len();
+        Ok(())
+    }
+
+    pub fn record_command(&mut self, command: &ScriptCommand) {
+        self.commands += 1;
+        if let ScriptCommand::Sleep(seconds) = command {
+            self.sleep_seconds += seconds.max(0.0);
+        }
+    }
+
+    pub fn record_dig(&mut self) {
+        self.digs += 1;
+    }
+}
+
+/// Par values a stage declares next to `dig_limit`; a metric without a par is not rated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SolutionPar {
+    pub lines: Option<usize>,
+    pub tokens: Option<usize>,
+    pub commands: Option<usize>,
+    pub sleep_seconds: Option<f32>,
+    pub digs: Option<u32>,
+}
+
+impl SolutionPar {
+    /// 3 stars when every par is met, 2 when at least half are, 1 for any other clear.
+    /// Stages without any par are not rated.
+    pub fn rate(&self, metrics: &SolutionMetrics) -> Option<u8> {
+        let checks = [
+            self.lines.map(|par| metrics.lines <= par),
+            self.tokens.map(|par| metrics.tokens <= par),
+            self.commands.map(|par| metrics.commands <= par),
+            self.sleep_seconds.map(|par| metrics.sleep_seconds <= par),
+            self.digs.map(|par| metrics.digs <= par),
+        ];
+        let declared = checks.iter().flatten().count();
+        let met = checks.iter().flatten().filter(|met| **met).count();
+
+        if declared == 0 {
+            None
+        } else if met == declared {
+            Some(3)
+        } else if met * 2 >= declared {
+            Some(2)
+        } else {
+            Some(1)
+        }
+    }
+}
+
+/// Best clear of a stage, kept in `StageProgress`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SolutionRating {
+    /// `None` when the stage declares no par.
+    pub stars: Option<u8>,
+    pub metrics: SolutionMetrics,
+}
+
+impl SolutionRating {
+    /// More stars win; equal stars go to the run with fewer commands.
+    pub fn beats(&self, other: &SolutionRating) -> bool {
+        (self.stars, std::cmp::Reverse(self.metrics.commands))
+            > (other.stars, std::cmp::Reverse(other.metrics.commands))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::script_types::MoveDirection;
+
+    #[test]
+    fn source_size_skips_blank_and_comment_lines() {
+        let mut metrics = SolutionMetrics::default();
+        metrics
+            .add_source(
+                "// walk right\nmove(\"right\");\n\nif steps >= 2 { sleep(0.5); }\n",
+                Language::Rhai,
+            )
+            .expect("the script should tokenize");
+
+        assert_eq!(metrics.lines, 2);
+        // move ( "right" ) ; | if steps >= 2 { sleep ( 0.5 ) ; }
+        assert_eq!(metrics.tokens, 5 + 11);
+
+        let mut broken = SolutionMetrics::default();
+        assert!(
+            broken
+                .add_source("move(\"right);\n", Language::Rhai)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn stars_follow_met_par_values() {
+        let mut metrics = SolutionMetrics::default();
+        metrics.record_command(&ScriptCommand::Move(MoveDirection::Right));
+        metrics.record_command(&ScriptCommand::Sleep(1.5));
+        // Only finished digs count, not the dig commands.
+        metrics.record_command(&ScriptCommand::Dig(MoveDirection::Down));
+        assert_eq!(metrics.commands, 3);
+        assert_eq!(metrics.sleep_seconds, 1.5);
+        assert_eq!(metrics.digs, 0);
+
+        let par = SolutionPar {
+            commands: Some(3),
+            sleep_seconds: Some(1.0),
+            digs: Some(0),
+            ..SolutionPar::default()
+        };
+        assert_eq!(par.rate(&metrics), Some(2));
+        assert_eq!(SolutionPar::default().rate(&metrics), None);
+
+        let strict = SolutionPar {
+            commands: Some(1),
+            sleep_seconds: Some(1.0),
+            ..SolutionPar::default()
+        };
+        assert_eq!(strict.rate(&metrics), Some(1));
+
+        metrics.record_dig();
+        assert_eq!(par.rate(&metrics), Some(1));
+    }
+}
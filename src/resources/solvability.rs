@@ -0,0 +1,336 @@
+//! Grid-level check that the cat can reach the goal of a generated `Map`.
+//!
+//! The check is optimistic: a layout it rejects cannot be cleared, while one it accepts may still
+//! need a careful script. Digging is relaxed per tile; every `Solid` tile a digging stone can
+//! tunnel to within `dig_limit` counts as removable, but separate tunnels are not added up.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::resources::{
+    chunk_grammar_map::{Map, TileKind},
+    stone_type::StoneCapabilities,
+};
+
+/// Tiles the cat climbs in one jump; `PlayerMotion` jumps about one and a half tiles high.
+const JUMP_HEIGHT: isize = 1;
+/// Widest gap, in tiles, the cat jumps across.
+const JUMP_DISTANCE: isize = 2;
+
+type Cell = (isize, isize);
+
+const NEIGHBORS: [Cell; 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+struct Grid {
+    size: (isize, isize),
+    solid: HashSet<Cell>,
+    walls: HashSet<Cell>,
+    goals: HashSet<Cell>,
+    /// `Solid` tiles a digging stone can remove.
+    removable: HashSet<Cell>,
+    /// Cells a moving stone can be parked in, including the spawn of every stone.
+    stone_cells: HashSet<Cell>,
+}
+
+impl Grid {
+    fn new(map: &Map) -> Self {
+        let mut grid = Self {
+            size: map.map_size,
+            solid: HashSet::new(),
+            walls: HashSet::new(),
+            goals: HashSet::new(),
+            removable: HashSet::new(),
+            stone_cells: HashSet::new(),
+        };
+        for (cell, kind) in map.map_iter() {
+            match kind {
+                TileKind::Solid => {
+                    grid.solid.insert(cell);
+                }
+                TileKind::Wall => {
+                    grid.walls.insert(cell);
+                }
+                TileKind::Goal => {
+                    grid.goals.insert(cell);
+                }
+                // Obstacles vanish after a while, so they never block for good.
+                TileKind::PlayerSpawn | TileKind::Stone | TileKind::Obstacle => {}
+            }
+        }
+        grid
+    }
+
+    fn in_bounds(&self, (x, y): Cell) -> bool {
+        x >= 0 && y >= 0 && x < self.size.0 && y < self.size.1
+    }
+
+    fn is_blocked(&self, cell: Cell) -> bool {
+        !self.in_bounds(cell) || self.walls.contains(&cell) || self.solid.contains(&cell)
+    }
+
+    /// Empty, or a tile that can be dug away.
+    fn is_passable(&self, cell: Cell) -> bool {
+        self.in_bounds(cell)
+            && !self.walls.contains(&cell)
+            && (!self.solid.contains(&cell) || self.removable.contains(&cell))
+    }
+
+    /// The cat can stand in `cell` on a tile or a parked stone.
+    fn is_standable(&self, (x, y): Cell) -> bool {
+        let below = (x, y - 1);
+        self.is_passable((x, y)) && (self.is_blocked(below) || self.stone_cells.contains(&below))
+    }
+
+    fn touches_goal(&self, (x, y): Cell) -> bool {
+        self.goals.contains(&(x, y))
+            || NEIGHBORS
+                .iter()
+                .any(|(dx, dy)| self.goals.contains(&(x + dx, y + dy)))
+    }
+
+    /// Where the cat lands falling from `cell`, if it lands at all.
+    fn landing(&self, (x, mut y): Cell) -> Option<Cell> {
+        while self.is_passable((x, y)) {
+            if self.is_standable((x, y)) {
+                return Some((x, y));
+            }
+            y -= 1;
+        }
+        None
+    }
+}
+
+/// Whether the cat can reach a goal tile of `map`, helped by its stones.
+pub fn is_solvable(map: &Map, stones: &StoneCapabilities) -> bool {
+    let mut grid = Grid::new(map);
+    let Some(&spawn) = map.tile_positions(TileKind::PlayerSpawn).first() else {
+        return false;
+    };
+
+    let can = |index: usize, command: &str| {
+        stones
+            .get_capabilities(&map.stone_type_at(index))
+            .is_some_and(|commands| commands.contains(command))
+    };
+    let stone_spawns = map.tile_positions(TileKind::Stone);
+
+    if map.dig_limit != Some(0) {
+        let limit = map.dig_limit.map_or(usize::MAX, |limit| limit as usize);
+        for (index, &start) in stone_spawns.iter().enumerate() {
+            if can(index, "move") && can(index, "dig") {
+                mark_removable(&mut grid, start, limit);
+            }
+        }
+    }
+
+    for (index, &start) in stone_spawns.iter().enumerate() {
+        grid.stone_cells.insert(start);
+        if can(index, "move") {
+            let reachable = flood(start, |cell| grid.is_passable(cell));
+            grid.stone_cells.extend(reachable);
+        }
+    }
+
+    cat_reaches_goal(&grid, spawn)
+}
+
+/// Marks the `Solid` tiles a stone starting at `start` can dig through with `limit` digs.
+fn mark_removable(grid: &mut Grid, start: Cell, limit: usize) {
+    // 0-1 breadth-first search: entering an empty cell is free, digging into a tile costs one.
+    let mut digs = std::collections::HashMap::from([(start, 0usize)]);
+    let mut queue = VecDeque::from([(start, 0usize)]);
+    while let Some((cell, cost)) = queue.pop_front() {
+        if digs.get(&cell).is_some_and(|&best| best < cost) {
+            continue;
+        }
+        for (dx, dy) in NEIGHBORS {
+            let next = (cell.0 + dx, cell.1 + dy);
+            if !grid.in_bounds(next) || grid.walls.contains(&next) {
+                continue;
+            }
+            let step = usize::from(grid.solid.contains(&next));
+            let next_cost = cost + step;
+            if next_cost > limit || digs.get(&next).is_some_and(|&best| best <= next_cost) {
+                continue;
+            }
+            digs.insert(next, next_cost);
+            if step == 0 {
+                queue.push_front((next, next_cost));
+            } else {
+                grid.removable.insert(next);
+                queue.push_back((next, next_cost));
+            }
+        }
+    }
+}
+
+fn flood(start: Cell, passable: impl Fn(Cell) -> bool) -> HashSet<Cell> {
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    while let Some((x, y)) = queue.pop_front() {
+        for (dx, dy) in NEIGHBORS {
+            let next = (x + dx, y + dy);
+            if passable(next) && seen.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    seen
+}
+
+fn cat_reaches_goal(grid: &Grid, spawn: Cell) -> bool {
+    let Some(start) = grid.landing(spawn) else {
+        return grid.touches_goal(spawn);
+    };
+    let mut seen = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(cell) = queue.pop_front() {
+        if grid.touches_goal(cell) {
+            return true;
+        }
+        for next in cat_moves(grid, cell) {
+            if grid.touches_goal(next) {
+                return true;
+            }
+            if let Some(landed) = grid.landing(next)
+                && seen.insert(landed)
+            {
+                queue.push_back(landed);
+            }
+        }
+    }
+    false
+}
+
+/// Cells the cat can move to from `(x, y)` by walking, jumping or riding a stone. The cat falls
+/// from each of them until it lands.
+fn cat_moves(grid: &Grid, (x, y): Cell) -> Vec<Cell> {
+    let mut moves = Vec::new();
+
+    for height in 0..=JUMP_HEIGHT {
+        if (1..=height).any(|up| !grid.is_passable((x, y + up))) {
+            break;
+        }
+        let apex_y = y + height;
+        moves.push((x, apex_y));
+        for direction in [-1, 1] {
+            // One tile past the widest gap, so the cat can land on the far side.
+            for distance in 1..=JUMP_DISTANCE + 1 {
+                let cell = (x + direction * distance, apex_y);
+                if !grid.is_passable(cell) {
+                    break;
+                }
+                moves.push(cell);
+            }
+        }
+    }
+
+    // Standing on a stone, the cat rides wherever the stone can move with it on top.
+    let below = (x, y - 1);
+    if grid.stone_cells.contains(&below) {
+        for (dx, dy) in NEIGHBORS {
+            let stone = (below.0 + dx, below.1 + dy);
+            let rider = (stone.0, stone.1 + 1);
+            if grid.stone_cells.contains(&stone) && grid.is_passable(rider) {
+                moves.push(rider);
+            }
+        }
+    }
+
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::chunk_grammar_map::{
+        ChunkGrammarConfig, MapGenError, generate_map_from_config,
+    };
+
+    /// Builds a grid from rows drawn top first: `#` is a tile, `G` the goal and `@` the cat.
+    fn grid(rows: &[String]) -> (Grid, Cell) {
+        let height = rows.len() as isize;
+        let mut grid = Grid {
+            size: (rows[0].len() as isize, height),
+            solid: HashSet::new(),
+            walls: HashSet::new(),
+            goals: HashSet::new(),
+            removable: HashSet::new(),
+            stone_cells: HashSet::new(),
+        };
+        let mut spawn = (0, 0);
+        for (row, line) in rows.iter().enumerate() {
+            let y = height - 1 - row as isize;
+            for (x, ch) in line.chars().enumerate() {
+                let cell = (x as isize, y);
+                match ch {
+                    '#' => {
+                        grid.solid.insert(cell);
+                    }
+                    'G' => {
+                        grid.goals.insert(cell);
+                    }
+                    '@' => spawn = cell,
+                    _ => {}
+                }
+            }
+        }
+        (grid, spawn)
+    }
+
+    /// The goal sits on a ledge `height` tiles above the cat's floor.
+    fn reaches_ledge(height: isize) -> bool {
+        let mut rows = vec![".......".to_string(), "....G..".to_string()];
+        rows.extend((1..height).map(|_| "...####".to_string()));
+        rows.extend(["@..####".to_string(), "#######".to_string()]);
+        let (grid, spawn) = grid(&rows);
+        cat_reaches_goal(&grid, spawn)
+    }
+
+    /// A pit `width` tiles wide, too deep to climb out of, lies between the cat and the goal.
+    fn crosses_gap(width: isize) -> bool {
+        let width = width as usize;
+        let floor = format!("##{}###", ".".repeat(width));
+        let rows = [
+            ".".repeat(width + 5),
+            format!("@{}G", ".".repeat(width + 3)),
+            floor.clone(),
+            floor,
+        ];
+        let (grid, spawn) = grid(&rows);
+        cat_reaches_goal(&grid, spawn)
+    }
+
+    /// A stage whose cat starts walled in next to its stone.
+    fn walled_in_stage(stone_type: &str, dig_limit: u32) -> ChunkGrammarConfig {
+        let source = format!(
+            "(
+                map_size: (26, 10),
+                stone_type: {stone_type},
+                dig_limit: Some({dig_limit}),
+                start_chunks: [ChunkTemplate(id: \"start\", map: [\"####.\", \"#@S#E\", \"####.\"])],
+                middle_chunks: [ChunkTemplate(id: \"floor\", map: [\"IE\", \"##\"])],
+                goal_chunks: [ChunkTemplate(id: \"goal\", map: [\"I...G\", \"#####\"])],
+            )"
+        );
+        ron::de::from_str(&source).expect("test stage should parse")
+    }
+
+    #[test]
+    fn jumps_reach_exactly_their_limits() {
+        assert!(reaches_ledge(JUMP_HEIGHT));
+        assert!(!reaches_ledge(JUMP_HEIGHT + 1));
+        assert!(crosses_gap(JUMP_DISTANCE));
+        assert!(!crosses_gap(JUMP_DISTANCE + 1));
+    }
+
+    #[test]
+    fn walls_only_open_to_digging_stones() {
+        let stones = StoneCapabilities::default();
+        let stuck = generate_map_from_config(walled_in_stage("Type1", 0), 0, &stones);
+        assert!(matches!(stuck, Err(MapGenError::Unsolvable(_))));
+
+        let digger = generate_map_from_config(walled_in_stage("Type3", 1), 0, &stones)
+            .expect("a digging stone can open the wall");
+        assert!(is_solvable(&digger, &stones));
+    }
+}
@@ -1,7 +1,10 @@
 use bevy::prelude::{Resource, *};
 use serde::{Deserialize, Serialize};
 
-use crate::resources::chunk_grammar_map::{ChunkGrammarConfig, Map, generate_map_from_config};
+use crate::resources::{
+    chunk_grammar_map::{ChunkGrammarConfig, Map, MapGenError, generate_map_from_config},
+    stone_type::StoneCapabilities,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 pub struct StageId(pub usize);
@@ -14,7 +17,7 @@ pub struct StageMeta {
 }
 
 impl StageMeta {
-    pub fn load_map(&self) -> Map {
+    pub fn load_map(&self, seed: u64, stones: &StoneCapabilities) -> Result<Map, MapGenError> {
         let stage_id = self.id.0;
         let bytes: &'static [u8] = match stage_id {
             1 => include_bytes!("../../assets/stages/stage-1.ron"),
@@ -29,13 +32,16 @@ impl StageMeta {
             10 => include_bytes!("../../assets/stages/stage-10.ron"),
             11 => include_bytes!("../../assets/stages/stage-11.ron"),
             12 => include_bytes!("../../assets/stages/stage-12.ron"),
-            _ => panic!("Stage ID: {} Not found.", stage_id),
+            _ => return Err(MapGenError::UnknownStage(stage_id)),
         };
 
-        let config: ChunkGrammarConfig = ron::de::from_bytes(bytes)
-            .unwrap_or_else(|_| panic!("Parse failed: stage-{}.ron", stage_id));
+        let config: ChunkGrammarConfig =
+            ron::de::from_bytes(bytes).map_err(|err| MapGenError::Parse {
+                stage_id,
+                message: err.to_string(),
+            })?;
 
-        generate_map_from_config(config)
+        generate_map_from_config(config, seed, stones)
     }
 }
 
@@ -1,8 +1,10 @@
 use bevy::prelude::{Resource, info, warn};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 use crate::resources::{
     file_storage::{FileError, FileStorage},
+    solution_metrics::SolutionRating,
     stage_catalog::{self, StageId},
 };
 
@@ -15,6 +17,12 @@ pub struct StageProgress {
     unlocked_until: StageId,
     #[serde(default)]
     pub last_played_stage_id: Option<StageId>,
+    /// Best rated clear of each stage.
+    #[serde(default)]
+    best_solutions: HashMap<StageId, SolutionRating>,
+    /// Layout seed of the latest attempt at each stage, so a reported map can be loaded again.
+    #[serde(default)]
+    map_seeds: HashMap<StageId, u64>,
 }
 
 impl StageProgress {
@@ -37,6 +45,33 @@ impl StageProgress {
         }
     }
 
+    pub fn best_solution(&self, stage_id: StageId) -> Option<&SolutionRating> {
+        self.best_solutions.get(&stage_id)
+    }
+
+    /// Keeps `rating` if it beats the stored clear of the stage. Returns true if state changed.
+    pub fn record_solution(&mut self, stage_id: StageId, rating: SolutionRating) -> bool {
+        if self
+            .best_solutions
+            .get(&stage_id)
+            .is_some_and(|best| !rating.beats(best))
+        {
+            return false;
+        }
+        self.best_solutions.insert(stage_id, rating);
+        true
+    }
+
+    pub fn map_seed(&self, stage_id: StageId) -> Option<u64> {
+        self.map_seeds.get(&stage_id).copied()
+    }
+
+    /// Remembers the seed of a new attempt; `persist_stage_progress` saves it with the rest of the
+    /// progress. Returns true if state changed.
+    pub fn record_map_seed(&mut self, stage_id: StageId, seed: u64) -> bool {
+        self.map_seeds.insert(stage_id, seed) != Some(seed)
+    }
+
     pub fn set_last_played(&mut self, stage_id: StageId, storage: &dyn FileStorage) {
         if self.last_played_stage_id != Some(stage_id) {
             self.last_played_stage_id = Some(stage_id);
@@ -105,4 +140,27 @@ mod tests {
         // unlocking same or lower doesn't change
         assert!(!p.unlock_until(StageId(1)));
     }
+
+    #[test]
+    fn keeps_best_solution() {
+        use crate::resources::solution_metrics::SolutionMetrics;
+
+        let rating = |stars, commands| SolutionRating {
+            stars: Some(stars),
+            metrics: SolutionMetrics {
+                commands,
+                ..SolutionMetrics::default()
+            },
+        };
+        let mut p = StageProgress::default();
+        assert!(p.record_solution(StageId(1), rating(2, 10)));
+        assert!(!p.record_solution(StageId(1), rating(1, 3)));
+        assert!(p.record_solution(StageId(1), rating(2, 8)));
+        assert!(!p.record_solution(StageId(1), rating(2, 8)));
+        assert_eq!(p.best_solution(StageId(1)), Some(&rating(2, 8)));
+
+        let saved = ron::ser::to_string(&p).unwrap();
+        let loaded: StageProgress = ron::de::from_str(&saved).unwrap();
+        assert_eq!(loaded.best_solution(StageId(1)), Some(&rating(2, 8)));
+    }
 }
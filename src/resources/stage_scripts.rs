@@ -1,6 +1,6 @@
 use crate::resources::{
     file_storage::{FileError, FileStorage},
-    script_engine::Language,
+    script_engine::{BlockProgram, Language},
     stage_catalog::StageId,
 };
 use bevy::prelude::{Resource, info, warn};
@@ -9,10 +9,20 @@ use std::collections::HashMap;
 
 pub const STAGE_SCRIPTS_FILE: &str = "stage_scripts.ron";
 
-/// Stores the latest editor script per stage.
+/// Stores the latest editor script per stage and stone.
+///
+/// The first stone keeps the original layout so older save files still load.
 #[derive(Resource, Debug, Clone, Serialize, Deserialize, Default)]
 pub struct StageScripts {
     scripts: HashMap<Language, HashMap<StageId, String>>,
+    /// Block editor programs; they generate source for whichever language is selected.
+    #[serde(default)]
+    blocks: HashMap<StageId, BlockProgram>,
+    /// Scripts of the second and later stones, keyed by stage and stone index.
+    #[serde(default)]
+    stone_scripts: HashMap<Language, HashMap<(StageId, usize), String>>,
+    #[serde(default)]
+    stone_blocks: HashMap<(StageId, usize), BlockProgram>,
 }
 
 impl StageScripts {
@@ -42,11 +52,45 @@ impl StageScripts {
             })
     }
 
-    pub fn stage_code(&self, lang: Language, stage_id: StageId) -> Option<&str> {
-        self.scripts.get(&lang)?.get(&stage_id).map(String::as_str)
+    pub fn stage_code(&self, lang: Language, stage_id: StageId, stone: usize) -> Option<&str> {
+        let code = match stone {
+            0 => self.scripts.get(&lang)?.get(&stage_id),
+            _ => self.stone_scripts.get(&lang)?.get(&(stage_id, stone)),
+        };
+        code.map(String::as_str)
     }
 
-    pub fn set_stage_code(&mut self, lang: Language, stage_id: StageId, code: String) {
-        self.scripts.entry(lang).or_default().insert(stage_id, code);
+    pub fn set_stage_code(
+        &mut self,
+        lang: Language,
+        stage_id: StageId,
+        stone: usize,
+        code: String,
+    ) {
+        match stone {
+            0 => {
+                self.scripts.entry(lang).or_default().insert(stage_id, code);
+            }
+            _ => {
+                self.stone_scripts
+                    .entry(lang)
+                    .or_default()
+                    .insert((stage_id, stone), code);
+            }
+        }
+    }
+
+    pub fn stage_blocks(&self, stage_id: StageId, stone: usize) -> Option<&BlockProgram> {
+        match stone {
+            0 => self.blocks.get(&stage_id),
+            _ => self.stone_blocks.get(&(stage_id, stone)),
+        }
+    }
+
+    pub fn set_stage_blocks(&mut self, stage_id: StageId, stone: usize, program: BlockProgram) {
+        match stone {
+            0 => self.blocks.insert(stage_id, program),
+            _ => self.stone_blocks.insert((stage_id, stone), program),
+        };
     }
 }
@@ -1,59 +1,194 @@
 use bevy::prelude::*;
-use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use serde::{
+    Deserialize, Deserializer,
+    de::{self, EnumAccess, VariantAccess, Visitor},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default, Component)]
-pub enum StoneType {
-    #[default]
-    Type1,
-    Type2,
-    Type3,
-    Type4,
+const DEFAULT_STONE_TYPE: &str = "Type1";
+
+/// Name of a stone definition in `assets/stones.ron`.
+///
+/// Written as a bare identifier in RON (`stone_type: Type3`), like the enum it replaced.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Component)]
+pub struct StoneType(String);
+
+impl StoneType {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for StoneType {
+    fn default() -> Self {
+        Self::new(DEFAULT_STONE_TYPE)
+    }
+}
+
+impl fmt::Display for StoneType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for StoneType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // RON hands bare identifiers to enum visitors only, so read the name as a unit variant.
+        deserializer.deserialize_enum("StoneType", &[], StoneTypeVisitor)
+    }
+}
+
+struct StoneTypeVisitor;
+
+impl<'de> Visitor<'de> for StoneTypeVisitor {
+    type Value = StoneType;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a stone type name")
+    }
+
+    fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+        Ok(StoneType::new(name))
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        let (name, variant) = data.variant::<StoneTypeName>()?;
+        variant.unit_variant()?;
+        Ok(name.0)
+    }
+}
+
+struct StoneTypeName(StoneType);
+
+impl<'de> Deserialize<'de> for StoneTypeName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_identifier(StoneTypeVisitor)
+            .map(StoneTypeName)
+    }
+}
+
+/// One entry of `assets/stones.ron`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StoneDefinition {
+    pub id: StoneType,
+    /// Commands and sensors scripts may use, e.g. `move`, `dig`, `is_touched`.
+    pub commands: HashSet<String>,
+    /// Distance covered by one `move`, in stage units.
+    pub step_size: f32,
+    /// Seconds one `move` takes.
+    pub move_duration: f32,
+    /// Column and row in the rune sprite sheet.
+    pub sprite: (u32, u32),
 }
 
 #[derive(Resource)]
 pub struct StoneCapabilities {
-    map: HashMap<StoneType, HashSet<String>>,
+    map: HashMap<StoneType, StoneDefinition>,
+    /// Why `stones.ron` could not be parsed; stages report it instead of loading.
+    load_error: Option<String>,
 }
 
 impl Default for StoneCapabilities {
     fn default() -> Self {
-        let mut map = HashMap::new();
+        const EMBEDDED: &str = include_str!("../../assets/stones.ron");
+
+        Self::from_ron(EMBEDDED)
+    }
+}
 
-        // Type 1: Move + Sleep
-        let mut type1 = HashSet::new();
-        type1.insert("move".to_string());
-        type1.insert("sleep".to_string());
-        map.insert(StoneType::Type1, type1);
+impl StoneCapabilities {
+    pub fn from_definitions(definitions: Vec<StoneDefinition>) -> Self {
+        let map = definitions
+            .into_iter()
+            .map(|definition| (definition.id.clone(), definition))
+            .collect();
+        Self {
+            map,
+            load_error: None,
+        }
+    }
 
-        // Type 2: Move + Touched + IsEmpty
-        let mut type2 = HashSet::new();
-        type2.insert("move".to_string());
-        type2.insert("is_touched".to_string());
-        type2.insert("is_empty".to_string());
-        map.insert(StoneType::Type2, type2.clone());
+    /// Parses `stones.ron`; on failure no stone is defined and the error is kept.
+    pub fn from_ron(source: &str) -> Self {
+        match ron::de::from_str::<Vec<StoneDefinition>>(source) {
+            Ok(definitions) => Self::from_definitions(definitions),
+            Err(err) => {
+                error!("Parse failed: stones.ron: {err}");
+                Self {
+                    map: HashMap::new(),
+                    load_error: Some(err.to_string()),
+                }
+            }
+        }
+    }
 
-        // Type 3: Move + Sleep + Touched + Dig + IsEmpty
-        let mut type3 = HashSet::new();
-        type3.insert("move".to_string());
-        type3.insert("sleep".to_string());
-        type3.insert("is_touched".to_string());
-        type3.insert("is_empty".to_string());
-        type3.insert("dig".to_string());
-        map.insert(StoneType::Type3, type3);
+    pub fn load_error(&self) -> Option<&str> {
+        self.load_error.as_deref()
+    }
 
-        // // Type 4: Move + Touched
-        // let mut type4 = HashSet::new();
-        // type4.insert("move".to_string());
-        // type4.insert("is_touched".to_string());
-        // map.insert(StoneType::Type4, type4);
+    pub fn get_capabilities(&self, stone_type: &StoneType) -> Option<&HashSet<String>> {
+        self.map
+            .get(stone_type)
+            .map(|definition| &definition.commands)
+    }
 
-        Self { map }
+    pub fn definition(&self, stone_type: &StoneType) -> Option<&StoneDefinition> {
+        self.map.get(stone_type)
     }
 }
 
-impl StoneCapabilities {
-    pub fn get_capabilities(&self, stone_type: StoneType) -> Option<&HashSet<String>> {
-        self.map.get(&stone_type)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::chunk_grammar_map::ChunkGrammarConfig;
+    use std::fs;
+
+    #[test]
+    fn every_stage_stone_is_defined() {
+        let stones = StoneCapabilities::default();
+        let stages_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/stages");
+        for entry in fs::read_dir(stages_dir).expect("stages directory should exist") {
+            let path = entry.expect("stage entry should be readable").path();
+            let is_stage = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("stage-") && name.ends_with(".ron"));
+            if !is_stage {
+                continue;
+            }
+
+            let bytes = fs::read(&path).expect("stage file should be readable");
+            let config: ChunkGrammarConfig =
+                ron::de::from_bytes(&bytes).expect("stage file should parse");
+            for stone_type in std::iter::once(&config.stone_type).chain(&config.stone_types) {
+                assert!(
+                    stones.definition(stone_type).is_some(),
+                    "{} uses undefined stone {stone_type}",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stone_types_read_as_bare_names() {
+        let definitions: Vec<StoneDefinition> = ron::de::from_str(
+            "[(id: Digger, commands: [\"dig\"], step_size: 16.0, move_duration: 0.5, sprite: (1, 2))]",
+        )
+        .expect("definition should parse");
+        let stones = StoneCapabilities::from_definitions(definitions);
+
+        let digger = StoneType::new("Digger");
+        assert!(stones.get_capabilities(&digger).unwrap().contains("dig"));
+        assert_eq!(stones.definition(&digger).unwrap().sprite, (1, 2));
+        assert!(stones.definition(&StoneType::default()).is_none());
     }
 }
@@ -153,6 +153,9 @@ pub fn update(
     if boot_timer.timer.is_finished() && loaded.0 && localization_ready {
         info!("Boot timer finished");
         let mut target_state = GameState::SelectStage;
+        if let Some(seed) = launch_profile.map_seed {
+            progression.set_next_map_seed(seed);
+        }
         if let Some(stage_id) = launch_profile.stage_id {
             match stage_catalog.stage_by_id(stage_id) {
                 Some(stage) => {
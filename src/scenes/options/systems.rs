@@ -8,7 +8,10 @@ use bevy_fluent::prelude::{Locale, Localization, LocalizationBuilder};
 
 use crate::{
     resources::asset_store::AssetStore,
-    resources::{script_engine::Language, settings::GameSettings},
+    resources::{
+        script_engine::Language,
+        settings::{EditorMode, GameSettings},
+    },
     scenes::audio::{AudioHandles, play_ui_click},
     util::{font::apply_font_for_locale, localization::tr},
 };
@@ -306,11 +309,21 @@ fn language_selector(
         );
         ui.add_space(8.0);
         ui.horizontal(|ui| {
-            for (language, key) in [
-                (Language::Rhai, "options-language-rhai"),
-                (Language::Keystone, "options-language-keystone"),
+            // Blocks keep generating source for the last selected text language.
+            for (language, mode, key) in [
+                (Language::Rhai, EditorMode::Text, "options-language-rhai"),
+                (
+                    Language::Keystone,
+                    EditorMode::Text,
+                    "options-language-keystone",
+                ),
+                (
+                    settings.script_language,
+                    EditorMode::Blocks,
+                    "options-language-blocks",
+                ),
             ] {
-                let selected = settings.script_language == language;
+                let selected = settings.script_language == language && settings.editor_mode == mode;
                 let mut button =
                     egui::Button::new(RichText::new(tr(localization, key)).size(20.0).color(
                         if selected {
@@ -328,6 +341,7 @@ fn language_selector(
 
                 if ui.add(button).clicked() {
                     settings.script_language = language;
+                    settings.editor_mode = mode;
                     changed = true;
                 }
                 ui.add_space(12.0);
@@ -21,6 +21,10 @@ pub struct DugTile {
 #[derive(Component)]
 pub struct StoneRune;
 
+/// Position of the stone among the map's stones; selects its editor tab and program.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StoneIndex(pub usize);
+
 #[derive(Component)]
 pub struct DigLimit(pub Option<u32>);
 
@@ -4,6 +4,7 @@ use bevy_egui::EguiPrimaryContextPass;
 use crate::{resources::game_state::GameState, scenes::stage::systems::tick_pending_tutorial};
 
 pub mod components;
+pub mod simulator;
 pub mod systems;
 
 pub use systems::StageProgressionState;
@@ -0,0 +1,514 @@
+//! Headless stage simulation.
+//!
+//! Runs a player script against a generated [`Map`] without a window, renderer or egui,
+//! reusing the regular stage systems on top of `MinimalPlugins` and avian2d. Intended for
+//! regression-testing saved solutions on machines without a GPU.
+
+use std::{fs, time::Duration};
+
+use avian2d::prelude::*;
+use bevy::{asset::AssetPlugin, gizmos::GizmoPlugin, prelude::*, time::TimeUpdateStrategy};
+use serde::Deserialize;
+
+use super::systems::{
+    self, ScriptEditorState, StageAudioHandles, StageAudioState, StageSystemSet,
+    StoneAppendCommandMessage, StoneCommandMessage,
+};
+use crate::{
+    resources::{
+        asset_store::AssetStore,
+        chunk_grammar_map::Map,
+        design_resolution::ScaledViewport,
+        launch_profile::LaunchProfile,
+        script_engine::{Language, ScriptExecutor},
+        settings::GameSettings,
+        stage_catalog::{StageId, StageMeta},
+        stone_type::StoneCapabilities,
+        tiled::{TILE_SIZE, TiledMapAssets, TiledTilesetImage, Tileset},
+    },
+    scenes::assets::PLAYER_IDLE_KEYS,
+    systems::engine::friction::apply_zero_friction_to_rigid_bodies,
+    util::script_types::{ScriptCommand, ScriptExecutionError, stone_rand_seed},
+};
+
+// Matches the design resolution configured in `main`, so physics runs at scale 1.0.
+const SIMULATION_VIEWPORT_SIZE: Vec2 = Vec2::new(1800.0, 1200.0);
+const DEFAULT_TIMESTEP: Duration = Duration::from_micros(16_667);
+const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(120);
+
+/// Keys the simulated player can hold down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SimulatedKey {
+    Left,
+    Right,
+    Jump,
+}
+
+impl SimulatedKey {
+    fn key_code(self) -> KeyCode {
+        match self {
+            SimulatedKey::Left => KeyCode::ArrowLeft,
+            SimulatedKey::Right => KeyCode::ArrowRight,
+            SimulatedKey::Jump => KeyCode::Space,
+        }
+    }
+}
+
+/// A key press or release applied `at` seconds after the run starts.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct PlayerInputEvent {
+    pub at: f32,
+    pub key: SimulatedKey,
+    pub pressed: bool,
+}
+
+pub struct SimulationSettings {
+    pub language: Language,
+    /// Script of each stone in map order; stones past the end run an empty script.
+    pub sources: Vec<String>,
+    pub player_inputs: Vec<PlayerInputEvent>,
+    /// Seed of the `rand` sensor, so a reported run can be replayed exactly.
+    pub seed: u64,
+    pub timestep: Duration,
+    pub time_limit: Duration,
+}
+
+impl SimulationSettings {
+    pub fn new(language: Language, sources: Vec<String>) -> Self {
+        Self {
+            language,
+            sources,
+            player_inputs: Vec::new(),
+            seed: 0,
+            timestep: DEFAULT_TIMESTEP,
+            time_limit: DEFAULT_TIME_LIMIT,
+        }
+    }
+}
+
+/// Verdict of a headless run.
+#[derive(Debug)]
+pub struct SimulationReport {
+    pub cleared: bool,
+    pub elapsed: Duration,
+    pub commands: Vec<ScriptCommand>,
+    /// Set when the script stopped on a runtime error before the goal was reached.
+    pub runtime_error: Option<String>,
+}
+
+#[derive(Resource)]
+struct SimulatedMap(Map);
+
+#[derive(Resource, Default)]
+struct CommandTrace(Vec<ScriptCommand>);
+
+#[derive(Resource)]
+struct PlayerInputTimeline {
+    events: Vec<PlayerInputEvent>,
+    next: usize,
+}
+
+/// Runs every stone of `map` on its script in `settings.sources` until the goal is reached or the
+/// time limit expires.
+pub fn simulate_stage(
+    map: &Map,
+    settings: &SimulationSettings,
+) -> Result<SimulationReport, ScriptExecutionError> {
+    let executor = ScriptExecutor::default();
+    let capabilities = StoneCapabilities::default();
+    let programs = (0..map.stone_positions().len())
+        .map(|stone| {
+            executor.compile_step(
+                settings.language,
+                settings.sources.get(stone).map_or("", String::as_str),
+                capabilities.get_capabilities(&map.stone_type_at(stone)),
+                &map.sandbox,
+                stone_rand_seed(settings.seed, stone),
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let editor = ScriptEditorState {
+        active_programs: programs,
+        stage_par: map.par,
+        sandbox: map.sandbox,
+        run_seed: settings.seed,
+        seed_fixed: true,
+        controls_enabled: true,
+        ..default()
+    };
+
+    let mut player_inputs = settings.player_inputs.clone();
+    player_inputs.sort_by(|a, b| a.at.total_cmp(&b.at));
+
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        TransformPlugin,
+        AssetPlugin::default(),
+        GizmoPlugin,
+        PhysicsPlugins::default(),
+    ))
+    .init_asset::<Image>()
+    .init_asset::<Mesh>()
+    .init_asset::<TextureAtlasLayout>()
+    .insert_resource(TimeUpdateStrategy::ManualDuration(settings.timestep))
+    .insert_resource(ScaledViewport::new(SIMULATION_VIEWPORT_SIZE))
+    .insert_resource(headless_tiled_map_assets())
+    .insert_resource(headless_asset_store())
+    .insert_resource(StageAudioHandles::new(Handle::default(), Handle::default()))
+    .init_resource::<StageAudioState>()
+    .init_resource::<GameSettings>()
+    .init_resource::<LaunchProfile>()
+    .init_resource::<ButtonInput<KeyCode>>()
+    .init_resource::<CommandTrace>()
+    .insert_resource(PlayerInputTimeline {
+        events: player_inputs,
+        next: 0,
+    })
+    .insert_resource(SimulatedMap(map.clone()))
+    .insert_resource(capabilities)
+    .insert_resource(editor)
+    .add_message::<StoneCommandMessage>()
+    .add_message::<StoneAppendCommandMessage>();
+
+    StageSystemSet::configure_sets(&mut app);
+
+    app.add_systems(Startup, spawn_simulated_stage)
+        .add_systems(PostStartup, apply_zero_friction_to_rigid_bodies)
+        .add_systems(PreUpdate, apply_player_inputs)
+        .add_systems(
+            Update,
+            (
+                systems::handle_stone_messages,
+                systems::handle_stone_append_messages,
+            )
+                .in_set(StageSystemSet::Input),
+        )
+        .add_systems(
+            Update,
+            (
+                systems::tick_script_program,
+                record_command_trace.after(systems::tick_script_program),
+            )
+                .in_set(StageSystemSet::Script),
+        )
+        .add_systems(
+            Update,
+            (
+                systems::restore_dug_tiles,
+                systems::reset_stone_position,
+                systems::reset_player_position,
+            )
+                .chain()
+                .in_set(StageSystemSet::Reset),
+        )
+        .add_systems(
+            Update,
+            (systems::move_player, systems::update_stone_behavior).in_set(StageSystemSet::Movement),
+        )
+        .add_systems(
+            Update,
+            systems::carry_riders_with_stone.in_set(StageSystemSet::Collision),
+        )
+        .add_systems(
+            Update,
+            systems::check_goal_completion.in_set(StageSystemSet::Goal),
+        );
+
+    app.finish();
+    app.cleanup();
+
+    let mut cleared = false;
+    let mut runtime_error = None;
+    loop {
+        app.update();
+
+        let editor = app.world().resource::<ScriptEditorState>();
+        if editor.stage_cleared {
+            cleared = true;
+            break;
+        }
+        if !editor.controls_enabled {
+            runtime_error = editor.last_run_feedback.clone();
+            break;
+        }
+        if app.world().resource::<Time>().elapsed() >= settings.time_limit {
+            break;
+        }
+    }
+
+    let elapsed = app.world().resource::<Time>().elapsed();
+    let commands = app
+        .world_mut()
+        .remove_resource::<CommandTrace>()
+        .map(|trace| trace.0)
+        .unwrap_or_default();
+
+    Ok(SimulationReport {
+        cleared,
+        elapsed,
+        commands,
+        runtime_error,
+    })
+}
+
+/// Entry point for `--simulate-stage`. Prints the verdict and returns whether the stage was cleared.
+pub fn run_from_launch_profile(launch_profile: &LaunchProfile) -> bool {
+    let stage_id = launch_profile.stage_id.unwrap_or(StageId(1));
+    if launch_profile.script_paths.is_empty() {
+        eprintln!("--simulate-stage requires --script <path>");
+        return false;
+    }
+    let mut sources = Vec::with_capacity(launch_profile.script_paths.len());
+    for script_path in &launch_profile.script_paths {
+        match fs::read_to_string(script_path) {
+            Ok(source) => sources.push(source),
+            Err(err) => {
+                eprintln!("Failed to read script '{script_path}': {err}");
+                return false;
+            }
+        }
+    }
+
+    let language = launch_profile.script_language.unwrap_or(Language::Rhai);
+    let mut settings = SimulationSettings::new(language, sources);
+    settings.seed = launch_profile.rand_seed.unwrap_or_default();
+    if let Some(inputs_path) = launch_profile.player_inputs_path.as_deref() {
+        match load_player_inputs(inputs_path) {
+            Ok(inputs) => settings.player_inputs = inputs,
+            Err(err) => {
+                eprintln!("Failed to load player inputs '{inputs_path}': {err}");
+                return false;
+            }
+        }
+    }
+
+    let meta = StageMeta {
+        id: stage_id,
+        title: String::new(),
+        unlocked: true,
+    };
+    let map = match meta.load_map(
+        launch_profile.map_seed.unwrap_or_default(),
+        &StoneCapabilities::default(),
+    ) {
+        Ok(map) => map,
+        Err(err) => {
+            eprintln!("Failed to build stage-{}: {err}", stage_id.0);
+            return false;
+        }
+    };
+
+    match simulate_stage(&map, &settings) {
+        Ok(report) => {
+            let verdict = if report.cleared {
+                "CLEARED"
+            } else {
+                "NOT CLEARED"
+            };
+            println!(
+                "stage-{}: {} after {:.2}s ({} commands)",
+                stage_id.0,
+                verdict,
+                report.elapsed.as_secs_f32(),
+                report.commands.len()
+            );
+            for (index, command) in report.commands.iter().enumerate() {
+                println!("{:>4}: {:?}", index + 1, command);
+            }
+            if let Some(error) = &report.runtime_error {
+                println!("stopped: {error}");
+            }
+            report.cleared
+        }
+        Err(err) => {
+            eprintln!("stage-{}: {}", stage_id.0, err);
+            false
+        }
+    }
+}
+
+fn load_player_inputs(path: &str) -> Result<Vec<PlayerInputEvent>, String> {
+    let bytes = fs::read(path).map_err(|err| err.to_string())?;
+    ron::de::from_bytes(&bytes).map_err(|err| err.to_string())
+}
+
+fn spawn_simulated_stage(
+    mut commands: Commands,
+    stage: Res<SimulatedMap>,
+    tiled_map_assets: Res<TiledMapAssets>,
+    viewport: Res<ScaledViewport>,
+    asset_store: Res<AssetStore>,
+    asset_server: Res<AssetServer>,
+    mut atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+    stones: Res<StoneCapabilities>,
+) {
+    // With the window matching the viewport, the stage root sits at the origin.
+    systems::spawn_stage(
+        &mut commands,
+        Transform::IDENTITY,
+        &tiled_map_assets,
+        &stage.0,
+        &viewport,
+        &asset_store,
+        &asset_server,
+        &mut atlas_layouts,
+        &stones,
+    );
+}
+
+fn apply_player_inputs(
+    time: Res<Time>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut timeline: ResMut<PlayerInputTimeline>,
+) {
+    keys.clear();
+
+    let now = time.elapsed_secs();
+    while let Some(event) = timeline.events.get(timeline.next).copied()
+        && event.at <= now
+    {
+        if event.pressed {
+            keys.press(event.key.key_code());
+        } else {
+            keys.release(event.key.key_code());
+        }
+        timeline.next += 1;
+    }
+}
+
+fn record_command_trace(
+    mut reader: MessageReader<StoneAppendCommandMessage>,
+    mut trace: ResMut<CommandTrace>,
+) {
+    trace
+        .0
+        .extend(reader.read().map(|message| message.command.clone()));
+}
+
+fn headless_tiled_map_assets() -> TiledMapAssets {
+    // Collision shapes are hardcoded per tile id, so placeholder handles are enough.
+    TiledMapAssets {
+        tileset: Tileset {
+            image: Some(TiledTilesetImage {
+                texture: Handle::default(),
+                layout: Handle::default(),
+                tile_size: UVec2::new(TILE_SIZE.0 as u32, TILE_SIZE.1 as u32),
+            }),
+        },
+    }
+}
+
+fn headless_asset_store() -> AssetStore {
+    // The player is only spawned when at least one animation frame exists.
+    let mut asset_store = AssetStore::default();
+    for key in PLAYER_IDLE_KEYS {
+        asset_store.insert_image(key.into(), Handle::default());
+    }
+    asset_store
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        resources::chunk_grammar_map::{ChunkGrammarConfig, generate_map_from_config},
+        util::script_types::MoveDirection,
+    };
+
+    fn test_map(start: &str) -> Map {
+        let config: ChunkGrammarConfig = ron::de::from_str(&format!(
+            "(
+                map_size: (7, 5),
+                start_chunks: [ChunkTemplate(id: \"start\", map: [\"{start}\", \"#####\"])],
+                middle_chunks: [],
+                goal_chunks: [ChunkTemplate(id: \"goal\", map: [\"I.G\", \"###\"])],
+            )"
+        ))
+        .expect("test stage should parse");
+        generate_map_from_config(config, 0, &StoneCapabilities::default())
+            .expect("test stage should generate")
+    }
+
+    fn two_stone_map() -> Map {
+        test_map("@S.SE")
+    }
+
+    fn settings(sources: &[&str]) -> SimulationSettings {
+        let mut settings = SimulationSettings::new(
+            Language::Rhai,
+            sources.iter().map(|source| source.to_string()).collect(),
+        );
+        settings.time_limit = Duration::from_secs(1);
+        settings
+    }
+
+    fn moves(report: &SimulationReport, direction: MoveDirection) -> usize {
+        report
+            .commands
+            .iter()
+            .filter(|command| matches!(command, ScriptCommand::Move(found) if *found == direction))
+            .count()
+    }
+
+    #[test]
+    fn walking_to_the_goal_clears_the_stage() {
+        // The stone stays behind the cat, which walks right and hops the step before the goal.
+        let mut settings = settings(&["move_left();"]);
+        settings.time_limit = Duration::from_secs(30);
+        settings.player_inputs.push(PlayerInputEvent {
+            at: 0.0,
+            key: SimulatedKey::Right,
+            pressed: true,
+        });
+        for second in 0..20 {
+            let at = second as f32 + 0.5;
+            settings.player_inputs.extend([
+                PlayerInputEvent {
+                    at,
+                    key: SimulatedKey::Jump,
+                    pressed: true,
+                },
+                PlayerInputEvent {
+                    at: at + 0.2,
+                    key: SimulatedKey::Jump,
+                    pressed: false,
+                },
+            ]);
+        }
+
+        let report =
+            simulate_stage(&test_map("S@..E"), &settings).expect("the stone should compile");
+        assert!(report.cleared, "stopped with {:?}", report.runtime_error);
+        assert!(report.runtime_error.is_none());
+        assert!(report.elapsed > Duration::ZERO && report.elapsed < settings.time_limit);
+        assert!(matches!(
+            report.commands.as_slice(),
+            [ScriptCommand::Move(MoveDirection::Left)]
+        ));
+    }
+
+    #[test]
+    fn every_stone_runs_its_own_script() {
+        let map = two_stone_map();
+        assert_eq!(map.stone_positions().len(), 2);
+
+        let report = simulate_stage(&map, &settings(&["move_left();", "move_right();"]))
+            .expect("both stones should compile");
+        assert_eq!(moves(&report, MoveDirection::Left), 1);
+        assert_eq!(moves(&report, MoveDirection::Right), 1);
+
+        // Stones past the end of `sources` run an empty script.
+        let report = simulate_stage(&map, &settings(&["move_left();"]))
+            .expect("the scripted stone should compile");
+        assert_eq!(report.commands.len(), 1);
+    }
+
+    #[test]
+    fn errors_in_any_stone_stop_the_simulation() {
+        let err = simulate_stage(&two_stone_map(), &settings(&["move_left();", "let = 3;"]))
+            .expect_err("the second stone does not compile");
+        assert_eq!(err.location().map(|location| location.line), Some(1));
+    }
+}
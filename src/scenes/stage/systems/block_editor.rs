@@ -0,0 +1,234 @@
+//! Drag-and-drop block editor shown instead of the text editor in `EditorMode::Blocks`.
+
+use std::{collections::HashSet, sync::Arc};
+
+use bevy_egui::egui::{self, Color32, Frame, Id, Margin, RichText};
+use bevy_fluent::prelude::Localization;
+
+use crate::{
+    resources::script_engine::{Block, BlockPath, BlockProgram},
+    util::{localization::tr, script_types::MoveDirection},
+};
+
+const DIRECTIONS: [MoveDirection; 4] = [
+    MoveDirection::Top,
+    MoveDirection::Down,
+    MoveDirection::Left,
+    MoveDirection::Right,
+];
+
+/// Payload carried while a block is dragged.
+enum BlockDrag {
+    New(Block),
+    Existing(BlockPath),
+}
+
+/// Structural changes are collected while drawing and applied afterwards.
+enum BlockEdit {
+    Insert(BlockPath, Block),
+    Move(BlockPath, BlockPath),
+    Remove(BlockPath),
+}
+
+/// Draws the palette, the program and a trash slot. Returns whether the program changed.
+pub(super) fn show_block_editor(
+    ui: &mut egui::Ui,
+    localization: &Localization,
+    program: &mut BlockProgram,
+    allowed_commands: Option<&HashSet<String>>,
+    locked: bool,
+) -> bool {
+    let before = program.clone();
+    let mut edits = Vec::new();
+
+    ui.add_enabled_ui(!locked, |ui| {
+        ui.label(RichText::new(tr(localization, "stage-ui-blocks-palette")).strong());
+        ui.horizontal_wrapped(|ui| {
+            for (index, block) in Block::palette(allowed_commands).into_iter().enumerate() {
+                ui.dnd_drag_source(
+                    Id::new(("block-palette", index)),
+                    BlockDrag::New(block.clone()),
+                    |ui| block_chip(ui, localization, &block),
+                );
+                if ui.small_button("+").clicked() {
+                    edits.push(BlockEdit::Insert(vec![program.blocks.len()], block));
+                }
+                ui.add_space(6.0);
+            }
+        });
+
+        ui.separator();
+        ui.label(RichText::new(tr(localization, "stage-ui-blocks-program")).strong());
+
+        let mut parent = Vec::new();
+        show_block_list(
+            ui,
+            localization,
+            &mut program.blocks,
+            &mut parent,
+            &mut edits,
+        );
+
+        let end = vec![program.blocks.len()];
+        let hint_key = if program.is_empty() {
+            "stage-ui-blocks-empty"
+        } else {
+            "stage-ui-blocks-drop-here"
+        };
+        let (_, dropped) = ui.dnd_drop_zone::<BlockDrag, _>(Frame::group(ui.style()), |ui| {
+            ui.set_min_width(ui.available_width());
+            ui.weak(tr(localization, hint_key));
+        });
+        edits.extend(drop_edit(dropped, end));
+
+        ui.add_space(4.0);
+        let (_, trashed) = ui.dnd_drop_zone::<BlockDrag, _>(Frame::group(ui.style()), |ui| {
+            ui.set_min_width(ui.available_width());
+            ui.weak(tr(localization, "stage-ui-blocks-trash"));
+        });
+        if let Some(drag) = trashed
+            && let BlockDrag::Existing(path) = drag.as_ref()
+        {
+            edits.push(BlockEdit::Remove(path.clone()));
+        }
+    });
+
+    for edit in edits {
+        match edit {
+            BlockEdit::Insert(path, block) => {
+                program.insert(&path, block);
+            }
+            BlockEdit::Move(from, to) => {
+                program.move_block(&from, &to);
+            }
+            BlockEdit::Remove(path) => {
+                program.remove(&path);
+            }
+        }
+    }
+
+    *program != before
+}
+
+fn show_block_list(
+    ui: &mut egui::Ui,
+    localization: &Localization,
+    blocks: &mut [Block],
+    parent: &mut BlockPath,
+    edits: &mut Vec<BlockEdit>,
+) {
+    for (index, block) in blocks.iter_mut().enumerate() {
+        parent.push(index);
+        let path = parent.clone();
+
+        // Dropping onto a block inserts in front of it.
+        let (_, dropped) = ui.dnd_drop_zone::<BlockDrag, _>(Frame::NONE, |ui| {
+            ui.horizontal(|ui| {
+                ui.dnd_drag_source(
+                    Id::new(("block", &path)),
+                    BlockDrag::Existing(path.clone()),
+                    |ui| block_chip(ui, localization, block),
+                );
+                show_block_params(ui, localization, block, &path);
+                if ui.small_button("✖").clicked() {
+                    edits.push(BlockEdit::Remove(path.clone()));
+                }
+            });
+        });
+        edits.extend(drop_edit(dropped, path.clone()));
+
+        if let Some(body) = block.body_mut() {
+            let mut end = path.clone();
+            end.push(body.len());
+            ui.indent(("block-body", &path), |ui| {
+                show_block_list(ui, localization, body, parent, edits);
+                let (_, dropped) = ui.dnd_drop_zone::<BlockDrag, _>(Frame::NONE, |ui| {
+                    ui.weak(tr(localization, "stage-ui-blocks-drop-here"));
+                });
+                edits.extend(drop_edit(dropped, end));
+            });
+        }
+
+        parent.pop();
+    }
+}
+
+fn show_block_params(
+    ui: &mut egui::Ui,
+    localization: &Localization,
+    block: &mut Block,
+    path: &BlockPath,
+) {
+    match block {
+        Block::Move(dir) | Block::Dig(dir) | Block::IfEmpty(dir, _) => {
+            egui::ComboBox::from_id_salt(("block-direction", path))
+                .selected_text(direction_label(localization, *dir))
+                .show_ui(ui, |ui| {
+                    for option in DIRECTIONS {
+                        ui.selectable_value(dir, option, direction_label(localization, option));
+                    }
+                });
+        }
+        Block::Sleep(seconds) => {
+            ui.add(
+                egui::DragValue::new(seconds)
+                    .range(0.1..=10.0)
+                    .speed(0.1)
+                    .max_decimals(1)
+                    .suffix(format!(" {}", tr(localization, "stage-ui-blocks-seconds"))),
+            );
+        }
+        Block::Loop(_) | Block::IfTouched(_) => {}
+    }
+}
+
+fn block_chip(ui: &mut egui::Ui, localization: &Localization, block: &Block) {
+    Frame::group(ui.style())
+        .fill(block_color(block))
+        .inner_margin(Margin::symmetric(6, 2))
+        .show(ui, |ui| {
+            ui.label(
+                RichText::new(block_label(localization, block))
+                    .color(Color32::WHITE)
+                    .strong(),
+            );
+        });
+}
+
+fn drop_edit(payload: Option<Arc<BlockDrag>>, at: BlockPath) -> Option<BlockEdit> {
+    Some(match payload?.as_ref() {
+        BlockDrag::New(block) => BlockEdit::Insert(at, block.clone()),
+        BlockDrag::Existing(from) => BlockEdit::Move(from.clone(), at),
+    })
+}
+
+fn block_label(localization: &Localization, block: &Block) -> String {
+    let key = match block {
+        Block::Move(_) => "stage-ui-blocks-move",
+        Block::Dig(_) => "stage-ui-blocks-dig",
+        Block::Sleep(_) => "stage-ui-blocks-sleep",
+        Block::Loop(_) => "stage-ui-blocks-loop",
+        Block::IfTouched(_) => "stage-ui-blocks-if-touched",
+        Block::IfEmpty(_, _) => "stage-ui-blocks-if-empty",
+    };
+    tr(localization, key)
+}
+
+fn block_color(block: &Block) -> Color32 {
+    match block {
+        Block::Move(_) | Block::Dig(_) => Color32::from_rgb(0x3c, 0x6e, 0xc8),
+        Block::Sleep(_) => Color32::from_rgb(0x8a, 0x5c, 0xc0),
+        Block::Loop(_) => Color32::from_rgb(0xd8, 0x8a, 0x28),
+        Block::IfTouched(_) | Block::IfEmpty(_, _) => Color32::from_rgb(0x3a, 0x9a, 0x5a),
+    }
+}
+
+fn direction_label(localization: &Localization, dir: MoveDirection) -> String {
+    let key = match dir {
+        MoveDirection::Top => "stage-ui-blocks-direction-up",
+        MoveDirection::Down => "stage-ui-blocks-direction-down",
+        MoveDirection::Left => "stage-ui-blocks-direction-left",
+        MoveDirection::Right => "stage-ui-blocks-direction-right",
+    };
+    tr(localization, key)
+}
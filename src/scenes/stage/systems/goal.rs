@@ -66,7 +66,7 @@ pub fn check_goal_completion(
     mut player_query: Query<GoalCheckPlayer<'_>, With<Player>>,
     goals: Query<(&Transform, &Goal)>,
     tiles: Query<&GlobalTransform, With<StageTile>>,
-    localization: Res<Localization>,
+    localization: Option<Res<Localization>>,
     audio_handles: Res<StageAudioHandles>,
     mut audio_state: ResMut<StageAudioState>,
     settings: Res<GameSettings>,
@@ -107,7 +107,9 @@ pub fn check_goal_completion(
         editor_state.controls_enabled = false;
         editor_state.stage_cleared = true;
         editor_state.pending_player_reset = false;
-        editor_state.last_run_feedback = Some(tr(&localization, "stage-ui-feedback-goal"));
+        if let Some(localization) = localization.as_ref() {
+            editor_state.last_run_feedback = Some(tr(localization, "stage-ui-feedback-goal"));
+        }
         editor_state.stage_clear_popup_open = false;
         audio_state.play_clear_once(&mut commands, &audio_handles, settings.sfx_volume_linear());
 
@@ -1,4 +1,5 @@
 mod audio;
+mod block_editor;
 mod goal;
 mod obstacle;
 mod player;
@@ -20,19 +21,21 @@ use crate::{
     MainCamera,
     resources::{
         asset_store::AssetStore,
-        chunk_grammar_map::{self, Map, TileKind},
+        chunk_grammar_map::{self, Map, MapGenError, TileKind},
         design_resolution::{LetterboxOffsets, ScaledViewport},
         file_storage::FileStorageResource,
         settings::GameSettings,
+        solution_metrics::SolutionRating,
         stage_catalog::*,
         stage_progress::StageProgress,
         stage_scripts::StageScripts,
+        stone_type::StoneCapabilities,
         tiled::TiledMapAssets,
     },
     scenes::{assets::AudioKey, stage::components::StageTile},
     util::localization::{localized_stage_name, tr, tr_with_args},
 };
-use audio::{StageAudioHandles, StageAudioState};
+pub use audio::{StageAudioHandles, StageAudioState};
 
 pub use goal::check_goal_completion;
 pub use obstacle::*;
@@ -42,8 +45,8 @@ pub use stone::{
     handle_stone_append_messages, handle_stone_messages, reset_stone_position,
     update_stone_behavior,
 };
-use ui::{ScriptEditorState, StageTutorialOverlay};
-pub use ui::{handle_tutorial_overlay_input, tick_script_program, ui};
+use ui::StageTutorialOverlay;
+pub use ui::{ScriptEditorState, handle_tutorial_overlay_input, tick_script_program, ui};
 
 pub use tiles::restore_dug_tiles;
 
@@ -51,18 +54,22 @@ pub use tiles::restore_dug_tiles;
 pub struct StageProgressionState {
     current_stage: Option<StageMeta>,
     pending_reload: bool,
+    /// Layout seed of the current attempt.
+    map_seed: u64,
+    /// Seed for the next attempt instead of a random one, from `--seed`.
+    next_map_seed: Option<u64>,
 }
 
 impl StageProgressionState {
-    pub fn current_map(&self) -> Map {
+    pub fn current_map(&self, stones: &StoneCapabilities) -> Result<Map, MapGenError> {
         let current_stage = self.current_stage.as_ref().expect("no current stage");
-        let map = current_stage.load_map();
-        for chunk in &map.placed_chunks {
-            println!("- {}", chunk.id);
-        }
-        println!();
-        chunk_grammar_map::print_ascii_map(&map);
-        map
+        let map = current_stage.load_map(self.map_seed, stones)?;
+        debug!(
+            "placed chunks:\n{}\n{}",
+            chunk_grammar_map::placed_chunk_list(&map),
+            chunk_grammar_map::ascii_map(&map)
+        );
+        Ok(map)
     }
 
     pub fn current_stage_id(&self) -> StageId {
@@ -87,12 +94,32 @@ impl StageProgressionState {
         };
 
         self.current_stage = Some(next_stage.clone());
-        self.pending_reload = true;
+        self.start_attempt();
         true
     }
 
     pub fn select_stage(&mut self, stage: &StageMeta) {
         self.current_stage = Some(stage.clone());
+        self.start_attempt();
+    }
+
+    pub fn map_seed(&self) -> u64 {
+        self.map_seed
+    }
+
+    /// Uses `seed` for the next stage that starts.
+    pub fn set_next_map_seed(&mut self, seed: u64) {
+        self.next_map_seed = Some(seed);
+    }
+
+    /// Rebuilds the current stage from `seed`.
+    pub fn reload_with_seed(&mut self, seed: u64) {
+        self.map_seed = seed;
+        self.pending_reload = true;
+    }
+
+    fn start_attempt(&mut self) {
+        self.map_seed = self.next_map_seed.take().unwrap_or_else(rand::random);
         self.pending_reload = true;
     }
 
@@ -126,7 +153,8 @@ fn compute_stage_root_translation(viewport: &ScaledViewport, window_size: Vec2)
     Vec3::new(translation.x, translation.y, 1.0)
 }
 
-fn spawn_stage(
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn spawn_stage(
     commands: &mut Commands,
     transform: Transform,
     map_assets: &TiledMapAssets,
@@ -135,6 +163,7 @@ fn spawn_stage(
     asset_store: &AssetStore,
     asset_server: &AssetServer,
     atlas_layouts: &mut Assets<TextureAtlasLayout>,
+    stones: &StoneCapabilities,
 ) -> Entity {
     let stage_root = commands
         .spawn((
@@ -154,6 +183,7 @@ fn spawn_stage(
         asset_store,
         asset_server,
         atlas_layouts,
+        stones,
     );
 
     stage_root
@@ -168,6 +198,7 @@ fn populate_stage_contents(
     asset_store: &AssetStore,
     asset_server: &AssetServer,
     atlas_layouts: &mut Assets<TextureAtlasLayout>,
+    stones: &StoneCapabilities,
 ) {
     tiles::spawn_tiles(commands, stage_root, tiled_map_assets, map, viewport);
 
@@ -186,17 +217,24 @@ fn populate_stage_contents(
         viewport.scale,
     );
 
-    let stone_position = map.tile_position(TileKind::Stone);
-    stone::spawn_stone(
-        commands,
-        stage_root,
-        asset_server,
-        atlas_layouts,
-        tile_position_to_world(stone_position, real_tile_size, viewport_size, scale, 0.0),
-        map.stone_type,
-        map.dig_limit,
-        stone::STONE_STEP_DISTANCE,
-    );
+    for (index, stone_position) in map.stone_positions().into_iter().enumerate() {
+        let stone_type = map.stone_type_at(index);
+        // Map generation rejects undefined stone types, so this only guards against a mismatch.
+        let Some(definition) = stones.definition(&stone_type) else {
+            warn!("Stone type {stone_type} is not defined in stones.ron");
+            continue;
+        };
+        stone::spawn_stone(
+            commands,
+            stage_root,
+            asset_server,
+            atlas_layouts,
+            tile_position_to_world(stone_position, real_tile_size, viewport_size, scale, 0.0),
+            StoneIndex(index),
+            definition,
+            map.dig_limit,
+        );
+    }
 
     map.tile_positions(TileKind::Obstacle)
         .iter()
@@ -294,12 +332,14 @@ fn cleanup_stage_entities(
 pub struct StageSetupParams<'w, 's> {
     asset_store: Res<'w, AssetStore>,
     tiled_map_assets: Res<'w, TiledMapAssets>,
+    stones: Res<'w, StoneCapabilities>,
     viewport: Res<'w, ScaledViewport>,
     letterbox_offsets: Res<'w, LetterboxOffsets>,
     asset_server: Res<'w, AssetServer>,
     atlas_layouts: ResMut<'w, Assets<TextureAtlasLayout>>,
     window_query: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
     progression: ResMut<'w, StageProgressionState>,
+    stage_progress: Option<ResMut<'w, StageProgress>>,
     editor_state: Option<ResMut<'w, ScriptEditorState>>,
     stage_scripts: Option<Res<'w, StageScripts>>,
     audio_handles: Option<Res<'w, StageAudioHandles>>,
@@ -308,6 +348,10 @@ pub struct StageSetupParams<'w, 's> {
     settings: Res<'w, GameSettings>,
 }
 
+/// Why the current stage could not be built; the stage UI shows it in place of the stage.
+#[derive(Resource)]
+pub struct StageMapError(pub MapGenError);
+
 #[derive(Resource)]
 pub struct PendingTutorial {
     pub dialog: ui::TutorialDialog,
@@ -353,8 +397,23 @@ pub fn setup(mut commands: Commands, mut params: StageSetupParams) {
     let saved_code = params
         .stage_scripts
         .as_ref()
-        .and_then(|scripts| scripts.stage_code(current_lang, current_stage_id))
+        .and_then(|scripts| scripts.stage_code(current_lang, current_stage_id, 0))
         .map(|s| s.to_string());
+    let current_map = match params.progression.current_map(params.stones.as_ref()) {
+        Ok(map) => {
+            record_map_seed(params.stage_progress.as_mut(), current_stage_id, map.seed);
+            commands.remove_resource::<StageMapError>();
+            Some(map)
+        }
+        Err(err) => {
+            warn!("Stage setup: failed to build stage {current_stage_id:?}: {err}");
+            commands.insert_resource(StageMapError(err));
+            None
+        }
+    };
+    let (stage_par, sandbox) = current_map
+        .as_ref()
+        .map_or_else(Default::default, |map| (map.par, map.sandbox));
     match params.editor_state.as_deref_mut() {
         Some(editor) => {
             editor.set_tutorial_for_stage(current_stage_id);
@@ -363,14 +422,23 @@ pub fn setup(mut commands: Commands, mut params: StageSetupParams) {
             editor.pending_player_reset = false;
             editor.stage_cleared = false;
             editor.stage_clear_popup_open = false;
-            editor.active_program = None;
+            editor.active_programs.clear();
+            editor.selected_stone = 0;
+            editor.stage_par = stage_par;
+            editor.sandbox = sandbox;
             if let Some(code) = &saved_code {
                 editor.buffer = code.clone();
             } else {
                 editor.buffer.clear();
             }
         }
-        None => ui::init_editor_state(&mut commands, current_stage_id, saved_code),
+        None => ui::init_editor_state(
+            &mut commands,
+            current_stage_id,
+            saved_code,
+            stage_par,
+            sandbox,
+        ),
     }
 
     if params.audio_handles.is_none() {
@@ -390,7 +458,10 @@ pub fn setup(mut commands: Commands, mut params: StageSetupParams) {
         commands.insert_resource(StageAudioState::default());
     }
 
-    let current_map = params.progression.current_map();
+    let Some(current_map) = current_map else {
+        params.progression.clear_reload();
+        return;
+    };
 
     let Some(window) = params.window_query.iter().next() else {
         warn!("Stage setup: primary window not available");
@@ -409,6 +480,7 @@ pub fn setup(mut commands: Commands, mut params: StageSetupParams) {
         params.asset_store.as_ref(),
         params.asset_server.as_ref(),
         params.atlas_layouts.as_mut(),
+        params.stones.as_ref(),
     );
 
     let tutorial_dialog = params
@@ -432,6 +504,16 @@ pub fn setup(mut commands: Commands, mut params: StageSetupParams) {
     params.progression.clear_reload();
 }
 
+/// `persist_stage_progress` saves the seed once the progress changes, so a repeated seed leaves
+/// the resource untouched.
+fn record_map_seed(progress: Option<&mut ResMut<StageProgress>>, stage_id: StageId, seed: u64) {
+    if let Some(progress) = progress
+        && progress.map_seed(stage_id) != Some(seed)
+    {
+        progress.record_map_seed(stage_id, seed);
+    }
+}
+
 pub fn cleanup(
     mut commands: Commands,
     stage_roots: Query<Entity, With<StageRoot>>,
@@ -453,6 +535,7 @@ pub fn cleanup(
         &tutorial_overlays,
     );
     commands.remove_resource::<StageAudioState>();
+    commands.remove_resource::<StageMapError>();
 }
 
 pub fn advance_stage_if_cleared(
@@ -469,6 +552,24 @@ pub fn advance_stage_if_cleared(
         return;
     }
 
+    let par = editor_state.stage_par;
+    editor_state.last_clear = match editor_state.run_metrics {
+        Some(metrics) => {
+            let rating = SolutionRating {
+                stars: par.rate(&metrics),
+                metrics,
+            };
+            info!("Stage cleared with {:?}", rating);
+            progress.record_solution(progression.current_stage_id(), rating);
+            Some((rating, par))
+        }
+        None => {
+            info!("Stage cleared without a rating");
+            None
+        }
+    };
+    editor_state.stage_clear_popup_open = true;
+
     if let (Some(scripts), Some(storage)) = (stage_scripts.as_ref(), storage.as_ref())
         && let Err(err) = scripts.persist(storage.backend().as_ref())
     {
@@ -508,8 +609,10 @@ pub struct StageReloadParams<'w, 's> {
     asset_server: Res<'w, AssetServer>,
     atlas_layouts: ResMut<'w, Assets<TextureAtlasLayout>>,
     tiled_map_assets: Res<'w, TiledMapAssets>,
+    stone_capabilities: Res<'w, StoneCapabilities>,
     window_query: Query<'w, 's, &'static Window, With<PrimaryWindow>>,
     progression: ResMut<'w, StageProgressionState>,
+    stage_progress: Option<ResMut<'w, StageProgress>>,
     storage: Option<Res<'w, FileStorageResource>>,
     stage_roots: Query<'w, 's, Entity, With<StageRoot>>,
     query: Query<'w, 's, Entity, StageCleanupFilter>,
@@ -534,12 +637,17 @@ pub fn reload_stage_if_needed(mut commands: Commands, mut params: StageReloadPar
         .current_stage()
         .map(|stage| localized_stage_name(&params.localization, stage.id, &stage.title))
         .unwrap_or_else(|| format!("STAGE-{}", stage_id.0));
-    let current_map = params.progression.current_map();
+    let current_map = params
+        .progression
+        .current_map(params.stone_capabilities.as_ref());
+    if let Ok(map) = &current_map {
+        record_map_seed(params.stage_progress.as_mut(), stage_id, map.seed);
+    }
     let lang = params.settings.script_language;
     let saved_code = params
         .stage_scripts
         .as_ref()
-        .and_then(|scripts| scripts.stage_code(lang, stage_id))
+        .and_then(|scripts| scripts.stage_code(lang, stage_id, 0))
         .map(|s| s.to_string());
 
     if let (Some(scripts), Some(storage)) = (params.stage_scripts.as_ref(), params.storage.as_ref())
@@ -564,6 +672,23 @@ pub fn reload_stage_if_needed(mut commands: Commands, mut params: StageReloadPar
         commands.insert_resource(StageAudioState::default());
     }
 
+    let current_map = match current_map {
+        Ok(map) => {
+            commands.remove_resource::<StageMapError>();
+            map
+        }
+        Err(err) => {
+            warn!("Stage reload: failed to build stage {stage_id:?}: {err}");
+            if let Some(editor) = params.editor_state.as_deref_mut() {
+                editor.controls_enabled = false;
+                editor.pending_player_reset = false;
+                editor.stage_cleared = false;
+            }
+            commands.insert_resource(StageMapError(err));
+            return;
+        }
+    };
+
     let Some(window) = params.window_query.iter().next() else {
         warn!("Stage reload: primary window not available");
         return;
@@ -581,6 +706,7 @@ pub fn reload_stage_if_needed(mut commands: Commands, mut params: StageReloadPar
         params.asset_store.as_ref(),
         params.asset_server.as_ref(),
         params.atlas_layouts.as_mut(),
+        params.stone_capabilities.as_ref(),
     );
 
     if let Some(editor) = params.editor_state.as_deref_mut() {
@@ -590,6 +716,9 @@ pub fn reload_stage_if_needed(mut commands: Commands, mut params: StageReloadPar
         editor.stage_cleared = false;
         editor.set_tutorial_for_stage(stage_id);
         editor.set_command_help_for_stage(stage_id);
+        editor.selected_stone = 0;
+        editor.stage_par = current_map.par;
+        editor.sandbox = current_map.sandbox;
         if let Some(code) = &saved_code {
             editor.buffer = code.clone();
         } else {
@@ -6,17 +6,23 @@ use bevy::prelude::*;
 use super::{StageAudioHandles, StageAudioState, ui::ScriptEditorState};
 use crate::{
     resources::{chunk_grammar_map::TileKind, settings::GameSettings},
-    scenes::stage::components::{DigLimit, Player, StageTile, StoneRune, StoneSpawnState},
+    scenes::stage::components::{
+        DigLimit, Player, StageTile, StoneIndex, StoneRune, StoneSpawnState,
+    },
     util::script_types::{MoveDirection, ScriptCommand},
 };
 
 #[derive(Message, Clone)]
 pub struct StoneCommandMessage {
+    /// `StoneIndex` of the stone whose queue is replaced.
+    pub stone: usize,
     pub commands: Vec<ScriptCommand>,
 }
 
 #[derive(Message, Clone)]
 pub struct StoneAppendCommandMessage {
+    /// `StoneIndex` of the stone the command is queued on.
+    pub stone: usize,
     pub command: ScriptCommand,
 }
 
@@ -26,6 +32,7 @@ pub(crate) struct StoneCommandState {
     current: Option<StoneAction>,
     cooldown: Timer,
     pub step_size: f32, // Dynamic step size based on map scale
+    move_duration: f32,
 }
 
 impl Default for StoneCommandState {
@@ -34,7 +41,8 @@ impl Default for StoneCommandState {
             queue: VecDeque::new(),
             current: None,
             cooldown: Timer::from_seconds(0.0, TimerMode::Once),
-            step_size: 32.0,
+            step_size: STONE_STEP_DISTANCE,
+            move_duration: STONE_MOVE_DURATION,
         }
     }
 }
@@ -78,7 +86,7 @@ const CARRY_VERTICAL_EPS: f32 = 3.0;
 const CARRY_X_MARGIN: f32 = 2.0;
 const STONE_ACTION_COOLDOWN: f32 = 0.2;
 
-use crate::resources::stone_type::StoneType;
+use crate::resources::stone_type::StoneDefinition;
 
 pub fn spawn_stone(
     commands: &mut Commands,
@@ -86,9 +94,9 @@ pub fn spawn_stone(
     asset_server: &AssetServer,
     layouts: &mut Assets<TextureAtlasLayout>,
     (object_x, object_y, _scale): (f32, f32, f32),
-    stone_type: StoneType,
+    index: StoneIndex,
+    definition: &StoneDefinition,
     dig_limit: Option<u32>,
-    step_size: f32,
 ) {
     let texture = asset_server.load(STONE_ATLAS_PATH);
     let layout = layouts.add(TextureAtlasLayout::from_grid(
@@ -99,14 +107,9 @@ pub fn spawn_stone(
         None,
     ));
 
-    let coord = match stone_type {
-        StoneType::Type1 => UVec2::new(2, 4),
-        StoneType::Type2 => UVec2::new(4, 4),
-        StoneType::Type3 => UVec2::new(2, 0),
-        StoneType::Type4 => UVec2::new(5, 0),
-    };
+    let coord = UVec2::new(definition.sprite.0, definition.sprite.1);
 
-    info!("stone_type: {:?}", stone_type);
+    info!("stone {}: stone_type: {}", index.0, definition.id);
 
     let tile_index = atlas_index(coord);
     let atlas = TextureAtlas {
@@ -117,6 +120,7 @@ pub fn spawn_stone(
     commands.entity(stage_root).with_children(|parent| {
         parent.spawn((
             StoneRune,
+            index,
             Sprite::from_atlas_image(texture, atlas),
             Transform::from_xyz(object_x, object_y, 1.0).with_scale(Vec3::splat(STONE_SCALE)),
             StoneSpawnState {
@@ -125,9 +129,10 @@ pub fn spawn_stone(
                 dig_limit,
             },
             DigLimit(dig_limit),
-            stone_type,
+            definition.id.clone(),
             StoneCommandState {
-                step_size,
+                step_size: definition.step_size,
+                move_duration: definition.move_duration,
                 ..default()
             },
             StoneMotion {
@@ -150,14 +155,14 @@ pub fn spawn_stone(
 
 pub fn handle_stone_messages(
     mut reader: MessageReader<StoneCommandMessage>,
-    mut query: Query<&mut StoneCommandState, With<StoneRune>>,
+    mut query: Query<(&StoneIndex, &mut StoneCommandState), With<StoneRune>>,
 ) {
-    let Some(mut state) = query.iter_mut().next() else {
-        return;
-    };
-
     for msg in reader.read() {
-        info!("Stone received command message");
+        let Some((_, mut state)) = query.iter_mut().find(|(index, _)| index.0 == msg.stone) else {
+            continue;
+        };
+
+        info!("Stone {} received command message", msg.stone);
         state.queue.clear();
         state.current = None;
         state.cooldown.reset(); // Stop cooldown immediately if we force a new program?
@@ -172,13 +177,13 @@ pub fn handle_stone_messages(
 
 pub fn handle_stone_append_messages(
     mut reader: MessageReader<StoneAppendCommandMessage>,
-    mut query: Query<&mut StoneCommandState, With<StoneRune>>,
+    mut query: Query<(&StoneIndex, &mut StoneCommandState), With<StoneRune>>,
 ) {
-    let Some(mut state) = query.iter_mut().next() else {
-        return;
-    };
-
     for msg in reader.read() {
+        let Some((_, mut state)) = query.iter_mut().find(|(index, _)| index.0 == msg.stone) else {
+            continue;
+        };
+
         state.queue.push_back(msg.command.clone());
         if matches!(msg.command, ScriptCommand::Move(_)) {
             // Move コマンドの直後に Sleep を追加することで障害物を貫通する問題を解消する
@@ -217,8 +222,11 @@ pub fn update_stone_behavior(
     mut query: StoneBehaviorQuery,
     query_colliders: Query<&Collider>,
     spatial: SpatialQuery,
+    mut editor: ResMut<ScriptEditorState>,
 ) {
-    let Some((
+    let mut any_stone_moving = false;
+
+    for (
         entity,
         mut state,
         mut transform,
@@ -227,86 +235,88 @@ pub fn update_stone_behavior(
         mut motion,
         mut dig_limit,
         _collisions,
-    )) = query.iter_mut().next()
-    else {
-        audio_state.stop_push_loop(&mut commands);
-        return;
-    };
-
-    // Tick cooldown
-    state.cooldown.tick(time.delta());
+    ) in query.iter_mut()
+    {
+        // Tick cooldown
+        state.cooldown.tick(time.delta());
 
-    // 前フレーム位置（ローカル空間）
-    let prev = motion.last;
+        // 前フレーム位置（ローカル空間）
+        let prev = motion.last;
 
-    if state.current.is_none()
+        if state.current.is_none()
         && state.cooldown.is_finished() // Only pop if cooldown is done
         && let Some(command) = state.queue.pop_front()
-    {
-        info!("Stone received command: {:?}", command);
-
-        state.current = Some(match command {
-            ScriptCommand::Move(direction) => {
-                let dir = direction_to_vec(direction);
+        {
+            info!("Stone received command: {:?}", command);
 
-                // Predictive raycast: check if path is blocked before moving
-                let ray_dir = Dir2::new(dir).unwrap_or(Dir2::X);
-                let origin = global_transform.translation().truncate();
-                let check_dist = STONE_RAYCAST_DISTANCE * global_transform.scale().x;
-                let filter =
-                    SpatialQueryFilter::from_mask(LayerMask::ALL).with_excluded_entities([entity]);
+            state.current = Some(match command {
+                ScriptCommand::Move(direction) => {
+                    let dir = direction_to_vec(direction);
 
-                let path_blocked = if let Some(hit) =
-                    spatial.cast_ray(origin, ray_dir, check_dist, true, &filter)
-                {
-                    tiles.get(hit.entity).is_ok()
-                } else {
-                    false
-                };
-
-                if path_blocked {
-                    // Path is blocked - skip this move, just do a tiny pause
-                    info!("Move blocked by tile, skipping");
-                    StoneAction::Sleep(Timer::from_seconds(0.05, TimerMode::Once))
-                } else {
-                    let offset = Vec3::new(dir.x, dir.y, 0.0) * state.step_size;
-                    let velocity = offset.truncate() / STONE_MOVE_DURATION;
-                    StoneAction::Move(MoveCommandProgress {
-                        velocity,
-                        timer: Timer::from_seconds(STONE_MOVE_DURATION, TimerMode::Once),
-                        moved_distance: 0.0,
-                        start_position: transform.translation,
-                    })
-                }
-            }
-            ScriptCommand::Sleep(seconds) => {
-                StoneAction::Sleep(Timer::from_seconds(seconds.max(0.0), TimerMode::Once))
-            }
-            ScriptCommand::Dig(direction) => match dig_limit.0 {
-                Some(0) => StoneAction::Sleep(Timer::from_seconds(0.1, TimerMode::Once)),
-                Some(_) | None => {
-                    let dir_vec = direction_to_vec(direction);
-                    let ray_dir = Dir2::new(dir_vec).unwrap_or(Dir2::X);
+                    // Predictive raycast: check if path is blocked before moving
+                    let ray_dir = Dir2::new(dir).unwrap_or(Dir2::X);
                     let origin = global_transform.translation().truncate();
-                    let max_dist = STONE_RAYCAST_DISTANCE * global_transform.scale().x;
-
+                    let check_dist = STONE_RAYCAST_DISTANCE * global_transform.scale().x;
                     let filter = SpatialQueryFilter::from_mask(LayerMask::ALL)
                         .with_excluded_entities([entity]);
 
-                    let hit = spatial.cast_ray(origin, ray_dir, max_dist, true, &filter);
+                    let path_blocked = if let Some(hit) =
+                        spatial.cast_ray(origin, ray_dir, check_dist, true, &filter)
+                    {
+                        tiles.get(hit.entity).is_ok()
+                    } else {
+                        false
+                    };
 
-                    if let Some(hit) = hit {
-                        if let Ok(kind) = tile_kinds.get(hit.entity) {
-                            if *kind == TileKind::Wall {
-                                info!("Hit a Wall, skipping dig");
-                                StoneAction::Dig(
-                                    Timer::from_seconds(0.5, TimerMode::Once),
-                                    Entity::PLACEHOLDER,
-                                )
+                    if path_blocked {
+                        // Path is blocked - skip this move, just do a tiny pause
+                        info!("Move blocked by tile, skipping");
+                        StoneAction::Sleep(Timer::from_seconds(0.05, TimerMode::Once))
+                    } else {
+                        let offset = Vec3::new(dir.x, dir.y, 0.0) * state.step_size;
+                        let velocity = offset.truncate() / state.move_duration;
+                        StoneAction::Move(MoveCommandProgress {
+                            velocity,
+                            timer: Timer::from_seconds(state.move_duration, TimerMode::Once),
+                            moved_distance: 0.0,
+                            start_position: transform.translation,
+                        })
+                    }
+                }
+                ScriptCommand::Sleep(seconds) => {
+                    StoneAction::Sleep(Timer::from_seconds(seconds.max(0.0), TimerMode::Once))
+                }
+                ScriptCommand::Dig(direction) => match dig_limit.0 {
+                    Some(0) => StoneAction::Sleep(Timer::from_seconds(0.1, TimerMode::Once)),
+                    Some(_) | None => {
+                        let dir_vec = direction_to_vec(direction);
+                        let ray_dir = Dir2::new(dir_vec).unwrap_or(Dir2::X);
+                        let origin = global_transform.translation().truncate();
+                        let max_dist = STONE_RAYCAST_DISTANCE * global_transform.scale().x;
+
+                        let filter = SpatialQueryFilter::from_mask(LayerMask::ALL)
+                            .with_excluded_entities([entity]);
+
+                        let hit = spatial.cast_ray(origin, ray_dir, max_dist, true, &filter);
+
+                        if let Some(hit) = hit {
+                            if let Ok(kind) = tile_kinds.get(hit.entity) {
+                                if *kind == TileKind::Wall {
+                                    info!("Hit a Wall, skipping dig");
+                                    StoneAction::Dig(
+                                        Timer::from_seconds(0.5, TimerMode::Once),
+                                        Entity::PLACEHOLDER,
+                                    )
+                                } else {
+                                    StoneAction::Dig(
+                                        Timer::from_seconds(0.5, TimerMode::Once),
+                                        hit.entity,
+                                    )
+                                }
                             } else {
                                 StoneAction::Dig(
                                     Timer::from_seconds(0.5, TimerMode::Once),
-                                    hit.entity,
+                                    Entity::PLACEHOLDER,
                                 )
                             }
                         } else {
@@ -315,147 +325,146 @@ pub fn update_stone_behavior(
                                 Entity::PLACEHOLDER,
                             )
                         }
-                    } else {
-                        StoneAction::Dig(
-                            Timer::from_seconds(0.5, TimerMode::Once),
-                            Entity::PLACEHOLDER,
-                        )
                     }
-                }
-            },
-        });
-    }
-
-    let mut stop_current = false;
-
-    if let Some(action) = state.current.as_mut() {
-        match action {
-            StoneAction::Move(progress) => {
-                progress.timer.tick(time.delta());
-                let world_scale = global_transform.scale().x;
-                progress.moved_distance +=
-                    progress.velocity.length() * time.delta_secs() * world_scale;
-                velocity.0 = progress.velocity * world_scale;
-                // Use shape cast to check for tile in the movement direction
-                // This casts a circle (same size as stone collider) to detect collisions properly
-                let dir = progress.velocity.normalize_or_zero();
-                let is_colliding = if dir.length_squared() > 0.0 {
-                    let ray_dir = Dir2::new(dir).unwrap_or(Dir2::X);
-                    let origin = global_transform.translation().truncate();
-                    let check_dist = STONE_COLLIDER_RADIUS * world_scale + 2.0;
-                    let filter = SpatialQueryFilter::from_mask(LayerMask::ALL)
-                        .with_excluded_entities([entity]);
+                },
+            });
+        }
 
-                    // Use shape cast with a circle matching the stone's collider
-                    let cast_shape = Collider::circle(STONE_COLLIDER_RADIUS * world_scale);
-                    let cast_config = ShapeCastConfig::from_max_distance(check_dist);
-                    let hit = spatial.cast_shape(
-                        &cast_shape,
-                        origin,
-                        0.0, // rotation
-                        ray_dir,
-                        &cast_config,
-                        &filter,
-                    );
-
-                    // Debug: visualize shape cast (only when render_physics is enabled)
-                    if launch_profile.render_physics {
-                        let end = origin + dir * check_dist;
-                        let color = if hit.is_some() {
-                            Color::srgb(1.0, 0.0, 0.0)
-                        } else {
-                            Color::srgb(0.0, 1.0, 0.0)
-                        };
-                        // Draw the circle shape at origin and destination
-                        gizmos.circle_2d(
-                            Isometry2d::from_translation(origin),
-                            STONE_COLLIDER_RADIUS * world_scale,
-                            color,
-                        );
-                        gizmos.circle_2d(
-                            Isometry2d::from_translation(end),
-                            STONE_COLLIDER_RADIUS * world_scale,
-                            color,
+        let mut stop_current = false;
+
+        if let Some(action) = state.current.as_mut() {
+            match action {
+                StoneAction::Move(progress) => {
+                    progress.timer.tick(time.delta());
+                    let world_scale = global_transform.scale().x;
+                    progress.moved_distance +=
+                        progress.velocity.length() * time.delta_secs() * world_scale;
+                    velocity.0 = progress.velocity * world_scale;
+                    // Use shape cast to check for tile in the movement direction
+                    // This casts a circle (same size as stone collider) to detect collisions properly
+                    let dir = progress.velocity.normalize_or_zero();
+                    let is_colliding = if dir.length_squared() > 0.0 {
+                        let ray_dir = Dir2::new(dir).unwrap_or(Dir2::X);
+                        let origin = global_transform.translation().truncate();
+                        let check_dist = STONE_COLLIDER_RADIUS * world_scale + 2.0;
+                        let filter = SpatialQueryFilter::from_mask(LayerMask::ALL)
+                            .with_excluded_entities([entity]);
+
+                        // Use shape cast with a circle matching the stone's collider
+                        let cast_shape = Collider::circle(STONE_COLLIDER_RADIUS * world_scale);
+                        let cast_config = ShapeCastConfig::from_max_distance(check_dist);
+                        let hit = spatial.cast_shape(
+                            &cast_shape,
+                            origin,
+                            0.0, // rotation
+                            ray_dir,
+                            &cast_config,
+                            &filter,
                         );
-                        gizmos.line_2d(origin, end, color);
-                    }
 
-                    hit.is_some_and(|h| tiles.get(h.entity).is_ok())
-                } else {
-                    false
-                };
+                        // Debug: visualize shape cast (only when render_physics is enabled)
+                        if launch_profile.render_physics {
+                            let end = origin + dir * check_dist;
+                            let color = if hit.is_some() {
+                                Color::srgb(1.0, 0.0, 0.0)
+                            } else {
+                                Color::srgb(0.0, 1.0, 0.0)
+                            };
+                            // Draw the circle shape at origin and destination
+                            gizmos.circle_2d(
+                                Isometry2d::from_translation(origin),
+                                STONE_COLLIDER_RADIUS * world_scale,
+                                color,
+                            );
+                            gizmos.circle_2d(
+                                Isometry2d::from_translation(end),
+                                STONE_COLLIDER_RADIUS * world_scale,
+                                color,
+                            );
+                            gizmos.line_2d(origin, end, color);
+                        }
 
-                if is_colliding
-                    && progress.moved_distance > STONE_COLLISION_GRACE_DISTANCE * world_scale
-                {
-                    info!(
-                        "Collision stop: moved_distance={}, grace={}, reverting to last safe",
-                        progress.moved_distance,
-                        STONE_COLLISION_GRACE_DISTANCE * world_scale
-                    );
-                    velocity.0 = Vec2::ZERO;
-                    stop_current = true;
-                    // Revert to last safe position (just before collision)
-                    transform.translation = progress.start_position;
-                } else if !is_colliding {
-                    // Update safe position to PREVIOUS frame's position
-                    // (current position might already be overlapping with tile)
-                    progress.start_position = prev;
-                }
-                if progress.timer.is_finished() {
-                    velocity.0 = Vec2::ZERO;
-                    stop_current = true;
-                }
-            }
-            StoneAction::Sleep(timer) => {
-                if timer.tick(time.delta()).is_finished() {
-                    velocity.0 = Vec2::ZERO;
-                    stop_current = true;
+                        hit.is_some_and(|h| tiles.get(h.entity).is_ok())
+                    } else {
+                        false
+                    };
+
+                    if is_colliding
+                        && progress.moved_distance > STONE_COLLISION_GRACE_DISTANCE * world_scale
+                    {
+                        info!(
+                            "Collision stop: moved_distance={}, grace={}, reverting to last safe",
+                            progress.moved_distance,
+                            STONE_COLLISION_GRACE_DISTANCE * world_scale
+                        );
+                        velocity.0 = Vec2::ZERO;
+                        stop_current = true;
+                        // Revert to last safe position (just before collision)
+                        transform.translation = progress.start_position;
+                    } else if !is_colliding {
+                        // Update safe position to PREVIOUS frame's position
+                        // (current position might already be overlapping with tile)
+                        progress.start_position = prev;
+                    }
+                    if progress.timer.is_finished() {
+                        velocity.0 = Vec2::ZERO;
+                        stop_current = true;
+                    }
                 }
-            }
-            StoneAction::Dig(timer, entity) => {
-                if timer.tick(time.delta()).is_finished() {
-                    if let Some(count) = dig_limit.0 {
-                        dig_limit.0 = Some(count.saturating_sub(1));
+                StoneAction::Sleep(timer) => {
+                    if timer.tick(time.delta()).is_finished() {
+                        velocity.0 = Vec2::ZERO;
+                        stop_current = true;
                     }
-                    if let Ok(collider) = query_colliders.get(*entity) {
-                        commands
-                            .entity(*entity)
-                            .remove::<Collider>()
-                            .insert(Visibility::Hidden)
-                            .insert(crate::scenes::stage::components::DugTile {
-                                collider: collider.clone(),
-                            });
-                    } else {
-                        // Fallback for non-colliding entities or if query fails (shouldn't happen for tiles)
-                        commands.entity(*entity).despawn();
+                }
+                StoneAction::Dig(timer, entity) => {
+                    if timer.tick(time.delta()).is_finished() {
+                        if let Some(count) = dig_limit.0 {
+                            dig_limit.0 = Some(count.saturating_sub(1));
+                        }
+                        if let Some(metrics) = editor.run_metrics.as_mut() {
+                            metrics.record_dig();
+                        }
+                        if let Ok(collider) = query_colliders.get(*entity) {
+                            commands
+                                .entity(*entity)
+                                .remove::<Collider>()
+                                .insert(Visibility::Hidden)
+                                .insert(crate::scenes::stage::components::DugTile {
+                                    collider: collider.clone(),
+                                });
+                        } else {
+                            // Fallback for non-colliding entities or if query fails (shouldn't happen for tiles)
+                            commands.entity(*entity).despawn();
+                        }
+                        // Play mining sound?
+                        velocity.0 = Vec2::ZERO;
+                        stop_current = true;
                     }
-                    // Play mining sound?
-                    velocity.0 = Vec2::ZERO;
-                    stop_current = true;
                 }
             }
         }
-    }
 
-    if stop_current {
-        state.current = None;
-        // Start cooldown
-        state.cooldown = Timer::from_seconds(STONE_ACTION_COOLDOWN, TimerMode::Once);
+        if stop_current {
+            state.current = None;
+            // Start cooldown
+            state.cooldown = Timer::from_seconds(STONE_ACTION_COOLDOWN, TimerMode::Once);
+        }
+
+        any_stone_moving |= matches!(state.current, Some(StoneAction::Move(_)));
+
+        // このフレームの移動デルタを保存（ローカル空間の delta）
+        let now = transform.translation;
+        let delta = now - prev;
+        motion.delta = delta.truncate();
+        motion.last = now;
     }
 
-    let is_stone_moving = matches!(state.current, Some(StoneAction::Move(_)));
-    if is_stone_moving {
+    if any_stone_moving {
         audio_state.ensure_push_loop(&mut commands, &audio_handles, settings.sfx_volume_linear());
     } else {
         audio_state.stop_push_loop(&mut commands);
     }
-
-    // このフレームの移動デルタを保存（ローカル空間の delta）
-    let now = transform.translation;
-    let delta = now - prev;
-    motion.delta = delta.truncate();
-    motion.last = now;
 }
 
 fn atlas_index(coord: UVec2) -> usize {
@@ -494,8 +503,8 @@ pub fn reset_stone_position(
         return;
     }
 
-    if let Ok((mut transform, mut state, mut motion, mut velocity, spawn, mut dig_limit)) =
-        query.single_mut()
+    for (mut transform, mut state, mut motion, mut velocity, spawn, mut dig_limit) in
+        query.iter_mut()
     {
         transform.translation = spawn.translation;
         transform.scale = Vec3::splat(spawn.scale);
@@ -508,9 +517,9 @@ pub fn reset_stone_position(
         velocity.0 = Vec2::ZERO;
 
         dig_limit.0 = spawn.dig_limit;
-
-        audio_state.stop_push_loop(&mut commands);
     }
+
+    audio_state.stop_push_loop(&mut commands);
 }
 
 #[allow(clippy::type_complexity)]
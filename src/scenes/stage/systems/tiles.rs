@@ -1,7 +1,8 @@
 use avian2d::prelude::*;
 use bevy::prelude::*;
 
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 use crate::{
     resources::{chunk_grammar_map::*, design_resolution::ScaledViewport, tiled::*},
@@ -12,7 +13,7 @@ const BACKGROUND_IDS: [u32; 16] = [
     251, 252, 253, 254, 268, 269, 270, 271, 285, 286, 287, 288, 302, 303, 304, 305,
 ];
 
-fn background_tile_id(rng: &mut rand::rngs::ThreadRng) -> u32 {
+fn background_tile_id(rng: &mut impl Rng) -> u32 {
     let index = rng.random_range(0..(BACKGROUND_IDS.len()));
     BACKGROUND_IDS[index]
 }
@@ -26,7 +27,8 @@ pub fn spawn_tiles(
 ) {
     let tileset = tiled_map_assets.tileset.clone();
 
-    let mut rng = rand::rng();
+    // Decorations follow the map seed too, so a reported layout looks the same when reloaded.
+    let mut rng = ChaCha8Rng::seed_from_u64(placed_chunks.seed);
 
     let (map_size_x, map_size_y) = placed_chunks.map_size;
 
@@ -9,8 +9,9 @@ use bevy_egui::{
     },
 };
 use bevy_fluent::prelude::Localization;
+use std::collections::{BTreeSet, VecDeque};
 
-use super::stone::StoneCommandState;
+use super::{block_editor, stone::StoneCommandState};
 use crate::scenes::stage::systems::StageProgressionState;
 use crate::{
     resources::{
@@ -18,8 +19,9 @@ use crate::{
         design_resolution::LetterboxOffsets,
         file_storage::FileStorageResource,
         game_state::GameState,
-        script_engine::{Language, ScriptExecutor},
-        settings::GameSettings,
+        script_engine::{Language, ScriptExecutor, ScriptWarning, lint, translate},
+        settings::{EditorMode, GameSettings},
+        solution_metrics::{SolutionMetrics, SolutionPar, SolutionRating},
         stage_catalog::StageId,
         stage_scripts::StageScripts,
         stone_type::{StoneCapabilities, StoneType},
@@ -30,13 +32,18 @@ use crate::{
         stage::{components::*, systems::*},
     },
     util::{
-        localization::{localized_stage_name, script_error_message, tr, tr_or, tr_with_args},
+        localization::{
+            localized_stage_name, script_error_message, script_warning_message, tr, tr_or,
+            tr_with_args,
+        },
         script_types::{
-            PLAYER_TOUCHED_STATE_KEY, RAND_STATE_KEY, ScriptProgram, ScriptState, ScriptStateValue,
+            DIGS_LEFT_STATE_KEY, GOAL_DX_STATE_KEY, GOAL_DY_STATE_KEY, PLAYER_TOUCHED_STATE_KEY,
+            POSITION_X_STATE_KEY, POSITION_Y_STATE_KEY, SandboxProfile, ScriptCommand,
+            ScriptProgram, ScriptState, ScriptStateValue, SourceLocation, steps_between,
+            stone_rand_seed,
         },
     },
 };
-use rand::Rng;
 
 #[derive(Clone, Debug)]
 pub struct TutorialDialog {
@@ -110,6 +117,12 @@ const FONT_OFFSET_STEP: f32 = 1.0;
 const FONT_OFFSET_MIN: f32 = -6.0;
 const FONT_OFFSET_MAX: f32 = 0.0;
 
+const CODE_GUTTER_WIDTH_EM: f32 = 1.6;
+const CODE_EDITOR_MARGIN: egui::Margin = egui::Margin::symmetric(4, 2);
+
+const CONSOLE_HEIGHT: f32 = 120.0;
+const CONSOLE_MAX_LINES: usize = 200;
+
 fn scaled_panel_font_size(base: f32, offset: f32) -> f32 {
     ((base + offset).max(4.0)) * 2.0
 }
@@ -120,7 +133,42 @@ pub struct ScriptEditorState {
     pub last_action: Option<EditorMenuAction>,
     pub last_action_context: bool,
     pub last_run_feedback: Option<String>,
-    pub active_program: Option<Box<dyn ScriptProgram>>,
+    /// Where the last compile error happened; underlined in the editor until the script changes.
+    pub error_location: Option<SourceLocation>,
+    pub scroll_to_error: bool,
+    /// 1-based source lines where a debug run pauses.
+    pub breakpoints: BTreeSet<usize>,
+    pub debug: Option<DebugSession>,
+    /// Sensor values fed to the running script on the last tick, shown in the inspector.
+    pub inspected_state: ScriptState,
+    /// Compile the next run's programs so the inspector can show their variables. Debug runs
+    /// always can.
+    pub inspect_variables: bool,
+    /// `StoneIndex` of the stone whose script is open in the editor.
+    pub selected_stone: usize,
+    /// Running programs indexed by `StoneIndex`; empty when no run is active.
+    pub active_programs: Vec<Box<dyn ScriptProgram>>,
+    /// Metrics of the current run, rated against `stage_par` when the goal is reached.
+    /// `None` when a script could not be measured, so the clear is not rated.
+    pub run_metrics: Option<SolutionMetrics>,
+    pub stage_par: SolutionPar,
+    /// Script limits of the current stage.
+    pub sandbox: SandboxProfile,
+    /// Rating of the last clear and the par it was measured against, shown in the clear popup.
+    pub last_clear: Option<(SolutionRating, SolutionPar)>,
+    /// Seed of the current or last run; the same seed, script and stage replay the same
+    /// random values in the same order.
+    pub run_seed: u64,
+    /// Reuse `run_seed` for the next run instead of drawing a new one.
+    pub seed_fixed: bool,
+    /// Text of the seed field; applied to `run_seed` whenever it parses as a `u64`.
+    pub run_seed_input: String,
+    /// Script `print` and `debug` output of the current run, capped at `CONSOLE_MAX_LINES`.
+    pub console: VecDeque<String>,
+    /// Pre-run warnings of the last run, with the `StoneIndex` of the script they came from.
+    pub lint_warnings: Vec<(usize, ScriptWarning)>,
+    /// Map seed typed into the stage overlay, loaded with the seed button.
+    pub map_seed_input: String,
     pub controls_enabled: bool,
     pub pending_player_reset: bool,
     pub stage_cleared: bool,
@@ -137,7 +185,24 @@ impl Default for ScriptEditorState {
             last_action: None,
             last_action_context: false,
             last_run_feedback: None,
-            active_program: None,
+            error_location: None,
+            scroll_to_error: false,
+            breakpoints: BTreeSet::new(),
+            debug: None,
+            inspected_state: ScriptState::default(),
+            inspect_variables: false,
+            selected_stone: 0,
+            active_programs: Vec::new(),
+            run_metrics: None,
+            stage_par: SolutionPar::default(),
+            sandbox: SandboxProfile::default(),
+            last_clear: None,
+            run_seed: 0,
+            seed_fixed: false,
+            run_seed_input: String::from("0"),
+            console: VecDeque::new(),
+            lint_warnings: Vec::new(),
+            map_seed_input: String::new(),
             controls_enabled: false,
             pending_player_reset: false,
             stage_cleared: false,
@@ -162,21 +227,73 @@ impl ScriptEditorState {
     pub fn set_command_help_for_stage(&mut self, stage_id: StageId) {
         self.command_help = command_help_for_stage(stage_id);
     }
+
+    /// Picks the seed of the next run, drawing a fresh one unless the player fixed it.
+    pub fn reseed_run(&mut self) {
+        if !self.seed_fixed {
+            self.run_seed = rand::random();
+        }
+        self.run_seed_input = self.run_seed.to_string();
+    }
+
+    /// Opens another stone's script. Breakpoints belong to the visible script, so they are dropped.
+    fn select_stone(&mut self, stone: usize, code: Option<&str>) {
+        self.selected_stone = stone;
+        self.buffer = code.unwrap_or_default().to_string();
+        self.breakpoints.clear();
+        self.error_location = None;
+        self.inspected_state.clear();
+    }
+}
+
+/// Step-through state of a debug run.
+pub struct DebugSession {
+    /// Pause before every command instead of only at breakpoints.
+    pub stepping: bool,
+    /// Command held back until the player steps or continues, with the stone it belongs to.
+    pub pending: Option<(usize, ScriptCommand)>,
+    /// Stone whose program `current_line` refers to.
+    pub current_stone: usize,
+    pub current_line: Option<usize>,
+    resume: bool,
+}
+
+impl DebugSession {
+    fn new() -> Self {
+        Self {
+            stepping: true,
+            pending: None,
+            current_stone: 0,
+            current_line: None,
+            resume: false,
+        }
+    }
+
+    fn release(&mut self, stepping: bool) {
+        self.stepping = stepping;
+        self.resume = self.pending.is_some();
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum EditorMenuAction {
     RunScript,
+    DebugScript,
+    StepScript,
+    ContinueScript,
     DecreaseFont,
     IncreaseFont,
     ToggleCommandHelp,
 }
 
 impl EditorMenuAction {
-    const ALL: [Self; 4] = [
+    const ALL: [Self; 7] = [
         Self::DecreaseFont,
         Self::IncreaseFont,
         Self::RunScript,
+        Self::DebugScript,
+        Self::StepScript,
+        Self::ContinueScript,
         Self::ToggleCommandHelp,
     ];
 
@@ -186,16 +303,32 @@ impl EditorMenuAction {
             Self::IncreaseFont => "stage-ui-menu-font-increase",
             Self::RunScript if is_running => "stage-ui-menu-stop",
             Self::RunScript => "stage-ui-menu-run",
+            Self::DebugScript => "stage-ui-menu-debug",
+            Self::StepScript => "stage-ui-menu-step",
+            Self::ContinueScript => "stage-ui-menu-continue",
             Self::ToggleCommandHelp => "stage-ui-command-help-button",
         }
     }
 
+    /// Debug is offered while idle; Step and Continue only during a debug run. Keystone programs
+    /// report no source lines, so only Rhai scripts can be debugged.
+    fn is_available(self, editor: &ScriptEditorState, language: Language) -> bool {
+        match self {
+            Self::DebugScript => language == Language::Rhai && !editor.controls_enabled,
+            Self::StepScript | Self::ContinueScript => editor.debug.is_some(),
+            _ => true,
+        }
+    }
+
     fn key_text(self) -> Option<&'static str> {
         match self {
             Self::DecreaseFont => Some("F1"),
             Self::IncreaseFont => Some("F2"),
             Self::RunScript => Some("F3"),
             Self::ToggleCommandHelp => Some("F4"),
+            Self::DebugScript => Some("F5"),
+            Self::StepScript => Some("F6"),
+            Self::ContinueScript => Some("F7"),
         }
     }
 
@@ -205,13 +338,76 @@ impl EditorMenuAction {
             Self::IncreaseFont => Some(egui::Key::F2),
             Self::RunScript => Some(egui::Key::F3),
             Self::ToggleCommandHelp => Some(egui::Key::F4),
+            Self::DebugScript => Some(egui::Key::F5),
+            Self::StepScript => Some(egui::Key::F6),
+            Self::ContinueScript => Some(egui::Key::F7),
         }
     }
 }
 
-pub fn init_editor_state(commands: &mut Commands, stage_id: StageId, saved_code: Option<String>) {
+/// Seed of the current layout, and a field to rebuild the stage from a reported seed.
+fn show_map_seed(
+    ui: &mut egui::Ui,
+    localization: &Localization,
+    editor: &mut ScriptEditorState,
+    progression: &mut StageProgressionState,
+    font_id: FontId,
+    color: egui::Color32,
+) {
+    let seed = progression.map_seed().to_string();
+    ui.label(
+        RichText::new(tr_with_args(
+            localization,
+            "stage-ui-map-seed",
+            &[("seed", seed.as_str())],
+        ))
+        .font(font_id.clone())
+        .color(color),
+    );
+
+    ui.horizontal(|ui| {
+        ui.add_enabled_ui(!editor.controls_enabled, |ui| {
+            let input = ui.add(
+                egui::TextEdit::singleline(&mut editor.map_seed_input)
+                    .hint_text(seed.as_str())
+                    .font(font_id)
+                    .desired_width(96.0),
+            );
+            let load = ui.button(tr(localization, "stage-ui-map-seed-load"));
+            let submitted =
+                input.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter));
+            if !load.clicked() && !submitted {
+                return;
+            }
+            match editor.map_seed_input.trim().parse::<u64>() {
+                Ok(seed) => {
+                    info!("Reloading stage with map seed {seed}");
+                    editor.map_seed_input.clear();
+                    progression.reload_with_seed(seed);
+                }
+                Err(_) => {
+                    editor.last_run_feedback = Some(tr_with_args(
+                        localization,
+                        "stage-ui-map-seed-invalid",
+                        &[("input", editor.map_seed_input.trim())],
+                    ));
+                }
+            }
+        });
+    });
+}
+
+pub fn init_editor_state(
+    commands: &mut Commands,
+    stage_id: StageId,
+    saved_code: Option<String>,
+    stage_par: SolutionPar,
+    sandbox: SandboxProfile,
+) {
     let mut editor_state = ScriptEditorState {
         buffer: saved_code.unwrap_or_default(),
+        stage_par,
+        sandbox,
         ..default()
     };
     editor_state.set_tutorial_for_stage(stage_id);
@@ -232,12 +428,12 @@ pub struct StageUIParams<'w, 's> {
     audio: Res<'w, AudioHandles>,
     settings: Res<'w, GameSettings>,
     stage_scripts: ResMut<'w, StageScripts>,
-    progression: Res<'w, StageProgressionState>,
+    progression: ResMut<'w, StageProgressionState>,
     tutorial_overlays: Query<'w, 's, Entity, With<StageTutorialOverlay>>,
     stone_capabilities: Res<'w, StoneCapabilities>,
-    stone_query:
-        Query<'w, 's, (Entity, &'static GlobalTransform, &'static StoneType), With<StoneRune>>,
+    stone_query: Query<'w, 's, (&'static StoneIndex, &'static StoneType), With<StoneRune>>,
     file_storage: Res<'w, FileStorageResource>,
+    map_error: Option<Res<'w, StageMapError>>,
 }
 
 pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
@@ -253,11 +449,12 @@ pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
         audio,
         settings,
         mut stage_scripts,
-        progression,
+        mut progression,
         tutorial_overlays,
         stone_capabilities,
         stone_query,
         file_storage,
+        map_error,
     } = params;
 
     let Ok(ctx) = contexts.ctx_mut() else {
@@ -355,6 +552,15 @@ pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
                     ui.spacing_mut().item_spacing.x = 8.0;
 
                     for action in EditorMenuAction::ALL {
+                        // Shown but disabled, so Keystone players learn why they cannot debug.
+                        let keystone_debug = action == EditorMenuAction::DebugScript
+                            && settings.script_language == Language::Keystone
+                            && !editor.controls_enabled;
+                        if !keystone_debug
+                            && !action.is_available(&editor, settings.script_language)
+                        {
+                            continue;
+                        }
                         let button_label =
                             tr(&localization, action.label_key(editor.controls_enabled));
                         let label = if let Some(key_text) = action.key_text() {
@@ -362,6 +568,14 @@ pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
                         } else {
                             button_label
                         };
+                        if keystone_debug {
+                            ui.add_enabled(false, egui::Button::new(label))
+                                .on_disabled_hover_text(tr(
+                                    &localization,
+                                    "stage-ui-debug-rhai-only",
+                                ));
+                            continue;
+                        }
                         if ui.button(label).clicked() {
                             play_ui_click(&mut commands, &audio, &settings);
                             pending_action = Some((action, true));
@@ -369,16 +583,19 @@ pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
                     }
                 });
 
-                if let Some((action, triggered_via_ui)) = pending_action {
+                if let Some((action, triggered_via_ui)) = pending_action
+                    .filter(|(action, _)| action.is_available(&editor, settings.script_language))
+                {
                     if !triggered_via_ui {
                         play_ui_click(&mut commands, &audio, &settings);
                     }
                     let was_running = editor.controls_enabled;
                     let mut action_context_flag = false;
                     match action {
-                        EditorMenuAction::RunScript => {
+                        EditorMenuAction::RunScript | EditorMenuAction::DebugScript => {
                             if was_running {
                                 info!("Stopping script execution");
+                                editor.debug = None;
                                 editor.controls_enabled = false;
                                 editor.pending_player_reset = true;
                                 editor.last_run_feedback =
@@ -388,23 +605,79 @@ pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
                             } else {
                                 hide_tutorial_overlays(&mut commands, &tutorial_overlays);
                                 let language = settings.script_language;
-                                let stone_type = stone_query
+                                let stage_id = progression.current_stage_id();
+                                let mut stones = stone_query
                                     .iter()
-                                    .next()
-                                    .map(|(_, _, type_)| *type_)
-                                    .unwrap_or(StoneType::Type1); // Correctly query StoneType
-
-                                let allowed_commands =
-                                    stone_capabilities.get_capabilities(stone_type);
-
-                                match script_executor.compile_step(
-                                    language,
-                                    &editor.buffer,
-                                    allowed_commands,
-                                ) {
-                                    Ok(program) => {
-                                        info!("Starting script execution:\n{}", editor.buffer);
+                                    .map(|(index, type_)| (index.0, type_.clone()))
+                                    .collect::<Vec<_>>();
+                                stones.sort_by_key(|(index, _)| *index);
+
+                                // Every stone runs its own program; Keystone signals are shared.
+                                script_executor.clear_signals();
+                                script_executor.set_inspection(
+                                    editor.inspect_variables
+                                        || action == EditorMenuAction::DebugScript,
+                                );
+                                editor.reseed_run();
+                                let mut programs = Vec::with_capacity(stones.len());
+                                let mut metrics = Some(SolutionMetrics::default());
+                                let mut warnings = Vec::new();
+                                let mut failure = None;
+                                for (index, stone_type) in stones {
+                                    let allowed_commands =
+                                        stone_capabilities.get_capabilities(&stone_type);
+                                    let source = match settings.editor_mode {
+                                        EditorMode::Text if index == editor.selected_stone => {
+                                            editor.buffer.clone()
+                                        }
+                                        EditorMode::Text => stage_scripts
+                                            .stage_code(language, stage_id, index)
+                                            .unwrap_or_default()
+                                            .to_string(),
+                                        EditorMode::Blocks => stage_scripts
+                                            .stage_blocks(stage_id, index)
+                                            .map(|program| program.to_source(language))
+                                            .unwrap_or_default(),
+                                    };
+
+                                    warnings.extend(
+                                        lint(&source, language, allowed_commands)
+                                            .into_iter()
+                                            .map(|warning| (index, warning)),
+                                    );
+                                    match script_executor.compile_step(
+                                        language,
+                                        &source,
+                                        allowed_commands,
+                                        &editor.sandbox,
+                                        stone_rand_seed(editor.run_seed, index),
+                                    ) {
+                                        Ok(program) => {
+                                            info!(
+                                                "Starting script execution for stone {}:\n{}",
+                                                index, source
+                                            );
+                                            let measured = metrics.as_mut().map(|metrics| {
+                                                metrics.add_source(&source, language)
+                                            });
+                                            if let Some(Err(err)) = measured {
+                                                warn!(
+                                                    "Stone {index} cannot be measured, this run will not be rated: {err}"
+                                                );
+                                                metrics = None;
+                                            }
+                                            programs.push(program);
+                                        }
+                                        Err(err) => {
+                                            failure = Some((index, err));
+                                            break;
+                                        }
+                                    }
+                                }
+                                editor.lint_warnings = warnings;
 
+                                match failure {
+                                    None => {
                                         // Persist script on run
                                         if let Err(err) =
                                             stage_scripts.persist(file_storage.backend().as_ref())
@@ -412,11 +685,21 @@ pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
                                             warn!("Failed to persist stage scripts on run: {err}");
                                         }
 
-                                        // Clear any existing queue on the Stone
-                                        stone_writer
-                                            .write(StoneCommandMessage { commands: vec![] });
+                                        // Clear any existing queue on the stones
+                                        for stone in 0..programs.len() {
+                                            stone_writer.write(StoneCommandMessage {
+                                                stone,
+                                                commands: vec![],
+                                            });
+                                        }
 
-                                        editor.active_program = Some(program);
+                                        editor.active_programs = programs;
+                                        editor.run_metrics = metrics;
+                                        editor.console.clear();
+                                        editor.debug = (action == EditorMenuAction::DebugScript)
+                                            .then(DebugSession::new);
+                                        editor.error_location = None;
+                                        editor.inspected_state.clear();
                                         editor.last_run_feedback = Some(tr(
                                             &localization,
                                             "stage-ui-feedback-step-started",
@@ -426,10 +709,18 @@ pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
                                         editor.stage_cleared = false;
                                         editor.stage_clear_popup_open = false;
                                     }
-                                    Err(err) => {
-                                        editor.active_program = None;
+                                    Some((index, err)) => {
+                                        // Open the failing script so the error can be underlined.
+                                        if index != editor.selected_stone {
+                                            let code =
+                                                stage_scripts.stage_code(language, stage_id, index);
+                                            editor.select_stone(index, code);
+                                        }
+                                        editor.active_programs.clear();
                                         editor.last_run_feedback =
                                             Some(script_error_message(&localization, &err));
+                                        editor.error_location = err.location();
+                                        editor.scroll_to_error = editor.error_location.is_some();
                                         info!("Script compilation error: {}", err);
                                         editor.controls_enabled = false;
                                         editor.pending_player_reset = false;
@@ -441,6 +732,16 @@ pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
                             }
                             action_context_flag = was_running;
                         }
+                        EditorMenuAction::StepScript => {
+                            if let Some(debug) = editor.debug.as_mut() {
+                                debug.release(true);
+                            }
+                        }
+                        EditorMenuAction::ContinueScript => {
+                            if let Some(debug) = editor.debug.as_mut() {
+                                debug.release(false);
+                            }
+                        }
                         EditorMenuAction::DecreaseFont => {
                             editor.font_offset = (editor.font_offset - FONT_OFFSET_STEP)
                                 .clamp(FONT_OFFSET_MIN, FONT_OFFSET_MAX);
@@ -469,9 +770,115 @@ pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
                 if let Some(feedback) = &editor.last_run_feedback {
                     ui.label(feedback);
                 }
+                show_lint_warnings(ui, &localization, &editor);
+
+                ui.add_enabled_ui(!editor.controls_enabled, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(tr(&localization, "stage-ui-seed"));
+                        let seed = ui.add(
+                            egui::TextEdit::singleline(&mut editor.run_seed_input)
+                                .desired_width(160.0),
+                        );
+                        if seed.changed()
+                            && let Ok(run_seed) = editor.run_seed_input.trim().parse::<u64>()
+                        {
+                            editor.run_seed = run_seed;
+                            editor.seed_fixed = true;
+                        }
+                        ui.checkbox(
+                            &mut editor.seed_fixed,
+                            tr(&localization, "stage-ui-seed-fixed"),
+                        )
+                        .on_hover_text(tr(&localization, "stage-ui-seed-fixed-hint"));
+                    });
+                    // Keystone programs cannot report variables, so only Rhai offers this.
+                    if settings.script_language == Language::Rhai {
+                        ui.checkbox(
+                            &mut editor.inspect_variables,
+                            tr(&localization, "stage-ui-inspect-variables"),
+                        )
+                        .on_hover_text(tr(&localization, "stage-ui-inspect-variables-hint"));
+                    }
+                });
+
+                if editor.controls_enabled {
+                    show_script_inspector(ui, &localization, &editor, settings.script_language);
+                }
+
+                // Offer to carry a solution over from the other language into an empty editor.
+                if settings.editor_mode == EditorMode::Text
+                    && !editor.controls_enabled
+                    && editor.buffer.trim().is_empty()
+                {
+                    let stage_id = progression.current_stage_id();
+                    let language = settings.script_language;
+                    let other = match language {
+                        Language::Rhai => Language::Keystone,
+                        Language::Keystone => Language::Rhai,
+                    };
+                    let stone = editor.selected_stone;
+                    if let Some(other_code) = stage_scripts
+                        .stage_code(other, stage_id, stone)
+                        .filter(|code| !code.trim().is_empty())
+                        .map(str::to_string)
+                    {
+                        let from_name = tr(&localization, language_label_key(other));
+                        let to_name = tr(&localization, language_label_key(language));
+                        let label = tr_with_args(
+                            &localization,
+                            "stage-ui-convert-script",
+                            &[("from", from_name.as_str()), ("to", to_name.as_str())],
+                        );
+                        if ui.button(label).clicked() {
+                            play_ui_click(&mut commands, &audio, &settings);
+                            match translate(&other_code, other, language) {
+                                Ok(code) => {
+                                    editor.buffer = code.clone();
+                                    editor.last_run_feedback =
+                                        Some(tr(&localization, "stage-ui-convert-script-done"));
+                                    stage_scripts.set_stage_code(language, stage_id, stone, code);
+                                }
+                                Err(err) => {
+                                    warn!("Failed to convert script: {}", err);
+                                    editor.last_run_feedback = Some(tr_with_args(
+                                        &localization,
+                                        "stage-ui-convert-script-failed",
+                                        &[("from", from_name.as_str())],
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                }
 
                 ui.separator();
 
+                let stone_count = stone_query.iter().count();
+                if stone_count > 1 {
+                    let mut selected = editor.selected_stone;
+                    ui.horizontal_wrapped(|ui| {
+                        for index in 0..stone_count {
+                            let number = (index + 1).to_string();
+                            let label = tr_with_args(
+                                &localization,
+                                "stage-ui-stone-tab",
+                                &[("index", number.as_str())],
+                            );
+                            ui.selectable_value(&mut selected, index, label);
+                        }
+                    });
+                    if selected != editor.selected_stone {
+                        play_ui_click(&mut commands, &audio, &settings);
+                        let code = stage_scripts.stage_code(
+                            settings.script_language,
+                            progression.current_stage_id(),
+                            selected,
+                        );
+                        editor.select_stone(selected, code);
+                    }
+                    ui.separator();
+                }
+
                 let mut available_size = ui.available_size();
                 if !available_size.x.is_finite() {
                     available_size.x = ui.max_rect().width();
@@ -491,46 +898,201 @@ pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
                 let help_target_height = 220.0;
                 let help_height = help_anim * help_target_height;
 
-                let text_height = (available_size.y - help_height).max(160.0);
+                // Only Rhai `print` and `debug` reach the console; keystone-lang events carry no
+                // output, so Keystone scripts that print are told to switch to Rhai instead.
+                let show_console =
+                    settings.script_language == Language::Rhai && !editor.console.is_empty();
+                let console_height = if show_console { CONSOLE_HEIGHT } else { 0.0 };
+                let text_height = (available_size.y - help_height - console_height).max(160.0);
                 let font_size = scaled_panel_font_size(BASE_EDITOR_FONT_SIZE, editor.font_offset);
                 let editing_locked = editor.controls_enabled;
+                let error_line = editor.error_location.map(|location| location.line);
+                let selected_stone = editor.selected_stone;
+                let current_line = editor
+                    .debug
+                    .as_ref()
+                    .filter(|debug| debug.current_stone == selected_stone)
+                    .and_then(|debug| debug.current_line);
+                // Breakpoints need the source lines only Rhai programs report.
+                let debuggable = settings.script_language == Language::Rhai;
+                let breakpoints = if debuggable {
+                    editor.breakpoints.clone()
+                } else {
+                    BTreeSet::new()
+                };
+                let scroll_to_error = std::mem::take(&mut editor.scroll_to_error);
+
+                if settings.editor_mode == EditorMode::Blocks {
+                    let stage_id = progression.current_stage_id();
+                    let mut program = stage_scripts
+                        .stage_blocks(stage_id, selected_stone)
+                        .cloned()
+                        .unwrap_or_default();
+                    let stone_type = stone_query
+                        .iter()
+                        .find(|(index, _)| index.0 == selected_stone)
+                        .map(|(_, type_)| type_.clone())
+                        .unwrap_or_default();
+                    let allowed_commands = stone_capabilities.get_capabilities(&stone_type);
+
+                    let mut changed = false;
+                    egui::ScrollArea::vertical()
+                        .id_salt("block-editor")
+                        .max_height(text_height)
+                        .show(ui, |ui| {
+                            changed = block_editor::show_block_editor(
+                                ui,
+                                &localization,
+                                &mut program,
+                                allowed_commands,
+                                editing_locked,
+                            );
+
+                            // Show the generated code so players can graduate to typing it.
+                            let title = format!(
+                                "{} ({})",
+                                tr(&localization, "stage-ui-blocks-preview"),
+                                tr(&localization, language_label_key(settings.script_language))
+                            );
+                            egui::CollapsingHeader::new(title)
+                                .id_salt("block-preview")
+                                .show(ui, |ui| {
+                                    ui.label(code_layout_job(
+                                        &program.to_source(settings.script_language),
+                                        &FontId::new(font_size, Monospace),
+                                        ui.visuals().widgets.inactive.text_color(),
+                                        error_line,
+                                        current_line,
+                                        f32::INFINITY,
+                                    ));
+                                });
+                        });
+
+                    if changed {
+                        editor.error_location = None;
+                        editor.stage_cleared = false;
+                        editor.stage_clear_popup_open = false;
+                        stage_scripts.set_stage_blocks(stage_id, selected_stone, program);
+                    }
+                } else {
+                    let mut text_edit_response = None;
+                    let mut toggled_breakpoint = None;
+
+                    egui::ScrollArea::vertical()
+                        .max_height(text_height)
+                        .show(ui, |ui| {
+                            let code_font = FontId::new(font_size, Monospace);
+                            let mut line_rows = Vec::new();
+                            let mut layouter =
+                                |ui: &egui::Ui, text: &dyn egui::TextBuffer, wrap_width: f32| {
+                                    let job = code_layout_job(
+                                        text.as_str(),
+                                        &code_font,
+                                        ui.visuals().widgets.inactive.text_color(),
+                                        error_line,
+                                        current_line,
+                                        wrap_width,
+                                    );
+                                    let galley = ui.fonts_mut(|fonts| fonts.layout_job(job));
+                                    line_rows = line_start_char_indices(text.as_str())
+                                        .map(|start| {
+                                            galley.pos_from_cursor(egui::text::CCursor::new(start))
+                                        })
+                                        .collect::<Vec<_>>();
+                                    galley
+                                };
+
+                            ui.horizontal_top(|ui| {
+                                let (gutter_rect, gutter_response) = ui.allocate_exact_size(
+                                    egui::Vec2::new(font_size * CODE_GUTTER_WIDTH_EM, text_height),
+                                    egui::Sense::click(),
+                                );
+                                let response = ui.add_sized(
+                                    egui::Vec2::new(ui.available_width(), text_height),
+                                    egui::TextEdit::multiline(&mut editor.buffer)
+                                        .code_editor()
+                                        .font(FontSelection::FontId(code_font.clone()))
+                                        .layouter(&mut layouter)
+                                        .margin(CODE_EDITOR_MARGIN)
+                                        .interactive(!editing_locked)
+                                        .desired_width(f32::INFINITY),
+                                );
+                                let galley_origin = response.rect.min
+                                    + egui::Vec2::new(
+                                        CODE_EDITOR_MARGIN.leftf(),
+                                        CODE_EDITOR_MARGIN.topf(),
+                                    );
+
+                                paint_code_gutter(
+                                    ui,
+                                    gutter_rect,
+                                    galley_origin.y,
+                                    &line_rows,
+                                    &breakpoints,
+                                    current_line,
+                                    font_size,
+                                );
+
+                                if debuggable
+                                    && gutter_response.clicked()
+                                    && let Some(pointer) = gutter_response.interact_pointer_pos()
+                                {
+                                    toggled_breakpoint = line_rows
+                                        .iter()
+                                        .rposition(|row| galley_origin.y + row.top() <= pointer.y)
+                                        .map(|index| index + 1);
+                                }
 
-                let mut text_edit_response = None;
-
-                egui::ScrollArea::vertical()
-                    .max_height(text_height)
-                    .show(ui, |ui| {
-                        text_edit_response = Some(
-                            ui.add_sized(
-                                egui::Vec2::new(available_size.x, text_height),
-                                egui::TextEdit::multiline(&mut editor.buffer)
-                                    .code_editor()
-                                    .font(FontSelection::FontId(FontId::new(font_size, Monospace)))
-                                    .interactive(!editing_locked)
-                                    .desired_width(f32::INFINITY),
-                            ),
-                        );
-                    });
-
-                if text_edit_response.is_some_and(|r| r.changed()) {
-                    // Prevent non-ASCII input (e.g. Japanese) as requested.
-                    editor.buffer.retain(|c| c.is_ascii());
+                                if scroll_to_error
+                                    && let Some(row) = error_line
+                                        .and_then(|line| line.checked_sub(1))
+                                        .and_then(|index| line_rows.get(index))
+                                {
+                                    ui.scroll_to_rect(
+                                        row.translate(galley_origin.to_vec2()),
+                                        Some(egui::Align::Center),
+                                    );
+                                }
+                                text_edit_response = Some(response);
+                            });
+                        });
+
+                    if let Some(line) = toggled_breakpoint
+                        && !editor.breakpoints.remove(&line)
+                    {
+                        editor.breakpoints.insert(line);
+                    }
 
-                    info!("Script editor buffer changed");
-                    editor.controls_enabled = false;
-                    editor.stage_cleared = false;
-                    editor.stage_clear_popup_open = false;
-                    let stage_id = progression.current_stage_id();
-                    let current = stage_scripts.stage_code(settings.script_language, stage_id);
-                    if current.map(|c| c != editor.buffer.as_str()).unwrap_or(true) {
-                        stage_scripts.set_stage_code(
+                    if text_edit_response.is_some_and(|r| r.changed()) {
+                        // Prevent non-ASCII input (e.g. Japanese) as requested.
+                        editor.buffer.retain(|c| c.is_ascii());
+
+                        info!("Script editor buffer changed");
+                        editor.controls_enabled = false;
+                        editor.error_location = None;
+                        editor.stage_cleared = false;
+                        editor.stage_clear_popup_open = false;
+                        let stage_id = progression.current_stage_id();
+                        let current = stage_scripts.stage_code(
                             settings.script_language,
                             stage_id,
-                            editor.buffer.clone(),
+                            selected_stone,
                         );
+                        if current.map(|c| c != editor.buffer.as_str()).unwrap_or(true) {
+                            stage_scripts.set_stage_code(
+                                settings.script_language,
+                                stage_id,
+                                selected_stone,
+                                editor.buffer.clone(),
+                            );
+                        }
                     }
                 }
 
+                if show_console {
+                    show_script_console(ui, &localization, &mut editor);
+                }
+
                 if help_is_open || help_height > 1.0 {
                     let mut remaining = ui.available_size();
                     if !remaining.x.is_finite() {
@@ -589,6 +1151,15 @@ pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
                                                     );
                                                     ui.label(entry_job);
                                                     ui.add_space(4.0);
+
+                                                    if settings.script_language == Language::Rhai {
+                                                        show_beginner_syntax_help(
+                                                            ui,
+                                                            &localization,
+                                                            command_help_args,
+                                                            &font_id,
+                                                        );
+                                                    }
                                                 });
                                             },
                                         );
@@ -622,6 +1193,10 @@ pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
                 ui.add_space(8.0);
                 let body = tr(&localization, "stage-ui-clear-body");
                 ui.label(body);
+                if let Some((rating, par)) = &editor.last_clear {
+                    ui.add_space(8.0);
+                    show_solution_rating(ui, &localization, rating, par);
+                }
                 ui.add_space(12.0);
                 let ok = tr(&localization, "stage-ui-clear-ok");
                 if ui.button(ok.as_str()).clicked() {
@@ -633,6 +1208,25 @@ pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
         editor.stage_clear_popup_open = popup_open && !request_close;
     }
 
+    if let Some(map_error) = &map_error {
+        let window_title = tr(&localization, "stage-ui-map-error-title");
+        egui::Window::new(window_title)
+            .anchor(Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(tr(&localization, "stage-ui-map-error-body"));
+                ui.add_space(8.0);
+                ui.label(RichText::new(map_error.0.to_string()).monospace());
+                ui.add_space(12.0);
+                let back = tr(&localization, "stage-ui-back-to-title");
+                if ui.button(back.as_str()).clicked() {
+                    play_ui_click(&mut commands, &audio, &settings);
+                    next_state.set(GameState::SelectStage);
+                }
+            });
+    }
+
     // // Draw in-stage (non-popup) clear banner while stage_cleared is true.
     // if editor.stage_cleared {
     //     let banner = tr(&localization, "stage-ui-feedback-goal");
@@ -670,7 +1264,21 @@ pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
         )
         .order(egui::Order::Foreground)
         .show(ctx, |ui| {
-            ui.label(RichText::new(stage_name).font(font_id).color(label_color));
+            ui.with_layout(Layout::top_down(egui::Align::Max), |ui| {
+                ui.label(
+                    RichText::new(stage_name)
+                        .font(font_id.clone())
+                        .color(label_color),
+                );
+                show_map_seed(
+                    ui,
+                    &localization,
+                    &mut editor,
+                    &mut progression,
+                    font_id,
+                    label_color,
+                );
+            });
         });
 
     if (letterbox_offsets.left - left).abs() > f32::EPSILON {
@@ -682,115 +1290,391 @@ pub fn ui(params: StageUIParams, mut not_first: Local<bool>) {
     }
 }
 
-/// Each frame, pull at most one next command from the active program and append it to the Stone.
+/// Each frame, pull at most one next command from every stone's program and append it to
+/// that stone.
+#[allow(clippy::too_many_arguments)]
 pub fn tick_script_program(
     mut editor: ResMut<ScriptEditorState>,
     mut append_writer: MessageWriter<StoneAppendCommandMessage>,
     players: Query<(Entity, &CollidingEntities), With<Player>>,
-    stone_query: Query<(Entity, &GlobalTransform, &StoneType), With<StoneRune>>,
+    stone_query: Query<(Entity, &GlobalTransform, &StoneIndex), With<StoneRune>>,
     stone_states: Query<&StoneCommandState, With<StoneRune>>,
+    stone_progress: Query<(&Transform, &StoneSpawnState, &DigLimit), With<StoneRune>>,
+    goals: Query<&Transform, With<Goal>>,
     tiles: Query<(), With<StageTile>>,
     spatial: SpatialQuery,
+    localization: Option<Res<Localization>>,
 ) {
     if !editor.controls_enabled {
-        editor.active_program = None;
+        editor.active_programs.clear();
+        editor.debug = None;
         return;
     }
 
-    let Some(program) = editor.active_program.as_mut() else {
-        return;
-    };
-
-    let Some((stone_entity, stone_transform, _)) = stone_query.iter().next() else {
-        return;
-    };
-
-    if let Ok(stone_state) = stone_states.get(stone_entity)
-        && stone_state.is_busy()
+    // A paused debug run holds its command until Step or Continue releases it.
+    if let Some(debug) = editor.debug.as_mut()
+        && debug.pending.is_some()
     {
-        // Wait until the stone finishes its current action to avoid
-        // queueing stale commands based on old touch state.
+        if std::mem::take(&mut debug.resume)
+            && let Some((stone, command)) = debug.pending.take()
+        {
+            append_writer.write(StoneAppendCommandMessage { stone, command });
+        }
         return;
     }
 
-    // Optimization: check player touch first.
-    // User requested "only move when touching".
-    let player_touched = is_player_touching_stone(&players, stone_entity);
-
-    // Reverted optimization: The strict check prevented non-touch scripts from running.
-    // Instead we will handle "double move" via a cooldown in stone.rs.
-
-    let mut state = ScriptState::default();
-    state.insert(
-        PLAYER_TOUCHED_STATE_KEY.to_string(),
-        ScriptStateValue::Bool(player_touched),
-    );
-    state.insert(
-        RAND_STATE_KEY.to_string(),
-        ScriptStateValue::Float(rand::rng().random_range(0.0..1.0)),
-    );
+    // Stones take turns in index order so runs stay reproducible.
+    let mut stones = stone_query.iter().collect::<Vec<_>>();
+    stones.sort_by_key(|(_, _, index)| index.0);
 
-    // Calculate surrounding state using shape cast
-    // We check if the stone can move one full step without hitting a wall
-    let directions = [
-        ("up", Vec2::Y),
-        ("down", Vec2::NEG_Y),
-        ("left", Vec2::NEG_X),
-        ("right", Vec2::X),
-    ];
-    let stone_scale = stone_transform.scale().x;
-
-    // Get step_size from stone state if available, or use default
-    let step_size = stone_states
-        .get(stone_entity)
-        .map(|s| s.step_size)
-        .unwrap_or(super::stone::STONE_STEP_DISTANCE);
-
-    // Check distance = stone collider radius + a small margin
-    // This detects if the stone's edge is already touching or very close to a wall
-    let collider_radius = super::stone::STONE_COLLIDER_RADIUS * stone_scale;
-    let check_dist = step_size * stone_scale; // Check for one full step distance
-    let origin = stone_transform.translation().truncate();
     // Collect player entities to exclude from collision detection
     let player_entities: Vec<Entity> = players.iter().map(|(e, _)| e).collect();
-    let mut excluded_entities = vec![stone_entity];
-    excluded_entities.extend(player_entities);
-    let filter =
-        SpatialQueryFilter::from_mask(LayerMask::ALL).with_excluded_entities(excluded_entities);
-    // Shape cast with a circle matching the stone's collider size
-    let cast_shape = Collider::circle(collider_radius);
-    let cast_config = ShapeCastConfig::from_max_distance(check_dist);
-
-    for (name, dir) in directions {
-        let ray_dir = Dir2::new(dir).expect("Invalid direction");
-        // Use shape cast to check if stone can move one step without collision
-        let hit = spatial.cast_shape(&cast_shape, origin, 0.0, ray_dir, &cast_config, &filter);
-        let is_blocked = hit.is_some_and(|h| tiles.get(h.entity).is_ok());
+
+    for (stone_entity, stone_transform, stone_index) in stones {
+        let stone = stone_index.0;
+        if stone >= editor.active_programs.len() {
+            continue;
+        }
+
+        if let Ok(stone_state) = stone_states.get(stone_entity)
+            && stone_state.is_busy()
+        {
+            // Wait until the stone finishes its current action to avoid
+            // queueing stale commands based on old touch state.
+            continue;
+        }
+
+        // Optimization: check player touch first.
+        // User requested "only move when touching".
+        let player_touched = is_player_touching_stone(&players, stone_entity);
+
+        // Reverted optimization: The strict check prevented non-touch scripts from running.
+        // Instead we will handle "double move" via a cooldown in stone.rs.
+
+        let mut state = ScriptState::default();
         state.insert(
-            format!("is-empty-{}", name),
-            ScriptStateValue::Bool(!is_blocked),
-        );
-        info!(
-            "is-empty-{}: {} (origin={:?}, radius={}, step={}, hit={:?})",
-            name,
-            !is_blocked,
-            origin,
-            collider_radius,
-            check_dist,
-            hit.map(|h| (h.distance, h.entity))
+            PLAYER_TOUCHED_STATE_KEY.to_string(),
+            ScriptStateValue::Bool(player_touched),
         );
+
+        // Calculate surrounding state using shape cast
+        // We check if the stone can move one full step without hitting a wall
+        let directions = [
+            ("up", Vec2::Y),
+            ("down", Vec2::NEG_Y),
+            ("left", Vec2::NEG_X),
+            ("right", Vec2::X),
+        ];
+        let stone_scale = stone_transform.scale().x;
+
+        // Get step_size from stone state if available, or use default
+        let step_size = stone_states
+            .get(stone_entity)
+            .map(|s| s.step_size)
+            .unwrap_or(super::stone::STONE_STEP_DISTANCE);
+
+        // Check distance = stone collider radius + a small margin
+        // This detects if the stone's edge is already touching or very close to a wall
+        let collider_radius = super::stone::STONE_COLLIDER_RADIUS * stone_scale;
+        let check_dist = step_size * stone_scale; // Check for one full step distance
+        let origin = stone_transform.translation().truncate();
+        let mut excluded_entities = vec![stone_entity];
+        excluded_entities.extend(player_entities.iter().copied());
+        let filter =
+            SpatialQueryFilter::from_mask(LayerMask::ALL).with_excluded_entities(excluded_entities);
+        // Shape cast with a circle matching the stone's collider size
+        let cast_shape = Collider::circle(collider_radius);
+        let cast_config = ShapeCastConfig::from_max_distance(check_dist);
+
+        // Stones, their spawns and the goal share the stage root, where a move is `step_size`.
+        if let Ok((transform, spawn, dig_limit)) = stone_progress.get(stone_entity) {
+            let position = transform.translation.truncate();
+            let (x, y) = steps_between(
+                spawn.translation.truncate().into(),
+                position.into(),
+                step_size,
+            );
+            state.insert(POSITION_X_STATE_KEY.to_string(), ScriptStateValue::Int(x));
+            state.insert(POSITION_Y_STATE_KEY.to_string(), ScriptStateValue::Int(y));
+            let digs_left = dig_limit.0.map_or(-1, i64::from);
+            state.insert(
+                DIGS_LEFT_STATE_KEY.to_string(),
+                ScriptStateValue::Int(digs_left),
+            );
+            if let Some(goal) = goals.iter().next() {
+                let (dx, dy) = steps_between(
+                    position.into(),
+                    goal.translation.truncate().into(),
+                    step_size,
+                );
+                state.insert(GOAL_DX_STATE_KEY.to_string(), ScriptStateValue::Int(dx));
+                state.insert(GOAL_DY_STATE_KEY.to_string(), ScriptStateValue::Int(dy));
+            }
+        }
+
+        for (name, dir) in directions {
+            let ray_dir = Dir2::new(dir).expect("Invalid direction");
+            // Use shape cast to check if stone can move one step without collision
+            let hit = spatial.cast_shape(&cast_shape, origin, 0.0, ray_dir, &cast_config, &filter);
+            let is_blocked = hit.is_some_and(|h| tiles.get(h.entity).is_ok());
+            state.insert(
+                format!("is-empty-{}", name),
+                ScriptStateValue::Bool(!is_blocked),
+            );
+            debug!(
+                "stone {} is-empty-{}: {} (origin={:?}, radius={}, step={}, hit={:?})",
+                stone,
+                name,
+                !is_blocked,
+                origin,
+                collider_radius,
+                check_dist,
+                hit.map(|h| (h.distance, h.entity))
+            );
+        }
+
+        let ScriptEditorState {
+            active_programs,
+            debug,
+            breakpoints,
+            selected_stone,
+            inspected_state,
+            run_metrics,
+            console,
+            ..
+        } = &mut *editor;
+        let several_stones = active_programs.len() > 1;
+        let program = &mut active_programs[stone];
+        let result = program.next(&state);
+        let line = program.current_location().map(|location| location.line);
+        for text in program.take_output() {
+            if console.len() >= CONSOLE_MAX_LINES {
+                console.pop_front();
+            }
+            // With several scripts running, say which stone printed the line.
+            console.push_back(if several_stones {
+                format!("{}> {text}", stone + 1)
+            } else {
+                text
+            });
+        }
+        let is_selected = stone == *selected_stone;
+        if is_selected {
+            *inspected_state = state;
+            inspected_state.extend(program.sensor_values());
+        }
+
+        match result {
+            Ok(Some(command)) => {
+                if let Some(run_metrics) = run_metrics.as_mut() {
+                    run_metrics.record_command(&command);
+                }
+                if let Some(debug) = debug.as_mut() {
+                    debug.current_stone = stone;
+                    debug.current_line = line;
+                    // Breakpoints are set on the script open in the editor.
+                    let at_breakpoint =
+                        is_selected && line.is_some_and(|line| breakpoints.contains(&line));
+                    if debug.stepping || at_breakpoint {
+                        debug.pending = Some((stone, command));
+                        return;
+                    }
+                }
+                append_writer.write(StoneAppendCommandMessage {
+                    stone,
+                    command: command.clone(),
+                });
+            }
+            Ok(None) => {
+                // // Program exhausted: stop execution.
+                // info!("Script program completed");
+                // editor.controls_enabled = false;
+                // editor.active_programs.clear();
+            }
+            Err(err) => {
+                warn!("Script execution stopped on stone {}: {}", stone, err);
+                editor.last_run_feedback = Some(match localization.as_ref() {
+                    Some(localization) => script_error_message(localization, &err),
+                    None => err.to_string(),
+                });
+                // Only the open script can show where the error happened.
+                editor.error_location = err.location().filter(|_| is_selected);
+                editor.scroll_to_error = editor.error_location.is_some();
+                editor.controls_enabled = false;
+                editor.active_programs.clear();
+                editor.debug = None;
+                return;
+            }
+        }
     }
+}
 
-    if let Some(command) = program.next(&state) {
-        append_writer.write(StoneAppendCommandMessage {
-            command: command.clone(),
+/// Warnings found before the last run; they never block it.
+fn show_lint_warnings(ui: &mut egui::Ui, localization: &Localization, editor: &ScriptEditorState) {
+    // Name the stone when a warning belongs to a script other than the open one.
+    let several_stones = editor
+        .lint_warnings
+        .iter()
+        .any(|(stone, _)| *stone != editor.selected_stone);
+    for (stone, warning) in &editor.lint_warnings {
+        let message = script_warning_message(localization, warning);
+        let text = if several_stones {
+            format!("{}> {message}", stone + 1)
+        } else {
+            message
+        };
+        ui.colored_label(ui.visuals().warn_fg_color, text);
+    }
+}
+
+/// Output the running scripts printed, newest at the bottom.
+fn show_script_console(
+    ui: &mut egui::Ui,
+    localization: &Localization,
+    editor: &mut ScriptEditorState,
+) {
+    ui.horizontal(|ui| {
+        ui.label(RichText::new(tr(localization, "stage-ui-console-title")).strong());
+        if ui
+            .small_button(tr(localization, "stage-ui-console-clear"))
+            .clicked()
+        {
+            editor.console.clear();
+        }
+    });
+    egui::ScrollArea::vertical()
+        .id_salt("script-console")
+        .max_height(CONSOLE_HEIGHT - 24.0)
+        .auto_shrink([false, true])
+        .stick_to_bottom(true)
+        .show(ui, |ui| {
+            for line in &editor.console {
+                ui.monospace(line);
+            }
         });
-    } else {
-        // // Program exhausted: stop execution.
-        // info!("Script program completed");
-        // editor.controls_enabled = false;
-        // editor.active_program = None;
+}
+
+/// Stars of a clear followed by each metric next to its par.
+fn show_solution_rating(
+    ui: &mut egui::Ui,
+    localization: &Localization,
+    rating: &SolutionRating,
+    par: &SolutionPar,
+) {
+    if let Some(stars) = rating.stars {
+        let earned = usize::from(stars.min(3));
+        let stars = "★".repeat(earned) + &"☆".repeat(3 - earned);
+        ui.label(RichText::new(stars).size(28.0).color(egui::Color32::GOLD));
     }
+
+    let metrics = &rating.metrics;
+    let rows = [
+        (
+            "stage-ui-clear-metric-lines",
+            metrics.lines.to_string(),
+            par.lines.map(|par| par.to_string()),
+        ),
+        (
+            "stage-ui-clear-metric-tokens",
+            metrics.tokens.to_string(),
+            par.tokens.map(|par| par.to_string()),
+        ),
+        (
+            "stage-ui-clear-metric-commands",
+            metrics.commands.to_string(),
+            par.commands.map(|par| par.to_string()),
+        ),
+        (
+            "stage-ui-clear-metric-sleep",
+            format!("{:.1}", metrics.sleep_seconds),
+            par.sleep_seconds.map(|par| format!("{par:.1}")),
+        ),
+        (
+            "stage-ui-clear-metric-digs",
+            metrics.digs.to_string(),
+            par.digs.map(|par| par.to_string()),
+        ),
+    ];
+
+    egui::Grid::new("stage-clear-metrics")
+        .num_columns(3)
+        .spacing([16.0, 4.0])
+        .show(ui, |ui| {
+            for (key, value, par) in rows {
+                ui.label(tr(localization, key));
+                ui.label(RichText::new(value).strong());
+                match par {
+                    Some(par) => ui.weak(tr_with_args(
+                        localization,
+                        "stage-ui-clear-metric-par",
+                        &[("par", par.as_str())],
+                    )),
+                    None => ui.weak("-"),
+                };
+                ui.end_row();
+            }
+        });
+}
+
+/// Lists the sensor values of the running program, and its variables when it is a Rhai script.
+fn show_script_inspector(
+    ui: &mut egui::Ui,
+    localization: &Localization,
+    editor: &ScriptEditorState,
+    language: Language,
+) {
+    let title = tr(localization, "stage-ui-inspector-title");
+    egui::CollapsingHeader::new(title)
+        .id_salt("script-inspector")
+        .default_open(true)
+        .show(ui, |ui| {
+            let mut sensors = editor.inspected_state.iter().collect::<Vec<_>>();
+            sensors.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            ui.label(RichText::new(tr(localization, "stage-ui-inspector-sensors")).strong());
+            egui::Grid::new("script-inspector-sensors")
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    for (name, value) in sensors {
+                        ui.monospace(name);
+                        ui.monospace(value.to_string());
+                        ui.end_row();
+                    }
+                });
+
+            // Keystone does not expose its environment, so only Rhai programs report variables.
+            if language != Language::Rhai {
+                return;
+            }
+            ui.add_space(4.0);
+            ui.label(RichText::new(tr(localization, "stage-ui-inspector-variables")).strong());
+            match editor
+                .active_programs
+                .get(editor.selected_stone)
+                .and_then(|program| program.variables())
+            {
+                None => {
+                    ui.label(tr(localization, "stage-ui-inspector-variables-off"));
+                }
+                Some(variables) if variables.is_empty() => {
+                    ui.label(tr(localization, "stage-ui-inspector-no-variables"));
+                }
+                Some(mut variables) => {
+                    variables.sort();
+                    egui::Grid::new("script-inspector-variables")
+                        .num_columns(2)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for (name, value) in variables {
+                                ui.monospace(name);
+                                ui.monospace(value);
+                                ui.end_row();
+                            }
+                        });
+                }
+            }
+        });
 }
 
 fn is_player_touching_stone(
@@ -818,6 +1702,13 @@ fn command_help_for_stage(stage_id: StageId) -> Option<CommandHelpDialog> {
     ))
 }
 
+fn language_label_key(language: Language) -> &'static str {
+    match language {
+        Language::Rhai => "options-language-rhai",
+        Language::Keystone => "options-language-keystone",
+    }
+}
+
 fn command_help_args(language: Language) -> &'static [(&'static str, &'static str)] {
     match language {
         Language::Rhai => &[
@@ -842,6 +1733,16 @@ fn command_help_args(language: Language) -> &'static [(&'static str, &'static st
                 "is-touched-example",
                 "loop {\n    if is_touched() {\n        <<dot>><<dot>><<dot>>\n    }\n}",
             ),
+            ("move-up-bare", "move up;"),
+            ("repeat-example", "repeat 3 {\n    move up;\n}"),
+            (
+                "forever-example",
+                "forever {\n    move up;\n    sleep(1);\n}",
+            ),
+            (
+                "while-touched-example",
+                "while_touched {\n    move right;\n}",
+            ),
         ],
         Language::Keystone => &[
             ("move-up", "move up"),
@@ -854,6 +1755,33 @@ fn command_help_args(language: Language) -> &'static [(&'static str, &'static st
     }
 }
 
+/// Rhai shortcuts that read like Keystone, listed under every stage's entry.
+fn show_beginner_syntax_help(
+    ui: &mut egui::Ui,
+    localization: &Localization,
+    command_help_args: &[(&str, &str)],
+    font_id: &FontId,
+) {
+    const KEYS: [&str; 4] = [
+        "stage-ui-syntax-move",
+        "stage-ui-syntax-repeat",
+        "stage-ui-syntax-forever",
+        "stage-ui-syntax-while-touched",
+    ];
+
+    ui.add_space(8.0);
+    ui.label(
+        RichText::new(tr(localization, "stage-ui-syntax-title"))
+            .strong()
+            .font(font_id.clone()),
+    );
+    for key in KEYS {
+        ui.add_space(4.0);
+        let text = tr_with_args(localization, key, command_help_args);
+        ui.label(highlight_backtick_segments(&text, font_id, ui));
+    }
+}
+
 fn highlight_backtick_segments(text: &str, font_id: &FontId, ui: &egui::Ui) -> LayoutJob {
     let mut job = LayoutJob::default();
     let normal_format = TextFormat {
@@ -881,6 +1809,107 @@ fn highlight_backtick_segments(text: &str, font_id: &FontId, ui: &egui::Ui) -> L
     job
 }
 
+/// Lays out the script, underlining `error_line` and tinting the line a debug run paused on.
+/// Both lines are 1-based.
+fn code_layout_job(
+    text: &str,
+    font_id: &FontId,
+    color: egui::Color32,
+    error_line: Option<usize>,
+    current_line: Option<usize>,
+    wrap_width: f32,
+) -> LayoutJob {
+    let mut job = LayoutJob::default();
+    job.wrap.max_width = wrap_width;
+
+    let normal_format = TextFormat::simple(font_id.clone(), color);
+    let error_format = TextFormat {
+        background: egui::Color32::from_rgba_unmultiplied(220, 60, 60, 48),
+        underline: egui::Stroke::new(2.0, egui::Color32::from_rgb(230, 80, 80)),
+        ..normal_format.clone()
+    };
+    let current_format = TextFormat {
+        background: egui::Color32::from_rgba_unmultiplied(240, 200, 80, 96),
+        ..normal_format.clone()
+    };
+
+    if text.is_empty() {
+        job.append("", 0.0, normal_format);
+        return job;
+    }
+
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        let line_number = Some(index + 1);
+        let format = if error_line == line_number {
+            error_format.clone()
+        } else if current_line == line_number {
+            current_format.clone()
+        } else {
+            normal_format.clone()
+        };
+        job.append(line, 0.0, format);
+    }
+
+    job
+}
+
+/// Character index where each line starts, including the empty line after a trailing newline.
+fn line_start_char_indices(text: &str) -> impl Iterator<Item = usize> + '_ {
+    std::iter::once(0).chain(
+        text.chars()
+            .enumerate()
+            .filter_map(|(index, c)| (c == '\n').then_some(index + 1)),
+    )
+}
+
+/// Draws line numbers, breakpoint markers and the paused-line arrow left of the editor.
+fn paint_code_gutter(
+    ui: &egui::Ui,
+    rect: egui::Rect,
+    galley_top: f32,
+    line_rows: &[egui::Rect],
+    breakpoints: &BTreeSet<usize>,
+    current_line: Option<usize>,
+    font_size: f32,
+) {
+    let painter = ui.painter_at(rect);
+    let number_font = FontId::new(font_size * 0.6, Monospace);
+    let number_color = egui::Color32::from_gray(130);
+    let marker_radius = font_size * 0.18;
+
+    for (index, row) in line_rows.iter().enumerate() {
+        let line = index + 1;
+        let center_y = galley_top + row.center().y;
+        painter.text(
+            egui::pos2(rect.right() - 2.0, center_y),
+            Align2::RIGHT_CENTER,
+            line.to_string(),
+            number_font.clone(),
+            number_color,
+        );
+        let marker_center = egui::pos2(rect.left() + marker_radius + 1.0, center_y);
+        if breakpoints.contains(&line) {
+            painter.circle_filled(
+                marker_center,
+                marker_radius,
+                egui::Color32::from_rgb(220, 60, 60),
+            );
+        }
+        if current_line == Some(line) {
+            let arrow = vec![
+                marker_center + egui::Vec2::new(-marker_radius, -marker_radius),
+                marker_center + egui::Vec2::new(marker_radius, 0.0),
+                marker_center + egui::Vec2::new(-marker_radius, marker_radius),
+            ];
+            painter.add(egui::Shape::convex_polygon(
+                arrow,
+                egui::Color32::from_rgb(240, 200, 80),
+                egui::Stroke::NONE,
+            ));
+        }
+    }
+}
+
 fn chunk_tutorial_text(input: &str) -> Vec<String> {
     let mut chunks = Vec::new();
     let mut current = Vec::new();
@@ -1,7 +1,13 @@
 use bevy_fluent::prelude::Localization;
 use fluent_content::Content;
 
-use crate::{resources::stage_catalog::StageId, util::script_types::ScriptExecutionError};
+use crate::{
+    resources::{
+        script_engine::{ScriptWarning, ScriptWarningKind},
+        stage_catalog::StageId,
+    },
+    util::script_types::{ScriptExecutionError, SourceLocation},
+};
 
 pub fn tr(localization: &Localization, key: &str) -> String {
     localization.content(key).unwrap_or_else(|| key.to_string())
@@ -64,5 +70,53 @@ pub fn script_error_message(localization: &Localization, error: &ScriptExecution
             &[("language", language.as_str())],
         ),
         ScriptExecutionError::InvalidCommand(message) => message.clone(),
+        ScriptExecutionError::CommandNotAllowed { command } => tr_with_args(
+            localization,
+            "stage-ui-error-command-not-allowed",
+            &[("command", command.as_str())],
+        ),
+        ScriptExecutionError::Located { error, location } => {
+            let message = script_error_message(localization, error);
+            let suffix = location_suffix(localization, location);
+            format!("{message} {suffix}")
+        }
+    }
+}
+
+pub fn script_warning_message(localization: &Localization, warning: &ScriptWarning) -> String {
+    let message = match &warning.kind {
+        ScriptWarningKind::BusyLoop => tr(localization, "stage-ui-warning-busy-loop"),
+        ScriptWarningKind::UnreachableCode => tr(localization, "stage-ui-warning-unreachable"),
+        ScriptWarningKind::UnknownDirection { direction } => tr_with_args(
+            localization,
+            "stage-ui-warning-unknown-direction",
+            &[("direction", direction.as_str())],
+        ),
+        ScriptWarningKind::MissingCommand { command } => tr_with_args(
+            localization,
+            "stage-ui-warning-missing-command",
+            &[("command", command.as_str())],
+        ),
+    };
+    let suffix = location_suffix(localization, &warning.location);
+    format!("{message} {suffix}")
+}
+
+fn location_suffix(localization: &Localization, location: &SourceLocation) -> String {
+    let line = location.line.to_string();
+    match location.column {
+        Some(column) => tr_with_args(
+            localization,
+            "stage-ui-error-location-line-column",
+            &[
+                ("line", line.as_str()),
+                ("column", column.to_string().as_str()),
+            ],
+        ),
+        None => tr_with_args(
+            localization,
+            "stage-ui-error-location-line",
+            &[("line", line.as_str())],
+        ),
     }
 }
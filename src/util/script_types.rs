@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
     fmt,
@@ -11,7 +12,7 @@ pub enum ScriptCommand {
     Dig(MoveDirection),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MoveDirection {
     Left,
     Top,
@@ -43,6 +44,44 @@ impl fmt::Display for MoveDirection {
     }
 }
 
+/// Position of an error inside the script source. Lines and columns are 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub line: usize,
+    pub column: Option<usize>,
+    /// Length of the offending token in characters, when the engine knows it.
+    pub span: Option<usize>,
+}
+
+impl SourceLocation {
+    pub fn line(line: usize) -> Self {
+        Self {
+            line,
+            column: None,
+            span: None,
+        }
+    }
+
+    pub fn with_column(mut self, column: usize) -> Self {
+        self.column = Some(column);
+        self
+    }
+
+    pub fn with_span(mut self, span: usize) -> Self {
+        self.span = Some(span);
+        self
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.column {
+            Some(column) => write!(f, "line {}, column {}", self.line, column),
+            None => write!(f, "line {}", self.line),
+        }
+    }
+}
+
 /// High-level errors surfaced when running scripts.
 #[derive(Debug)]
 pub enum ScriptExecutionError {
@@ -54,6 +93,41 @@ pub enum ScriptExecutionError {
     UnsupportedLanguage(String),
     #[allow(dead_code)]
     InvalidCommand(String),
+    /// The script uses a command the current stone does not have.
+    CommandNotAllowed {
+        command: String,
+    },
+    /// Wraps another error with the place in the source that caused it.
+    Located {
+        error: Box<ScriptExecutionError>,
+        location: SourceLocation,
+    },
+}
+
+impl ScriptExecutionError {
+    /// Attaches a source location, replacing any location already present.
+    pub fn at(self, location: SourceLocation) -> Self {
+        let error = match self {
+            ScriptExecutionError::Located { error, .. } => error,
+            other => Box::new(other),
+        };
+        ScriptExecutionError::Located { error, location }
+    }
+
+    pub fn location(&self) -> Option<SourceLocation> {
+        match self {
+            ScriptExecutionError::Located { location, .. } => Some(*location),
+            _ => None,
+        }
+    }
+
+    /// The error without its location.
+    pub fn kind(&self) -> &ScriptExecutionError {
+        match self {
+            ScriptExecutionError::Located { error, .. } => error,
+            other => other,
+        }
+    }
 }
 
 impl fmt::Display for ScriptExecutionError {
@@ -72,6 +146,12 @@ impl fmt::Display for ScriptExecutionError {
             ScriptExecutionError::InvalidCommand(msg) => {
                 write!(f, "Invalid command: {msg}")
             }
+            ScriptExecutionError::CommandNotAllowed { command } => {
+                write!(f, "This stone cannot use {command}.")
+            }
+            ScriptExecutionError::Located { error, location } => {
+                write!(f, "{error} ({location})")
+            }
         }
     }
 }
@@ -83,14 +163,72 @@ pub trait ScriptRunner: Send + Sync + 'static {
         &self,
         source: &str,
         allowed_commands: Option<&HashSet<String>>,
+        sandbox: &SandboxProfile,
     ) -> Result<Vec<ScriptCommand>, ScriptExecutionError>;
 }
 
 /// Iterator-like interface for step-by-step command generation.
 /// Implementations should be cheap to `next` and honor safety limits internally.
 pub trait ScriptProgram: Send + Sync + 'static {
-    /// Produces the next command, or None if finished.
-    fn next(&mut self, state: &ScriptState) -> Option<ScriptCommand>;
+    /// Produces the next command, or None if there is nothing to do this step.
+    /// An error means the script stopped and the program will produce nothing further.
+    fn next(&mut self, state: &ScriptState) -> Result<Option<ScriptCommand>, ScriptExecutionError>;
+
+    /// Where the command last returned by `next` came from, if the engine tracks call sites.
+    fn current_location(&self) -> Option<SourceLocation> {
+        None
+    }
+
+    /// Latest snapshot of the script's variables as `(name, value)` pairs,
+    /// or `None` if the engine cannot inspect them.
+    fn variables(&self) -> Option<Vec<(String, String)>> {
+        None
+    }
+
+    /// Lines the script printed since the last call, oldest first.
+    fn take_output(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Sensor values the program produces itself, such as its last `rand()` draw, keyed like
+    /// the `ScriptState` it is fed.
+    fn sensor_values(&self) -> ScriptState {
+        ScriptState::new()
+    }
+}
+
+/// Resource limits a script runs under. Stages may override any field in their RON file.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SandboxProfile {
+    /// Rhai VM operations or Keystone evaluation steps of a buffered run or preflight.
+    /// Running programs are not capped, since stones may loop for the whole stage.
+    pub max_operations: usize,
+    /// Commands a buffered run may record before it is treated as runaway.
+    pub max_commands: usize,
+    pub max_expr_depth: usize,
+    pub max_call_levels: usize,
+    /// Length of a single string, in bytes.
+    pub max_string_size: usize,
+    pub max_array_size: usize,
+    pub max_map_size: usize,
+    /// Variables in scope at once.
+    pub max_variables: usize,
+}
+
+impl Default for SandboxProfile {
+    fn default() -> Self {
+        Self {
+            max_operations: 100_000,
+            max_commands: 5_000,
+            max_expr_depth: 64,
+            max_call_levels: 32,
+            max_string_size: 4_096,
+            max_array_size: 1_024,
+            max_map_size: 256,
+            max_variables: 256,
+        }
+    }
 }
 
 /// Compiles a script into a step-executable program.
@@ -99,13 +237,27 @@ pub trait ScriptStepper: Send + Sync + 'static {
         &self,
         source: &str,
         allowed_commands: Option<&HashSet<String>>,
+        sandbox: &SandboxProfile,
+        rand_seed: u64,
     ) -> Result<Box<dyn ScriptProgram>, ScriptExecutionError>;
+
+    /// Forgets signals left over from earlier runs of programs compiled by this stepper.
+    fn clear_signals(&self) {}
+
+    /// Whether programs compiled from now on report `variables`. Snapshots cost time on every
+    /// statement, so runs only pay for them while someone is looking.
+    fn set_inspection(&self, _enabled: bool) {}
 }
 
-#[derive(Debug, Clone)]
+/// A sensor value handed to scripts. Each executor converts it into its own value type.
+#[derive(Debug, Clone, PartialEq)]
 pub enum ScriptStateValue {
     Bool(bool),
     Float(f32),
+    Int(i64),
+    String(String),
+    Direction(MoveDirection),
+    List(Vec<ScriptStateValue>),
 }
 
 impl ScriptStateValue {
@@ -116,13 +268,62 @@ impl ScriptStateValue {
         }
     }
 
-    #[allow(dead_code)]
     pub fn as_float(&self) -> Option<f32> {
         match self {
             ScriptStateValue::Float(value) => Some(*value),
             _ => None,
         }
     }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            ScriptStateValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            ScriptStateValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_direction(&self) -> Option<MoveDirection> {
+        match self {
+            ScriptStateValue::Direction(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[ScriptStateValue]> {
+        match self {
+            ScriptStateValue::List(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ScriptStateValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptStateValue::Bool(value) => write!(f, "{value}"),
+            ScriptStateValue::Float(value) => write!(f, "{value:.3}"),
+            ScriptStateValue::Int(value) => write!(f, "{value}"),
+            ScriptStateValue::String(value) => write!(f, "{value:?}"),
+            ScriptStateValue::Direction(value) => write!(f, "{value}"),
+            ScriptStateValue::List(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
 }
 
 impl From<bool> for ScriptStateValue {
@@ -137,7 +338,80 @@ impl From<f32> for ScriptStateValue {
     }
 }
 
+impl From<i64> for ScriptStateValue {
+    fn from(value: i64) -> Self {
+        ScriptStateValue::Int(value)
+    }
+}
+
+impl From<String> for ScriptStateValue {
+    fn from(value: String) -> Self {
+        ScriptStateValue::String(value)
+    }
+}
+
+impl From<&str> for ScriptStateValue {
+    fn from(value: &str) -> Self {
+        ScriptStateValue::String(value.to_string())
+    }
+}
+
+impl From<MoveDirection> for ScriptStateValue {
+    fn from(value: MoveDirection) -> Self {
+        ScriptStateValue::Direction(value)
+    }
+}
+
+impl<T: Into<ScriptStateValue>> From<Vec<T>> for ScriptStateValue {
+    fn from(values: Vec<T>) -> Self {
+        ScriptStateValue::List(values.into_iter().map(Into::into).collect())
+    }
+}
+
 pub type ScriptState = HashMap<String, ScriptStateValue>;
 
 pub const PLAYER_TOUCHED_STATE_KEY: &str = "player-touched";
+/// Last value drawn by `rand()` (`Float`); reported by the program rather than fed to it.
 pub const RAND_STATE_KEY: &str = "rand";
+/// Stone position in move steps from where it was spawned (`Int`); up and right are positive.
+pub const POSITION_X_STATE_KEY: &str = "position-x";
+pub const POSITION_Y_STATE_KEY: &str = "position-y";
+/// Remaining digs (`Int`), or -1 when the stone can dig without limit.
+pub const DIGS_LEFT_STATE_KEY: &str = "digs-left";
+/// Offset from the stone to the goal, in move steps (`Int`).
+pub const GOAL_DX_STATE_KEY: &str = "goal-dx";
+pub const GOAL_DY_STATE_KEY: &str = "goal-dy";
+
+/// Offset from `from` to `to` in whole moves of `step_size`, as `(x, y)` with up and right positive.
+/// Both points must be in the same space, the one the stone moves `step_size` per step in.
+pub fn steps_between(from: (f32, f32), to: (f32, f32), step_size: f32) -> (i64, i64) {
+    let step = |from: f32, to: f32| ((to - from) / step_size).round() as i64;
+    (step(from.0, to.0), step(from.1, to.1))
+}
+
+/// Seed of one stone's `rand` sensor, so stones of the same run draw different sequences.
+pub fn stone_rand_seed(run_seed: u64, stone: usize) -> u64 {
+    run_seed ^ (stone as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn position_and_goal_offsets_count_the_same_steps() {
+        let spawn = (100.0, 50.0);
+        let stone = (164.0, 18.0);
+        let goal = (260.0, 18.0);
+        assert_eq!(steps_between(spawn, stone, 32.0), (2, -1));
+        assert_eq!(steps_between(stone, goal, 32.0), (3, 0));
+
+        // Walking the goal offset from the current position lands on the goal.
+        let (x, y) = steps_between(spawn, stone, 32.0);
+        let (dx, dy) = steps_between(stone, goal, 32.0);
+        assert_eq!(steps_between(spawn, goal, 32.0), (x + dx, y + dy));
+
+        // Half-finished moves round to the nearest step.
+        assert_eq!(steps_between(spawn, (115.0, 67.0), 32.0), (0, 1));
+    }
+}